@@ -0,0 +1,249 @@
+use shakmaty::{Color, Position};
+use simple_error::SimpleError;
+
+use crate::board::start_position;
+use crate::pgn::annotation::{parse_eval, Eval};
+use crate::pgn::movetext::{mainline, tokenize};
+use crate::pgn::Pgn;
+
+pub mod puzzle;
+
+/// Evaluations beyond this many centipawns, and mates, count as this many.
+const MAX_CP: i32 = 1000;
+
+/// Centipawn losses from which moves are judged inaccuracies, mistakes and
+/// blunders.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Thresholds {
+    pub inaccuracy: i32,
+    pub mistake: i32,
+    pub blunder: i32,
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        Self {
+            inaccuracy: 50,
+            mistake: 100,
+            blunder: 300,
+        }
+    }
+}
+
+impl Thresholds {
+    pub fn judge(&self, cp_loss: i32) -> Option<Judgement> {
+        if cp_loss >= self.blunder {
+            Some(Judgement::Blunder)
+        } else if cp_loss >= self.mistake {
+            Some(Judgement::Mistake)
+        } else if cp_loss >= self.inaccuracy {
+            Some(Judgement::Inaccuracy)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Judgement {
+    Inaccuracy,
+    Mistake,
+    Blunder,
+}
+
+impl Judgement {
+    /// `$6` (?!), `$2` (?) or `$4` (??).
+    pub fn nag(&self) -> u8 {
+        match self {
+            Judgement::Inaccuracy => 6,
+            Judgement::Mistake => 2,
+            Judgement::Blunder => 4,
+        }
+    }
+}
+
+/// A move evaluated both before and after it was played.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MoveAnalysis {
+    /// Index of the move, 0 for the first.
+    pub ply: usize,
+    pub colour: Color,
+    pub cp_loss: i32,
+    /// From 0 to 100, by the winning chances lost.
+    pub accuracy: f64,
+    pub judgement: Option<Judgement>,
+}
+
+/// Totals over the analysed moves of a player.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct PlayerAnalysis {
+    pub moves: usize,
+    /// Average centipawn loss, `None` without analysed moves.
+    pub acpl: Option<f64>,
+    /// Average move accuracy, `None` without analysed moves.
+    pub accuracy: Option<f64>,
+    pub inaccuracies: usize,
+    pub mistakes: usize,
+    pub blunders: usize,
+}
+
+impl PlayerAnalysis {
+    fn new<'a>(moves: impl Iterator<Item = &'a MoveAnalysis>) -> Self {
+        let mut player = Self::default();
+        let mut cp_loss: i64 = 0;
+        let mut accuracy: f64 = 0.0;
+        for m in moves {
+            player.moves += 1;
+            cp_loss += m.cp_loss as i64;
+            accuracy += m.accuracy;
+            match m.judgement {
+                Some(Judgement::Inaccuracy) => player.inaccuracies += 1,
+                Some(Judgement::Mistake) => player.mistakes += 1,
+                Some(Judgement::Blunder) => player.blunders += 1,
+                None => {}
+            }
+        }
+        if player.moves > 0 {
+            player.acpl = Some(cp_loss as f64 / player.moves as f64);
+            player.accuracy = Some(accuracy / player.moves as f64);
+        }
+        player
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GameAnalysis {
+    pub moves: Vec<MoveAnalysis>,
+    pub white: PlayerAnalysis,
+    pub black: PlayerAnalysis,
+}
+
+impl GameAnalysis {
+    /// NAG of each of the first `plies` moves, for `set_nags`.
+    pub fn nags(&self, plies: usize) -> Vec<Option<u8>> {
+        let mut nags = vec![None; plies];
+        for m in self.moves.iter() {
+            if let Some(nag) = nags.get_mut(m.ply) {
+                *nag = m.judgement.map(|j| j.nag());
+            }
+        }
+        nags
+    }
+}
+
+/// Evaluation after each move of the main line, from `[%eval]` comments.
+pub fn evals_from_comments(pgn: &Pgn) -> Vec<Option<Eval>> {
+    mainline(&tokenize(pgn.moves_text.as_str()))
+        .iter()
+        .map(|ply| ply.comments.iter().find_map(|c| parse_eval(c)))
+        .collect()
+}
+
+/// Judges the moves of `pgn` from the evaluation after each of them, e.g.
+/// from `evals_from_comments` or `Engine::analyze`. The starting position
+/// counts as equal unless the game has a `FEN` tag, in which case the first
+/// move is not judged.
+pub fn analyze_game(
+    pgn: &Pgn,
+    evals: &[Option<Eval>],
+    thresholds: &Thresholds,
+) -> Result<GameAnalysis, SimpleError> {
+    let start = start_position(pgn)?;
+    let initial = (!pgn.tags.contains_key("FEN")).then_some(Eval::Cp(0));
+    Ok(analyze_evals(evals, start.turn(), initial, thresholds))
+}
+
+/// Judges each move with an evaluation before and after it. `evals` holds
+/// the evaluation after each move, `initial` the one before the first.
+pub fn analyze_evals(
+    evals: &[Option<Eval>],
+    first_turn: Color,
+    initial: Option<Eval>,
+    thresholds: &Thresholds,
+) -> GameAnalysis {
+    let mut moves: Vec<MoveAnalysis> = Vec::new();
+    let mut colour = first_turn;
+    let mut before = initial;
+
+    for (ply, after) in evals.iter().enumerate() {
+        if let (Some(before), Some(after)) = (before.and_then(to_cp), after.and_then(to_cp)) {
+            let sign = colour.fold_wb(1, -1);
+            let cp_loss = (sign * (before - after)).max(0);
+            let win_loss = (win_percent(sign * before) - win_percent(sign * after)).max(0.0);
+            let accuracy = (103.1668 * (-0.04354 * win_loss).exp() - 3.1669).clamp(0.0, 100.0);
+
+            moves.push(MoveAnalysis {
+                ply,
+                colour,
+                cp_loss,
+                accuracy,
+                judgement: thresholds.judge(cp_loss),
+            });
+        }
+        before = *after;
+        colour = !colour;
+    }
+
+    GameAnalysis {
+        white: PlayerAnalysis::new(moves.iter().filter(|m| m.colour == Color::White)),
+        black: PlayerAnalysis::new(moves.iter().filter(|m| m.colour == Color::Black)),
+        moves,
+    }
+}
+
+/// Centipawns from the point of view of White, capped at `MAX_CP`.
+fn to_cp(eval: Eval) -> Option<i32> {
+    match eval {
+        Eval::Cp(cp) => Some(cp.clamp(-MAX_CP, MAX_CP)),
+        Eval::Mate(n) if n > 0 => Some(MAX_CP),
+        Eval::Mate(n) if n < 0 => Some(-MAX_CP),
+        Eval::Mate(_) => None,
+    }
+}
+
+/// Chances of winning of the side the evaluation is for, from 0 to 100.
+fn win_percent(cp: i32) -> f64 {
+    50.0 + 50.0 * (2.0 / (1.0 + (-0.00368208 * cp as f64).exp()) - 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn judge_moves() {
+        let mut pgn = Pgn::new("game", 1);
+        pgn.moves_text = "1. e4 {[%eval 0.30]} 1... e5 {[%eval 0.40]} 2. Qh5 {[%eval -0.40]} \
+            2... g6 {[%eval -0.30]} 3. Qxg6 {[%eval -9.50]} 3... hxg6 {[%eval -9.60]} 0-1"
+            .to_string();
+        let evals = evals_from_comments(&pgn);
+        assert_eq!(evals.len(), 6);
+
+        let analysis = analyze_game(&pgn, &evals, &Thresholds::default()).unwrap();
+        let losses: Vec<i32> = analysis.moves.iter().map(|m| m.cp_loss).collect();
+        assert_eq!(losses, vec![0, 10, 80, 10, 920, 0]);
+        assert_eq!(
+            analysis.nags(6),
+            vec![None, None, Some(6), None, Some(4), None]
+        );
+
+        assert_eq!(analysis.white.moves, 3);
+        assert_eq!(analysis.white.acpl, Some(1000.0 / 3.0));
+        assert_eq!(analysis.white.inaccuracies, 1);
+        assert_eq!(analysis.white.blunders, 1);
+        assert_eq!(analysis.black.acpl, Some(20.0 / 3.0));
+        assert!(analysis.black.accuracy.unwrap() > 95.0);
+        assert!(analysis.white.accuracy.unwrap() < analysis.black.accuracy.unwrap());
+
+        let strict = Thresholds {
+            inaccuracy: 5,
+            mistake: 50,
+            blunder: 1000,
+        };
+        let analysis = analyze_game(&pgn, &evals, &strict).unwrap();
+        assert_eq!(
+            analysis.nags(6),
+            vec![None, Some(6), Some(2), Some(6), Some(2), None]
+        );
+    }
+}
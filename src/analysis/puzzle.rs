@@ -0,0 +1,370 @@
+use std::io;
+
+use serde::{Deserialize, Serialize};
+use shakmaty::{Chess, Position, Role};
+use simple_error::SimpleError;
+
+use super::{analyze_evals, to_cp, Thresholds};
+use crate::board::{fen, parse_fen, parse_uci, start_position, uci, uci_moves_to_san, Replay};
+use crate::pgn::annotation::Eval;
+use crate::pgn::Pgn;
+use crate::uci::{Engine, Info, Limit};
+
+/// A position to solve, taken from a game after a move that threw away the
+/// advantage or let the opponent win.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Puzzle {
+    /// `{game id}:{ply}`.
+    pub id: String,
+    /// Position to solve, with the solver to move.
+    pub fen: String,
+    /// Solution in UCI notation, the solver's moves and the replies, ending
+    /// with a move of the solver.
+    pub moves: Vec<String>,
+    pub themes: Vec<String>,
+    pub game_id: String,
+    /// Number of moves played in the game before the position.
+    pub ply: usize,
+}
+
+impl Puzzle {
+    /// The puzzle as a game starting from its position, with the solution as
+    /// moves.
+    pub fn to_pgn(&self) -> Result<Pgn, SimpleError> {
+        let pos = parse_fen(self.fen.as_str())?;
+
+        let mut pgn = Pgn::new("puzzle", 0);
+        pgn.id = self.id.clone();
+        pgn.moves = uci_moves_to_san(&pos, &self.moves)?;
+        for (name, value) in [
+            ("Event", "Puzzle"),
+            ("Site", self.game_id.as_str()),
+            ("Result", "*"),
+            ("SetUp", "1"),
+            ("FEN", self.fen.as_str()),
+            ("PuzzleId", self.id.as_str()),
+            ("PuzzleThemes", self.themes.join(" ").as_str()),
+        ] {
+            pgn.set_tag(name, value);
+        }
+
+        Ok(pgn)
+    }
+}
+
+/// Finds puzzles in analysed games: after a move losing at least a given
+/// number of centipawns, the opponent must have exactly one move keeping a
+/// winning advantage, at every move of the solution as checked by the engine.
+#[derive(Debug, Clone)]
+pub struct PuzzleFinder {
+    limit: Limit,
+    min_swing: i32,
+    min_advantage: i32,
+    unique_margin: i32,
+    max_moves: usize,
+}
+
+impl PuzzleFinder {
+    pub fn new(limit: Limit) -> Self {
+        Self {
+            limit,
+            min_swing: 200,
+            min_advantage: 200,
+            unique_margin: 150,
+            max_moves: 4,
+        }
+    }
+
+    /// Centipawns the move before the puzzle must lose.
+    pub fn with_min_swing(mut self, min_swing: i32) -> Self {
+        self.min_swing = min_swing;
+        self
+    }
+
+    /// Centipawns the solver must be ahead by after each solution move.
+    pub fn with_min_advantage(mut self, min_advantage: i32) -> Self {
+        self.min_advantage = min_advantage;
+        self
+    }
+
+    /// Centipawns the second best move must be behind the best one by.
+    pub fn with_unique_margin(mut self, unique_margin: i32) -> Self {
+        self.unique_margin = unique_margin;
+        self
+    }
+
+    /// Most solver moves in a solution.
+    pub fn with_max_moves(mut self, max_moves: usize) -> Self {
+        self.max_moves = max_moves;
+        self
+    }
+
+    /// Puzzles of `pgn` given the evaluation after each move, from the point
+    /// of view of White, e.g. from `evals_from_comments` or
+    /// `Engine::analyze`. The `MultiPV` option of `engine` is set to 2 while
+    /// searching, then back to its previous value.
+    pub fn find(
+        &self,
+        engine: &mut Engine,
+        pgn: &Pgn,
+        evals: &[Option<Eval>],
+    ) -> io::Result<Vec<Puzzle>> {
+        let invalid = |e: SimpleError| io::Error::new(io::ErrorKind::InvalidData, e);
+        let replay = Replay::new(pgn).map_err(invalid)?;
+        if evals.len() > replay.moves.len() {
+            return Err(invalid(SimpleError::new("more evaluations than moves")));
+        }
+        let start = start_position(pgn).map_err(invalid)?;
+        let initial = (!pgn.tags.contains_key("FEN")).then_some(Eval::Cp(0));
+        let thresholds = Thresholds {
+            inaccuracy: self.min_swing,
+            mistake: self.min_swing,
+            blunder: self.min_swing,
+        };
+        let analysis = analyze_evals(evals, start.turn(), initial, &thresholds);
+
+        let previous = engine.option("MultiPV").unwrap_or("1").to_string();
+        engine.set_option("MultiPV", "2")?;
+
+        let mut search = || -> io::Result<Vec<Puzzle>> {
+            engine.new_game()?;
+
+            let mut puzzles: Vec<Puzzle> = Vec::new();
+            for m in analysis.moves.iter().filter(|m| m.judgement.is_some()) {
+                let ply = m.ply + 1;
+                let pos = &replay.positions[ply];
+                // The opponent of the mover must be winning afterwards.
+                let sign = pos.turn().fold_wb(1, -1);
+                let winning = evals[m.ply]
+                    .and_then(to_cp)
+                    .is_some_and(|cp| sign * cp >= self.min_advantage);
+                if !winning || pos.is_game_over() {
+                    continue;
+                }
+
+                if let Some(moves) = self.solve(engine, pos)? {
+                    puzzles.push(Puzzle {
+                        id: format!("{}:{}", pgn.id, ply),
+                        fen: fen(pos),
+                        themes: themes(pos, &moves, ply),
+                        moves,
+                        game_id: pgn.id.clone(),
+                        ply,
+                    });
+                }
+            }
+
+            Ok(puzzles)
+        };
+        let puzzles = search();
+
+        engine.set_option("MultiPV", previous.as_str())?;
+        puzzles
+    }
+
+    /// Solution from `pos`, as long as the solver has a single good move.
+    fn solve(&self, engine: &mut Engine, pos: &Chess) -> io::Result<Option<Vec<String>>> {
+        let mut moves: Vec<String> = Vec::new();
+        let mut current = pos.clone();
+
+        for _ in 0..self.max_moves {
+            let result = engine.go(Some(pos), &moves, self.limit)?;
+            let best = match result.lines.first() {
+                Some(best) if self.is_unique(best, result.lines.get(1)) => best,
+                _ => break,
+            };
+
+            // The best line gives the solver's move and the reply.
+            let mut line = best.pv.iter();
+            match line.next().map(|m| parse_uci(&current, m)) {
+                Some(Ok(m)) => {
+                    moves.push(uci(m));
+                    current.play_unchecked(m);
+                }
+                _ => break,
+            }
+            if current.is_game_over() {
+                break;
+            }
+            match line.next().map(|m| parse_uci(&current, m)) {
+                Some(Ok(m)) => {
+                    moves.push(uci(m));
+                    current.play_unchecked(m);
+                }
+                _ => break,
+            }
+        }
+
+        // The solution ends with a move of the solver.
+        if moves.len().is_multiple_of(2) {
+            moves.pop();
+        }
+        Ok((!moves.is_empty()).then_some(moves))
+    }
+
+    /// Whether `best` keeps the advantage and `second` does not come close.
+    fn is_unique(&self, best: &Info, second: Option<&Info>) -> bool {
+        let score = |info: &Info| info.score.and_then(to_cp);
+        let best = match score(best) {
+            Some(best) if best >= self.min_advantage => best,
+            _ => return false,
+        };
+        match second.map(score) {
+            None => true,
+            Some(None) => false,
+            Some(Some(second)) => {
+                second < self.min_advantage && second <= best - self.unique_margin
+            }
+        }
+    }
+}
+
+/// Themes of a puzzle from its position, solution and ply in the game.
+pub fn themes(pos: &Chess, moves: &[String], ply: usize) -> Vec<String> {
+    let mut themes: Vec<String> = Vec::new();
+    let solver_moves = moves.len().div_ceil(2);
+
+    let mut last = pos.clone();
+    for m in moves.iter() {
+        match parse_uci(&last, m) {
+            Ok(m) => last.play_unchecked(m),
+            Err(_) => break,
+        }
+    }
+    if last.is_checkmate() {
+        themes.push("mate".to_string());
+        themes.push(format!("mateIn{}", solver_moves));
+    }
+
+    themes.push(
+        match solver_moves {
+            1 => "oneMove",
+            2 => "short",
+            3 => "long",
+            _ => "veryLong",
+        }
+        .to_string(),
+    );
+
+    if moves.iter().step_by(2).any(|m| m.len() == 5) {
+        themes.push("promotion".to_string());
+    }
+
+    let board = pos.board();
+    let pieces = (board.occupied() & !board.kings() & !board.by_role(Role::Pawn)).count();
+    themes.push(
+        if ply < 20 {
+            "opening"
+        } else if pieces <= 6 {
+            "endgame"
+        } else {
+            "middlegame"
+        }
+        .to_string(),
+    );
+
+    themes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn puzzle_output() {
+        let puzzle = Puzzle {
+            id: "game.1:3".to_string(),
+            fen: "rnbqkbnr/pppp1ppp/8/4p3/6P1/5P2/PPPPP2P/RNBQKBNR b KQkq - 0 2".to_string(),
+            moves: vec!["d8h4".to_string()],
+            themes: vec!["mate".to_string(), "mateIn1".to_string()],
+            game_id: "game.1".to_string(),
+            ply: 3,
+        };
+
+        let pos = parse_fen(puzzle.fen.as_str()).unwrap();
+        assert_eq!(
+            themes(&pos, &puzzle.moves, puzzle.ply),
+            vec!["mate", "mateIn1", "oneMove", "opening"]
+        );
+
+        assert_eq!(
+            serde_json::to_string(&puzzle).unwrap(),
+            "{\"id\":\"game.1:3\",\
+            \"fen\":\"rnbqkbnr/pppp1ppp/8/4p3/6P1/5P2/PPPPP2P/RNBQKBNR b KQkq - 0 2\",\
+            \"moves\":[\"d8h4\"],\"themes\":[\"mate\",\"mateIn1\"],\
+            \"game_id\":\"game.1\",\"ply\":3}"
+        );
+
+        let pgn = puzzle.to_pgn().unwrap();
+        assert_eq!(pgn.moves, vec!["Qh4#"]);
+        assert_eq!(pgn.tags["SetUp"], "1");
+        assert_eq!(pgn.tags["PuzzleThemes"], "mate mateIn1");
+    }
+
+    /// Answers every search with mate in one by `d8h4` as the best line and
+    /// `second` centipawns for `b8c6` as the second one.
+    #[cfg(unix)]
+    fn scripted_engine(second: i32) -> (Engine, std::path::PathBuf) {
+        let script = format!(
+            r#"
+while read -r line; do
+    case "$line" in
+        uci) echo "id name Scripted"; echo "option name MultiPV type spin default 1 min 1 max 500"; echo "uciok" ;;
+        isready) echo "readyok" ;;
+        go*) echo "info depth 1 multipv 1 score mate 1 pv d8h4"; echo "info depth 1 multipv 2 score cp {} pv b8c6"; echo "bestmove d8h4" ;;
+        quit) exit 0 ;;
+    esac
+done
+"#,
+            second
+        );
+        let path = std::env::temp_dir().join(format!(
+            "mudfish-puzzle-{}-{}.sh",
+            std::process::id(),
+            second
+        ));
+        std::fs::write(&path, script).unwrap();
+
+        let engine = Engine::launch("sh", &[path.to_string_lossy().to_string()]).unwrap();
+        (engine, path)
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn find_puzzles() {
+        let mut pgn = Pgn::new("game", 1);
+        pgn.moves = ["f3", "e5", "g4", "Qh4#"]
+            .iter()
+            .map(|m| m.to_string())
+            .collect();
+        // 2. g4 lets Black mate.
+        let evals = vec![
+            Some(Eval::Cp(-20)),
+            Some(Eval::Cp(-30)),
+            Some(Eval::Mate(-1)),
+            None,
+        ];
+        let finder = PuzzleFinder::new(Limit::Depth(1));
+
+        let (mut engine, path) = scripted_engine(0);
+        let puzzles = finder.find(&mut engine, &pgn, &evals).unwrap();
+        assert_eq!(puzzles.len(), 1);
+        assert_eq!(puzzles[0].id, "game.1:3");
+        assert_eq!(puzzles[0].moves, vec!["d8h4"]);
+        assert_eq!(
+            puzzles[0].themes,
+            vec!["mate", "mateIn1", "oneMove", "opening"]
+        );
+        assert_eq!(engine.option("MultiPV"), Some("1"));
+        engine.quit().unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        // Black has two winning moves: no puzzle.
+        let (mut engine, path) = scripted_engine(900);
+        engine.set_option("MultiPV", "3").unwrap();
+        assert!(finder.find(&mut engine, &pgn, &evals).unwrap().is_empty());
+        assert_eq!(engine.option("MultiPV"), Some("3"));
+        engine.quit().unwrap();
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -1,10 +1,38 @@
+use std::collections::HashSet;
+use std::io::Write;
 use std::path::Path;
+use std::str::FromStr;
 
 use clap::{Args, Parser, Subcommand};
-use simple_error::simple_error;
+use shakmaty::{Chess, Color};
+use simple_error::{simple_error, SimpleError};
 
-use mudfish::pgn::{ReadOutcome, Reader};
-use mudfish::store::PostgresStore;
+use mudfish::analysis::puzzle::PuzzleFinder;
+use mudfish::analysis::{
+    analyze_game, evals_from_comments, GameAnalysis, PlayerAnalysis, Thresholds,
+};
+use mudfish::board::epd::{EpdWriter, PositionSelector};
+use mudfish::board::material::EndgameCategory;
+use mudfish::board::pattern::Pattern;
+use mudfish::board::termination::analyze_termination;
+use mudfish::board::{fen, parse_fen, parse_position, polyglot_key, san, uci, Replay};
+use mudfish::book::{create_book, Book, BookBuilder};
+use mudfish::eco::Classifier;
+use mudfish::pgn::annotation::{annotate, set_nags};
+use mudfish::pgn::merge;
+use mudfish::pgn::sequence::MoveSequence;
+#[cfg(feature = "parquet")]
+use mudfish::pgn::ParquetWriter;
+use mudfish::pgn::{
+    create_output, parse_columns, Filter, IdStrategy, JsonOptions, Output, Pgn, ReadOutcome,
+    Reader, SplitBy, Splitter, TimeControlClass, Writer, DEFAULT_COLUMNS,
+};
+use mudfish::rating::{Method, Period, RatingPool};
+use mudfish::store::{PostgresStore, Query, Score, SortBy, RATING_BAND};
+use mudfish::uci::{Engine, Limit};
+
+mod output;
+use output::{Format, GameOutput};
 
 #[derive(Subcommand, Debug)]
 enum Commands {
@@ -13,6 +41,79 @@ enum Commands {
 
     /// Reads PGNs.
     ReadPgn(ReadPgnArgs),
+
+    /// Removes duplicated games from database.
+    Dedup(DedupArgs),
+
+    /// Prints PGNs of games in database.
+    Query(QueryPgnArgs),
+
+    /// Converts a PGN file to another format.
+    Convert(ConvertArgs),
+
+    /// Cuts a PGN file into several by game count, size, tag value or month.
+    Split(SplitArgs),
+
+    /// Concatenates PGN files, optionally removing duplicated games and
+    /// sorting by date.
+    Merge(MergeArgs),
+
+    /// Writes the games of a PGN file matching an expression, e.g.
+    /// `WhiteElo >= 2500 && Event ~ "Olympiad" && plies > 40`.
+    Filter(FilterArgs),
+
+    /// Builds a Polyglot opening book from games in a PGN file or database.
+    BuildBook(BuildBookArgs),
+
+    /// Prints the book moves of a position.
+    ProbeBook(ProbeBookArgs),
+
+    /// Writes positions of games in a PGN file as EPD.
+    ExtractPositions(ExtractPositionsArgs),
+
+    /// Adds the positions, endgames, patterns and move n-grams of stored
+    /// games to the database.
+    IndexPositions(IndexPositionsArgs),
+
+    /// Prints statistics of the moves played from a position.
+    Explore(ExploreArgs),
+
+    /// Fills opening tags of games in database from the opening table.
+    Classify(ClassifyArgs),
+
+    /// Prints games reaching a position matching a pattern, e.g.
+    /// `Bg5 Nf3 qd8`, `iqp-white` or `opposite-bishops R r`.
+    SearchPattern(SearchPatternArgs),
+
+    /// Prints games containing consecutive moves, e.g. `Nxf7 Kxf7` or
+    /// `... Bxh2+`.
+    SearchMoves(SearchMovesArgs),
+
+    /// Prints how games ended and results contradicting it.
+    Terminations(TerminationsArgs),
+
+    /// Prints the results, performance, openings and game length of a
+    /// player over the stored games.
+    Player(PlayerArgs),
+
+    /// Rates players by Elo or Glicko-2 from their stored games, e.g. for a
+    /// club without official ratings, and prints the final ratings.
+    Rate(RateArgs),
+
+    /// Annotates each move of games with the evaluation of a UCI engine.
+    Analyze(AnalyzeArgs),
+
+    /// Judges moves by centipawn loss from `[%eval]` comments, e.g. added by
+    /// analyze, and prints the average loss and accuracy of each player.
+    Judge(JudgeArgs),
+
+    /// Writes positions of analysed games where a single move wins as
+    /// puzzles.
+    ExtractPuzzles(ExtractPuzzlesArgs),
+
+    /// Writes games in database to a PGN, JSON Lines, CSV or TSV file.
+    #[clap(alias = "export")]
+    ExportPgn(ExportPgnArgs),
 }
 
 #[derive(Args, Debug)]
@@ -26,9 +127,52 @@ struct ReadPgnArgs {
     #[clap(short, long)]
     print: bool,
 
+    /// Prints the number of games read, to standard error when `--format`
+    /// writes the games to standard output.
     #[clap(short, long)]
     count: bool,
 
+    /// How game ids are assigned: positional or content.
+    #[clap(long, default_value = "positional")]
+    id_strategy: IdStrategy,
+
+    /// Prints games as pgn, jsonl, csv or tsv.
+    #[clap(long)]
+    format: Option<Format>,
+
+    /// Comma separated tags and derived values (id, plies, fingerprint,
+    /// time_control_base, time_control_increment, time_control_class,
+    /// uci_moves, termination) for csv and tsv output.
+    #[clap(long, default_value = DEFAULT_COLUMNS)]
+    columns: String,
+
+    /// Adds the FEN after each ply to jsonl output.
+    #[clap(long)]
+    fens: bool,
+
+    /// Adds the clock after each ply to jsonl output.
+    #[clap(long)]
+    clocks: bool,
+
+    /// Adds the evaluation after each ply to jsonl output.
+    #[clap(long)]
+    evals: bool,
+
+    /// Adds moves in UCI notation to jsonl output and the uci_moves column;
+    /// games whose moves cannot be replayed are reported as bad.
+    #[clap(long)]
+    uci: bool,
+
+    /// Fills missing ECO, Opening and Variation tags from the opening
+    /// table.
+    #[clap(long)]
+    classify: bool,
+
+    /// With --classify, replaces opening tags inconsistent with the table,
+    /// unless the table only has a coarser opening.
+    #[clap(long)]
+    overwrite_eco: bool,
+
     pgnfile: String,
 }
 
@@ -46,91 +190,1731 @@ struct StorePgnArgs {
     #[clap(short, long)]
     count: bool,
 
+    /// Skips games of which a richer copy is already stored.
+    #[clap(long)]
+    dedup: bool,
+
+    /// How game ids are assigned: positional or content.
+    #[clap(long, default_value = "positional")]
+    id_strategy: IdStrategy,
+
+    /// Fills missing ECO, Opening and Variation tags from the opening
+    /// table.
+    #[clap(long)]
+    classify: bool,
+
+    /// With --classify, replaces opening tags inconsistent with the table,
+    /// unless the table only has a coarser opening.
+    #[clap(long)]
+    overwrite_eco: bool,
+
     pgnfile: String,
 }
 
-#[derive(Parser, Debug)]
-#[clap(author, version, about, long_about = None)]
-struct App {
-    #[clap(subcommand)]
-    command: Commands,
+#[derive(Args, Debug)]
+struct DedupArgs {
+    #[clap(long, default_value = "postgres://localhost/mudfish")]
+    postgres_uri: String,
 }
 
-fn store_pgn(args: &StorePgnArgs) -> Result<(), Box<dyn std::error::Error>> {
-    let p = Path::new(args.pgnfile.as_str());
-    let mut reader = Reader::new(p)?;
+#[derive(Args, Debug)]
+struct QueryArgs {
+    /// Player with either colour, `%` matches any characters.
+    #[clap(long)]
+    player: Option<String>,
 
-    let mut store = PostgresStore::open(args.postgres_uri.as_str())?;
+    #[clap(long)]
+    min_elo: Option<i32>,
 
-    let mut count: usize = 0;
-    loop {
-        match reader.read_next() {
-            ReadOutcome::Game(pgn) => {
-                count += 1;
-                if count < args.start {
-                    continue;
-                }
+    #[clap(long)]
+    max_elo: Option<i32>,
 
-                if let Err(err) = store.upsert_pgn(&pgn) {
-                    return Err(Box::new(err));
-                }
+    /// Earliest date, as YYYY.MM.DD, YYYY.MM or YYYY.
+    #[clap(long)]
+    date_from: Option<String>,
 
-                if args.end > 0 && count >= args.end {
-                    return Ok(());
-                }
-            }
-            ReadOutcome::Ended => {
-                if args.count {
-                    println!("{}", count);
-                }
-                return Ok(());
-            }
-            ReadOutcome::BadPgn(message) => {
-                println!("{}", message);
-                continue;
-            }
-            ReadOutcome::Error(message) => return Err(Box::new(simple_error!(message))),
+    /// Latest date, as YYYY.MM.DD, YYYY.MM or YYYY.
+    #[clap(long)]
+    date_to: Option<String>,
+
+    #[clap(long)]
+    eco_from: Option<String>,
+
+    #[clap(long)]
+    eco_to: Option<String>,
+
+    #[clap(long)]
+    result: Option<String>,
+
+    #[clap(long)]
+    event: Option<String>,
+
+    /// bullet, blitz, rapid, classical or correspondence.
+    #[clap(long)]
+    time_control: Option<TimeControlClass>,
+
+    #[clap(long)]
+    min_plies: Option<i32>,
+
+    /// Material signature where the game entered an endgame, e.g. KRPvKR,
+    /// `%` matches any pieces.
+    #[clap(long)]
+    material: Option<String>,
+
+    /// Endgame the game entered: pawn, minor, rook, queen or mixed.
+    #[clap(long)]
+    endgame: Option<EndgameCategory>,
+
+    /// id, date, white-elo, black-elo, elo or plies.
+    #[clap(long)]
+    sort: Option<SortBy>,
+
+    #[clap(long)]
+    desc: bool,
+
+    #[clap(long)]
+    limit: Option<i64>,
+
+    #[clap(long)]
+    offset: Option<i64>,
+}
+
+impl QueryArgs {
+    fn to_query(&self) -> Query {
+        let mut query = Query::new()
+            .elo(self.min_elo, self.max_elo)
+            .date(self.date_from.clone(), self.date_to.clone())
+            .eco(self.eco_from.clone(), self.eco_to.clone());
+
+        if let Some(player) = &self.player {
+            query = query.player(player);
+        }
+        if let Some(result) = &self.result {
+            query = query.result(result);
+        }
+        if let Some(event) = &self.event {
+            query = query.event(event);
+        }
+        if let Some(class) = self.time_control {
+            query = query.time_control_class(class);
+        }
+        if let Some(plies) = self.min_plies {
+            query = query.min_plies(plies);
+        }
+        if let Some(material) = &self.material {
+            query = query.material(material);
+        }
+        if let Some(category) = self.endgame {
+            query = query.endgame(category);
+        }
+        if let Some(sort) = self.sort {
+            query = query.sort_by(sort, self.desc);
+        }
+        if let Some(limit) = self.limit {
+            query = query.limit(limit);
+        }
+        if let Some(offset) = self.offset {
+            query = query.offset(offset);
         }
+
+        query
     }
 }
 
-fn read_pgn(args: &ReadPgnArgs) -> Result<(), Box<dyn std::error::Error>> {
-    let p = Path::new(args.pgnfile.as_str());
-    let mut reader = Reader::new(p)?;
+#[derive(Args, Debug)]
+struct QueryPgnArgs {
+    #[clap(long, default_value = "postgres://localhost/mudfish")]
+    postgres_uri: String,
 
-    let mut count: usize = 0;
-    loop {
-        match reader.read_next() {
-            ReadOutcome::Game(pgn) => {
-                count += 1;
-                if count < args.start {
-                    continue;
-                }
+    #[clap(flatten)]
+    query: QueryArgs,
+}
 
-                if args.print {
-                    println!("{}\n\n{}\n{}\n", pgn.id, pgn.tags_text, pgn.moves_text);
-                }
+#[derive(Args, Debug)]
+struct ExportPgnArgs {
+    #[clap(long, default_value = "postgres://localhost/mudfish")]
+    postgres_uri: String,
 
-                if args.end > 0 && count >= args.end {
-                	if args.count {
-                		println!("{}", count);
-                	}
-                    return Ok(());
-                }
-            }
-            ReadOutcome::Ended => {
-            	if args.count {
-            		println!("{}", count);
-            	}
-            	return Ok(());
-            }
-            ReadOutcome::BadPgn(message) => {
-                println!("{}", message);
-                continue;
-            }
-            ReadOutcome::Error(message) => return Err(Box::new(simple_error!(message))),
-        }
-    }
+    #[clap(flatten)]
+    query: QueryArgs,
+
+    #[clap(short, long)]
+    count: bool,
+
+    /// pgn, jsonl, csv or tsv.
+    #[clap(long, default_value = "pgn")]
+    format: Format,
+
+    /// Comma separated tags and derived values (id, plies, fingerprint,
+    /// time_control_base, time_control_increment, time_control_class,
+    /// uci_moves, termination) for csv and tsv output.
+    #[clap(long, default_value = DEFAULT_COLUMNS)]
+    columns: String,
+
+    /// Adds moves in UCI notation to jsonl output and the uci_moves column.
+    #[clap(long)]
+    uci: bool,
+
+    /// Output file, compressed if it ends with .bz2 or .zst. Standard
+    /// output if not given.
+    output: Option<String>,
+}
+
+#[derive(Args, Debug)]
+struct ConvertArgs {
+    /// pgn, jsonl, csv, tsv, or parquet when built with the parquet feature.
+    #[clap(long)]
+    to: String,
+
+    /// Games per row group of parquet output.
+    #[clap(long, default_value_t = 100_000)]
+    row_group_size: usize,
+
+    /// Comma separated tags and derived values for csv and tsv output.
+    #[clap(long, default_value = DEFAULT_COLUMNS)]
+    columns: String,
+
+    /// Adds moves in UCI notation to jsonl and parquet output and the
+    /// uci_moves column; games whose moves cannot be replayed are reported
+    /// as bad.
+    #[clap(long)]
+    uci: bool,
+
+    /// Fills missing ECO, Opening and Variation tags from the opening
+    /// table.
+    #[clap(long)]
+    classify: bool,
+
+    /// With --classify, replaces opening tags inconsistent with the table,
+    /// unless the table only has a coarser opening.
+    #[clap(long)]
+    overwrite_eco: bool,
+
+    #[clap(short, long)]
+    count: bool,
+
+    pgnfile: String,
+
+    /// Output file, compressed if it ends with .bz2 or .zst unless written
+    /// as parquet.
+    output: String,
+}
+
+#[derive(Args, Debug)]
+struct SplitArgs {
+    /// `games:N`, `size:N` with an optional K, M or G suffix, `tag:NAME` for
+    /// one file per value of a tag, or `month` for one file per month of the
+    /// Date tag.
+    #[clap(long)]
+    by: SplitBy,
+
+    /// Prints each file written and its number of games.
+    #[clap(short, long)]
+    count: bool,
+
+    /// Input file, compressed if it ends with .bz2 or .zst.
+    pgnfile: String,
+
+    /// Template of the output files, e.g. `out/games.pgn.zst` for
+    /// `out/games-0001.pgn.zst` and so on, compressed if it ends with .bz2
+    /// or .zst.
+    output: String,
+}
+
+#[derive(Args, Debug)]
+struct MergeArgs {
+    /// Removes games with the same moves, players and date, keeping the
+    /// richest copy. Holds all games in memory.
+    #[clap(long)]
+    dedup: bool,
+
+    /// Sorts games by date, unknown dates last and unknown months and days
+    /// first. Holds all games in memory.
+    #[clap(long)]
+    sort_date: bool,
+
+    /// Prints the number of games written, and removed as duplicates, to
+    /// standard error.
+    #[clap(short, long)]
+    count: bool,
+
+    /// Output file, compressed if it ends with .bz2 or .zst. Standard
+    /// output if not given.
+    #[clap(short, long)]
+    output: Option<String>,
+
+    /// Input files, compressed if they end with .bz2 or .zst.
+    #[clap(required = true)]
+    pgnfiles: Vec<String>,
+}
+
+#[derive(Args, Debug)]
+struct FilterArgs {
+    /// Prints the number of games read and written to standard error.
+    #[clap(short, long)]
+    count: bool,
+
+    /// Comparisons of tags and derived values (id, plies, fingerprint,
+    /// time_control_base, time_control_increment, time_control_class,
+    /// termination) with `==`, `!=`, `<`, `<=`, `>`, `>=`, and `~` or `!~`
+    /// for regular expressions, combined with `&&`, `||`, `!` and
+    /// parentheses. A name alone tests that the value is present.
+    expression: String,
+
+    /// Input file, compressed if it ends with .bz2 or .zst.
+    pgnfile: String,
+
+    /// Output file, compressed if it ends with .bz2 or .zst. Standard
+    /// output if not given.
+    output: Option<String>,
+}
+
+#[derive(Args, Debug)]
+struct BuildBookArgs {
+    #[clap(long, default_value = "postgres://localhost/mudfish")]
+    postgres_uri: String,
+
+    /// Reads games from a PGN file instead of the database.
+    #[clap(long)]
+    pgn: Option<String>,
+
+    /// Minimum Elo of both players.
+    #[clap(long)]
+    min_elo: Option<i32>,
+
+    /// Minimum number of games a move is played in.
+    #[clap(long, default_value_t = 1)]
+    min_games: usize,
+
+    /// Only records the moves of white or black.
+    #[clap(long)]
+    colour: Option<Color>,
+
+    /// Only records the first plies of each game.
+    #[clap(long)]
+    max_ply: Option<usize>,
+
+    /// Prints the number of games and entries.
+    #[clap(short, long)]
+    count: bool,
+
+    /// Book file to write, usually ending with .bin.
+    output: String,
+}
+
+#[derive(Args, Debug)]
+struct ProbeBookArgs {
+    /// Position to probe, the starting position if not given.
+    #[clap(long)]
+    fen: Option<String>,
+
+    book: String,
+}
+
+#[derive(Args, Debug)]
+struct ExtractPositionsArgs {
+    /// Comma separated plies, 0 being the starting position.
+    #[clap(long, value_delimiter = ',')]
+    ply: Vec<usize>,
+
+    /// Positions at every Nth ply.
+    #[clap(long)]
+    every: Option<usize>,
+
+    /// Positions after SAN moves matching a regular expression, e.g. `Qx.*`.
+    #[clap(long)]
+    after: Option<String>,
+
+    /// Writes each position only once.
+    #[clap(long)]
+    unique: bool,
+
+    /// Prints the number of positions to standard error.
+    #[clap(short, long)]
+    count: bool,
+
+    pgnfile: String,
+
+    /// Output file, compressed if it ends with .bz2 or .zst. Standard
+    /// output if not given.
+    output: Option<String>,
+}
+
+#[derive(Args, Debug)]
+struct IndexPositionsArgs {
+    #[clap(long, default_value = "postgres://localhost/mudfish")]
+    postgres_uri: String,
+
+    #[clap(flatten)]
+    query: QueryArgs,
+}
+
+#[derive(Args, Debug)]
+struct ExploreArgs {
+    #[clap(long, default_value = "postgres://localhost/mudfish")]
+    postgres_uri: String,
+
+    /// Minimum Elo of both players.
+    #[clap(long)]
+    min_elo: Option<i32>,
+
+    /// Maximum Elo of both players.
+    #[clap(long)]
+    max_elo: Option<i32>,
+
+    /// bullet, blitz, rapid, classical or correspondence.
+    #[clap(long)]
+    time_control: Option<TimeControlClass>,
+
+    /// Earliest date, as YYYY.MM.DD, YYYY.MM or YYYY.
+    #[clap(long)]
+    date_from: Option<String>,
+
+    /// Latest date, as YYYY.MM.DD, YYYY.MM or YYYY.
+    #[clap(long)]
+    date_to: Option<String>,
+
+    /// FEN, or SAN moves from the starting position, e.g. "1. e4 c5".
+    position: String,
+}
+
+#[derive(Args, Debug)]
+struct ClassifyArgs {
+    #[clap(long, default_value = "postgres://localhost/mudfish")]
+    postgres_uri: String,
+
+    #[clap(flatten)]
+    query: QueryArgs,
+
+    /// Replaces opening tags inconsistent with the table, unless the table
+    /// only has a coarser opening.
+    #[clap(long)]
+    overwrite: bool,
+
+    /// Opening table to use instead of the built-in one, as tab separated
+    /// eco, name and pgn columns after a header line.
+    #[clap(long)]
+    eco_file: Option<String>,
+}
+
+#[derive(Args, Debug)]
+struct SearchPatternArgs {
+    #[clap(long, default_value = "postgres://localhost/mudfish")]
+    postgres_uri: String,
+
+    #[clap(flatten)]
+    query: QueryArgs,
+
+    /// Reads games from a PGN file instead of the database.
+    #[clap(long)]
+    pgn: Option<String>,
+
+    /// Conditions separated by spaces or commas: `Bg5` for a white bishop on
+    /// g5, `qd8` for a black queen on d8, `R`, `r=2`, `P>=6` or `N<=1` for
+    /// piece counts, `iqp-white`, `iqp-black`, `opposite-bishops`, and any
+    /// of them negated with `!`.
+    pattern: String,
+}
+
+#[derive(Args, Debug)]
+struct SearchMovesArgs {
+    #[clap(long, default_value = "postgres://localhost/mudfish")]
+    postgres_uri: String,
+
+    #[clap(flatten)]
+    query: QueryArgs,
+
+    /// Reads games from a PGN file instead of the database.
+    #[clap(long)]
+    pgn: Option<String>,
+
+    /// Earliest ply of the first move, 1 being White's first move.
+    #[clap(long)]
+    min_ply: Option<usize>,
+
+    /// Latest ply of the first move.
+    #[clap(long)]
+    max_ply: Option<usize>,
+
+    /// Moves in SAN, optionally starting with a move number such as `12.`
+    /// or `12...`, or with `...` when the first move is Black's.
+    moves: String,
+}
+
+#[derive(Args, Debug)]
+struct PlayerArgs {
+    #[clap(long, default_value = "postgres://localhost/mudfish")]
+    postgres_uri: String,
+
+    /// bullet, blitz, rapid, classical or correspondence.
+    #[clap(long)]
+    time_control: Option<TimeControlClass>,
+
+    /// Earliest date, as YYYY.MM.DD, YYYY.MM or YYYY.
+    #[clap(long)]
+    date_from: Option<String>,
+
+    /// Latest date, as YYYY.MM.DD, YYYY.MM or YYYY.
+    #[clap(long)]
+    date_to: Option<String>,
+
+    /// Most played openings to print for each colour.
+    #[clap(long, default_value_t = 5)]
+    openings: usize,
+
+    /// FIDE id, or name where `%` matches any characters.
+    player: String,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum RatingMethod {
+    Elo,
+    Glicko2,
+}
+
+impl FromStr for RatingMethod {
+    type Err = SimpleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "elo" => Ok(RatingMethod::Elo),
+            "glicko2" => Ok(RatingMethod::Glicko2),
+            _ => Err(simple_error!("unknown rating method: {}", s)),
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+struct RateArgs {
+    #[clap(long, default_value = "postgres://localhost/mudfish")]
+    postgres_uri: String,
+
+    #[clap(flatten)]
+    query: QueryArgs,
+
+    /// elo or glicko2.
+    #[clap(long, default_value = "elo")]
+    method: RatingMethod,
+
+    /// Development coefficient of Elo.
+    #[clap(long, default_value_t = 20.0)]
+    k: f64,
+
+    /// System constant of Glicko-2, constraining changes of volatility.
+    #[clap(long, default_value_t = 0.5)]
+    tau: f64,
+
+    /// Rating of new players.
+    #[clap(long, default_value_t = 1500.0)]
+    initial: f64,
+
+    /// Rating period: day, month or year.
+    #[clap(long, default_value = "month")]
+    period: Period,
+
+    /// Stores the rating history under this pool name, replacing the
+    /// previous history of the pool.
+    #[clap(long)]
+    pool: Option<String>,
+}
+
+#[derive(Args, Debug)]
+struct TerminationsArgs {
+    #[clap(long, default_value = "postgres://localhost/mudfish")]
+    postgres_uri: String,
+
+    #[clap(flatten)]
+    query: QueryArgs,
+
+    /// Reads games from a PGN file instead of the database.
+    #[clap(long)]
+    pgn: Option<String>,
+
+    /// Only prints games whose result contradicts the final position.
+    #[clap(long)]
+    contradictions: bool,
+}
+
+#[derive(Args, Debug)]
+struct EngineArgs {
+    /// Path of the engine binary.
+    #[clap(long)]
+    engine: String,
+
+    /// Argument passed to the engine, may be repeated.
+    #[clap(long)]
+    engine_arg: Vec<String>,
+
+    /// Engine option as Name=Value, may be repeated.
+    #[clap(long)]
+    option: Vec<String>,
+
+    /// Search depth, 12 unless --nodes is given.
+    #[clap(long)]
+    depth: Option<u32>,
+
+    /// Nodes searched per position.
+    #[clap(long, conflicts_with = "depth")]
+    nodes: Option<u64>,
+}
+
+impl EngineArgs {
+    /// Starts the engine with the options given.
+    fn launch(&self) -> Result<(Engine, Limit), Box<dyn std::error::Error>> {
+        let mut engine = Engine::launch(self.engine.as_str(), &self.engine_arg)?;
+        for option in self.option.iter() {
+            let (name, value) = option
+                .split_once('=')
+                .ok_or_else(|| simple_error!("option must be Name=Value: {}", option))?;
+            engine.set_option(name.trim(), value.trim())?;
+        }
+        let limit = match self.nodes {
+            Some(nodes) => Limit::Nodes(nodes),
+            None => Limit::Depth(self.depth.unwrap_or(12)),
+        };
+        Ok((engine, limit))
+    }
+}
+
+#[derive(Args, Debug)]
+struct AnalyzeArgs {
+    #[clap(long, default_value = "postgres://localhost/mudfish")]
+    postgres_uri: String,
+
+    #[clap(flatten)]
+    query: QueryArgs,
+
+    #[clap(flatten)]
+    engine: EngineArgs,
+
+    /// Reads games from a PGN file instead of the database.
+    #[clap(long)]
+    pgn: Option<String>,
+
+    /// Saves the annotated games to the database instead of printing them.
+    #[clap(long, conflicts_with = "pgn")]
+    update: bool,
+
+    /// PGN file to write, standard output if omitted.
+    #[clap(short, long)]
+    output: Option<String>,
+}
+
+#[derive(Args, Debug)]
+struct JudgeArgs {
+    #[clap(long, default_value = "postgres://localhost/mudfish")]
+    postgres_uri: String,
+
+    #[clap(flatten)]
+    query: QueryArgs,
+
+    /// Reads games from a PGN file instead of the database.
+    #[clap(long)]
+    pgn: Option<String>,
+
+    /// Centipawn loss from which a move is an inaccuracy.
+    #[clap(long, default_value_t = 50)]
+    inaccuracy: i32,
+
+    /// Centipawn loss from which a move is a mistake.
+    #[clap(long, default_value_t = 100)]
+    mistake: i32,
+
+    /// Centipawn loss from which a move is a blunder.
+    #[clap(long, default_value_t = 300)]
+    blunder: i32,
+
+    /// Prints the games with $6, $2 and $4 after inaccuracies, mistakes and
+    /// blunders instead of the totals.
+    #[clap(long)]
+    nags: bool,
+
+    /// Saves the totals to the game_analysis table and the NAGs to the games.
+    #[clap(long, conflicts_with = "pgn")]
+    update: bool,
+}
+
+#[derive(Args, Debug)]
+struct ExtractPuzzlesArgs {
+    #[clap(long, default_value = "postgres://localhost/mudfish")]
+    postgres_uri: String,
+
+    #[clap(flatten)]
+    query: QueryArgs,
+
+    #[clap(flatten)]
+    engine: EngineArgs,
+
+    /// Reads games from a PGN file instead of the database.
+    #[clap(long)]
+    pgn: Option<String>,
+
+    /// Centipawns the move before a puzzle must lose.
+    #[clap(long, default_value_t = 200)]
+    min_swing: i32,
+
+    /// Centipawns the solver must be ahead by after each solution move.
+    #[clap(long, default_value_t = 200)]
+    min_advantage: i32,
+
+    /// Centipawns the second best move must be behind the best one by.
+    #[clap(long, default_value_t = 150)]
+    unique_margin: i32,
+
+    /// Most solver moves in a solution.
+    #[clap(long, default_value_t = 4)]
+    max_moves: usize,
+
+    /// Writes puzzles as jsonl or pgn.
+    #[clap(long, default_value = "jsonl")]
+    format: Format,
+
+    /// Output file, compressed if it ends with .bz2 or .zst. Standard
+    /// output if not given.
+    #[clap(short, long)]
+    output: Option<String>,
+}
+
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct App {
+    #[clap(subcommand)]
+    command: Commands,
+}
+
+fn store_pgn(args: &StorePgnArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let p = Path::new(args.pgnfile.as_str());
+    let reader = Reader::new(p)?.with_id_strategy(args.id_strategy);
+    let mut reader = with_classifier(reader, args.classify, args.overwrite_eco);
+
+    let mut store = PostgresStore::open(args.postgres_uri.as_str())?;
+
+    let mut count: usize = 0;
+    loop {
+        match reader.read_next() {
+            ReadOutcome::Game(pgn) => {
+                count += 1;
+                if count < args.start {
+                    continue;
+                }
+
+                let stored = if args.dedup {
+                    store.upsert_pgn_dedup(&pgn)
+                } else {
+                    store.upsert_pgn(&pgn).map(|_| true)
+                };
+
+                if let Err(err) = stored {
+                    return Err(Box::new(err));
+                }
+
+                if args.end > 0 && count >= args.end {
+                    return Ok(());
+                }
+            }
+            ReadOutcome::Ended => {
+                if args.count {
+                    println!("{}", count);
+                }
+                return Ok(());
+            }
+            ReadOutcome::BadPgn(message) => {
+                println!("{}", message);
+                continue;
+            }
+            ReadOutcome::Error(message) => return Err(Box::new(simple_error!(message))),
+        }
+    }
+}
+
+fn read_pgn(args: &ReadPgnArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let p = Path::new(args.pgnfile.as_str());
+    let reader = Reader::new(p)?
+        .with_id_strategy(args.id_strategy)
+        .with_uci_moves(args.uci);
+    let mut reader = with_classifier(reader, args.classify, args.overwrite_eco);
+
+    let mut output = match args.format {
+        Some(format) => Some(GameOutput::new(
+            Output::stdout(),
+            format,
+            JsonOptions {
+                fens: args.fens,
+                clocks: args.clocks,
+                evals: args.evals,
+            },
+            parse_columns(args.columns.as_str())?,
+        )?),
+        None => None,
+    };
+
+    let mut count: usize = 0;
+    loop {
+        match reader.read_next() {
+            ReadOutcome::Game(pgn) => {
+                count += 1;
+                if count < args.start {
+                    continue;
+                }
+
+                if args.print {
+                    println!("{}\n\n{}\n{}\n", pgn.id, pgn.tags_text, pgn.moves_text);
+                }
+
+                if let Some(output) = output.as_mut() {
+                    output.write(&pgn)?;
+                }
+
+                if args.end > 0 && count >= args.end {
+                    break;
+                }
+            }
+            ReadOutcome::Ended => break,
+            ReadOutcome::BadPgn(message) => {
+                if args.format.is_some() {
+                    eprintln!("{}", message);
+                } else {
+                    println!("{}", message);
+                }
+                continue;
+            }
+            ReadOutcome::Error(message) => return Err(Box::new(simple_error!(message))),
+        }
+    }
+
+    // The count goes to standard error when the games go to standard output.
+    match output {
+        Some(output) => {
+            output.finish()?;
+            if args.count {
+                eprintln!("{}", count);
+            }
+        }
+        None => {
+            if args.count {
+                println!("{}", count);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn dedup(args: &DedupArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let mut store = PostgresStore::open(args.postgres_uri.as_str())?;
+
+    let removed = store.dedup()?;
+    println!("{}", removed);
+
+    Ok(())
+}
+
+fn query(args: &QueryPgnArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let mut store = PostgresStore::open(args.postgres_uri.as_str())?;
+
+    let mut writer = Writer::new(std::io::stdout().lock());
+    store.for_each(&args.query.to_query(), |pgn| {
+        writer.write(&pgn).map_err(|e| e.into())
+    })?;
+
+    writer.flush()?;
+    Ok(())
+}
+
+fn export_pgn(args: &ExportPgnArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let mut store = PostgresStore::open(args.postgres_uri.as_str())?;
+
+    let out = match &args.output {
+        Some(output) => create_output(Path::new(output.as_str()))?,
+        None => Output::stdout(),
+    };
+    let mut output = GameOutput::new(
+        out,
+        args.format,
+        JsonOptions::default(),
+        parse_columns(args.columns.as_str())?,
+    )?;
+
+    let count = store.for_each(&args.query.to_query(), |mut pgn| {
+        if args.uci {
+            if let Err(e) = pgn.fill_uci_moves() {
+                eprintln!("{}: {}", pgn.id, e);
+            }
+        }
+        output.write(&pgn).map_err(|e| e.into())
+    })?;
+    output.finish()?;
+
+    if args.count {
+        eprintln!("{}", count);
+    }
+
+    Ok(())
+}
+
+fn build_book(args: &BuildBookArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let mut builder = BookBuilder::new().with_min_games(args.min_games);
+    if let Some(min_elo) = args.min_elo {
+        builder = builder.with_min_elo(min_elo);
+    }
+    if let Some(colour) = args.colour {
+        builder = builder.with_colour(colour);
+    }
+    if let Some(max_ply) = args.max_ply {
+        builder = builder.with_max_ply(max_ply);
+    }
+
+    let mut games: usize = 0;
+    let mut add = |pgn: &Pgn| match builder.add(pgn) {
+        Ok(added) => games += added as usize,
+        Err(e) => eprintln!("{}: {}", pgn.id, e),
+    };
+
+    match &args.pgn {
+        Some(pgnfile) => {
+            let mut reader = Reader::new(Path::new(pgnfile.as_str()))?;
+            loop {
+                match reader.read_next() {
+                    ReadOutcome::Game(pgn) => add(&pgn),
+                    ReadOutcome::Ended => break,
+                    ReadOutcome::BadPgn(message) => eprintln!("{}", message),
+                    ReadOutcome::Error(message) => return Err(Box::new(simple_error!(message))),
+                }
+            }
+        }
+        None => {
+            let mut store = PostgresStore::open(args.postgres_uri.as_str())?;
+            let query = Query::new().elo(args.min_elo, None);
+            store.for_each(&query, |pgn| {
+                add(&pgn);
+                Ok(())
+            })?;
+        }
+    }
+
+    let entries = builder.build();
+    create_book(Path::new(args.output.as_str()), &entries)?;
+
+    if args.count {
+        println!("{} games, {} entries", games, entries.len());
+    }
+
+    Ok(())
+}
+
+fn probe_book(args: &ProbeBookArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let book = Book::open(Path::new(args.book.as_str()))?;
+    let pos = match &args.fen {
+        Some(fen) => parse_fen(fen)?,
+        None => Chess::default(),
+    };
+
+    for (m, weight) in book.probe(&pos) {
+        println!("{} {} {}", san(&pos, m), uci(m), weight);
+    }
+
+    Ok(())
+}
+
+fn extract_positions(args: &ExtractPositionsArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let mut selector = PositionSelector::new().with_plies(args.ply.clone());
+    if let Some(every) = args.every {
+        selector = selector.with_every(every);
+    }
+    if let Some(after) = &args.after {
+        selector = selector.with_after(after)?;
+    }
+
+    let mut reader = Reader::new(Path::new(args.pgnfile.as_str()))?;
+    let out = match &args.output {
+        Some(output) => create_output(Path::new(output.as_str()))?,
+        None => Output::stdout(),
+    };
+    let mut writer = EpdWriter::new(out);
+
+    let mut seen: HashSet<u64> = HashSet::new();
+    let mut count: usize = 0;
+    loop {
+        match reader.read_next() {
+            ReadOutcome::Game(pgn) => {
+                let records = match selector.records(&pgn) {
+                    Ok(records) => records,
+                    Err(e) => {
+                        eprintln!("{}: {}", pgn.id, e);
+                        continue;
+                    }
+                };
+                for record in records {
+                    if args.unique && !seen.insert(polyglot_key(&record.position)) {
+                        continue;
+                    }
+                    writer.write(&record)?;
+                    count += 1;
+                }
+            }
+            ReadOutcome::Ended => break,
+            ReadOutcome::BadPgn(message) => eprintln!("{}", message),
+            ReadOutcome::Error(message) => return Err(Box::new(simple_error!(message))),
+        }
+    }
+    writer.into_inner().finish()?;
+
+    if args.count {
+        eprintln!("{}", count);
+    }
+
+    Ok(())
+}
+
+fn index_positions(args: &IndexPositionsArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let mut store = PostgresStore::open(args.postgres_uri.as_str())?;
+
+    let count = store.reindex_positions(&args.query.to_query())?;
+    println!("{}", count);
+
+    Ok(())
+}
+
+fn explore(args: &ExploreArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let pos = parse_position(args.position.as_str())?;
+
+    let mut query = Query::new()
+        .elo(args.min_elo, args.max_elo)
+        .date(args.date_from.clone(), args.date_to.clone());
+    if let Some(class) = args.time_control {
+        query = query.time_control_class(class);
+    }
+
+    let mut store = PostgresStore::open(args.postgres_uri.as_str())?;
+    let moves = store.explore(&pos, &query)?;
+
+    println!("{}", fen(&pos));
+    println!(
+        "{:<8} {:>8} {:>6} {:>6} {:>6} {:>6}  last game",
+        "move", "games", "white", "draw", "black", "elo"
+    );
+    for m in moves.iter() {
+        let last_game = m.last_game.as_ref().map_or(String::new(), |g| {
+            format!(
+                "{} - {}, {} {} ({})",
+                g.white, g.black, g.date, g.result, g.id
+            )
+        });
+        println!(
+            "{:<8} {:>8} {:>5.1}% {:>5.1}% {:>5.1}% {:>6}  {}",
+            m.san,
+            m.games,
+            m.white_percent(),
+            m.draw_percent(),
+            m.black_percent(),
+            m.average_elo
+                .map_or(String::new(), |elo| format!("{:.0}", elo)),
+            last_game
+        );
+    }
+
+    Ok(())
+}
+
+fn player(args: &PlayerArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let mut query = Query::new().date(args.date_from.clone(), args.date_to.clone());
+    if let Some(class) = args.time_control {
+        query = query.time_control_class(class);
+    }
+
+    let mut store = PostgresStore::open(args.postgres_uri.as_str())?;
+    let report = store
+        .player_report(args.player.as_str(), &query, args.openings)?
+        .ok_or_else(|| simple_error!("no games of {}", args.player))?;
+
+    let score = |score: &Score| {
+        format!(
+            "{:>6} {:>5} {:>5} {:>5} {:>5.1}%",
+            score.games,
+            score.wins,
+            score.draws,
+            score.losses,
+            score.percent()
+        )
+    };
+    let header = format!(
+        "{:>6} {:>5} {:>5} {:>5} {:>6}",
+        "games", "won", "drawn", "lost", "score"
+    );
+
+    println!("{}", report.name);
+    println!(
+        "average length: {}",
+        report
+            .average_plies
+            .map_or("?".to_string(), |plies| format!("{:.1} plies", plies))
+    );
+
+    println!();
+    println!("{:<10} {}", "colour", header);
+    println!("{:<10} {}", "white", score(&report.white));
+    println!("{:<10} {}", "black", score(&report.black));
+    println!("{:<10} {}", "total", score(&report.total()));
+
+    println!();
+    println!("{:<10} {} {:>6} {:>6}", "year", header, "opp", "perf");
+    for year in report.years.iter() {
+        println!(
+            "{:<10} {} {:>6} {:>6}",
+            year.year,
+            score(&year.score),
+            year.average_opponent
+                .map_or(String::new(), |elo| format!("{:.0}", elo)),
+            year.performance()
+                .map_or(String::new(), |elo| format!("{:.0}", elo)),
+        );
+    }
+
+    for (colour, openings) in [
+        ("white", &report.white_openings),
+        ("black", &report.black_openings),
+    ] {
+        println!();
+        println!("{:<10} {}  opening as {}", "eco", header, colour);
+        for opening in openings.iter() {
+            println!(
+                "{:<10} {}  {}",
+                opening.eco,
+                score(&opening.score),
+                opening.opening
+            );
+        }
+    }
+
+    println!();
+    println!("{:<10} {}", "opp elo", header);
+    for band in report.bands.iter() {
+        println!(
+            "{:<10} {}",
+            format!("{}-{}", band.min_elo, band.min_elo + RATING_BAND - 1),
+            score(&band.score)
+        );
+    }
+
+    Ok(())
+}
+
+fn rate(args: &RateArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let method = match args.method {
+        RatingMethod::Elo => Method::Elo { k: args.k },
+        RatingMethod::Glicko2 => Method::Glicko2 { tau: args.tau },
+    };
+
+    // Games are rated in the order they were played, all of them.
+    if args.query.sort.is_some() || args.query.limit.is_some() || args.query.offset.is_some() {
+        return Err(Box::new(simple_error!(
+            "rate does not take --sort, --limit or --offset"
+        )));
+    }
+
+    let mut store = PostgresStore::open(args.postgres_uri.as_str())?;
+    let games = store.rated_games(&args.query.to_query())?;
+
+    let mut pool = RatingPool::new(method, args.period).with_initial(args.initial);
+    pool.rate(games);
+    if let Some(name) = &args.pool {
+        store.replace_rating_history(name.as_str(), pool.history())?;
+    }
+
+    for entry in pool.ratings().iter() {
+        println!(
+            "{:>7.1} {:>6} {:>6} {:<10} {}",
+            entry.rating,
+            entry
+                .deviation
+                .map_or(String::new(), |rd| format!("{:.1}", rd)),
+            entry.games,
+            entry.period,
+            entry.player
+        );
+    }
+
+    Ok(())
+}
+
+fn classify(args: &ClassifyArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let classifier = match &args.eco_file {
+        Some(path) => Classifier::from_tsv(std::fs::read_to_string(path)?.as_str())?,
+        None => Classifier::default(),
+    };
+
+    let mut store = PostgresStore::open(args.postgres_uri.as_str())?;
+    let updated = store.update_each(&args.query.to_query(), |_, pgn| {
+        let before = pgn.tags_text.clone();
+        classifier.apply(pgn, args.overwrite);
+        Ok(pgn.tags_text != before)
+    })?;
+    println!("{}", updated);
+
+    Ok(())
+}
+
+fn search_pattern(args: &SearchPatternArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let pattern: Pattern = args.pattern.parse()?;
+
+    let print = |pgn: &Pgn, ply: usize| {
+        println!(
+            "{}\t{}\t{}\t{}\t{}",
+            pgn.id,
+            ply,
+            pgn.tags.get("White").map_or("?", |v| v.as_str()),
+            pgn.tags.get("Black").map_or("?", |v| v.as_str()),
+            pgn.tags.get("Date").map_or("?", |v| v.as_str()),
+        );
+    };
+
+    match &args.pgn {
+        Some(pgnfile) => {
+            let mut reader = Reader::new(Path::new(pgnfile.as_str()))?;
+            loop {
+                match reader.read_next() {
+                    ReadOutcome::Game(pgn) => match Replay::new(&pgn) {
+                        Ok(replay) => {
+                            if let Some(ply) = pattern.first_match(&replay) {
+                                print(&pgn, ply);
+                            }
+                        }
+                        Err(e) => eprintln!("{}: {}", pgn.id, e),
+                    },
+                    ReadOutcome::Ended => break,
+                    ReadOutcome::BadPgn(message) => eprintln!("{}", message),
+                    ReadOutcome::Error(message) => return Err(Box::new(simple_error!(message))),
+                }
+            }
+        }
+        None => {
+            let mut store = PostgresStore::open(args.postgres_uri.as_str())?;
+            store.search_pattern(&pattern, &args.query.to_query(), |pgn, ply| {
+                print(&pgn, ply);
+                Ok(())
+            })?;
+        }
+    }
+
+    Ok(())
+}
+
+fn search_moves(args: &SearchMovesArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let sequence: MoveSequence = args.moves.parse()?;
+
+    let print = |pgn: &Pgn, plies: &[usize]| {
+        let plies: Vec<String> = plies.iter().map(|ply| ply.to_string()).collect();
+        println!(
+            "{}\t{}\t{}\t{}\t{}",
+            pgn.id,
+            plies.join(","),
+            pgn.tags.get("White").map_or("?", |v| v.as_str()),
+            pgn.tags.get("Black").map_or("?", |v| v.as_str()),
+            pgn.tags.get("Date").map_or("?", |v| v.as_str()),
+        );
+    };
+
+    match &args.pgn {
+        Some(pgnfile) => {
+            let mut reader = Reader::new(Path::new(pgnfile.as_str()))?;
+            loop {
+                match reader.read_next() {
+                    ReadOutcome::Game(pgn) => {
+                        let plies = sequence.find(&pgn, args.min_ply, args.max_ply);
+                        if !plies.is_empty() {
+                            print(&pgn, &plies);
+                        }
+                    }
+                    ReadOutcome::Ended => break,
+                    ReadOutcome::BadPgn(message) => eprintln!("{}", message),
+                    ReadOutcome::Error(message) => return Err(Box::new(simple_error!(message))),
+                }
+            }
+        }
+        None => {
+            let mut store = PostgresStore::open(args.postgres_uri.as_str())?;
+            store.search_moves(
+                &sequence,
+                args.min_ply,
+                args.max_ply,
+                &args.query.to_query(),
+                |pgn, plies| {
+                    print(&pgn, &plies);
+                    Ok(())
+                },
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+fn terminations(args: &TerminationsArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let print = |pgn: &Pgn| match analyze_termination(pgn) {
+        Ok(analysis) => {
+            if !args.contradictions || analysis.contradiction.is_some() {
+                println!(
+                    "{}\t{}\t{}\t{}",
+                    pgn.id,
+                    pgn.tags.get("Result").map_or("*", |r| r.as_str()),
+                    analysis.termination,
+                    analysis.contradiction.unwrap_or_default()
+                );
+            }
+        }
+        Err(e) => eprintln!("{}: {}", pgn.id, e),
+    };
+
+    match &args.pgn {
+        Some(pgnfile) => {
+            let mut reader = Reader::new(Path::new(pgnfile.as_str()))?;
+            loop {
+                match reader.read_next() {
+                    ReadOutcome::Game(pgn) => print(&pgn),
+                    ReadOutcome::Ended => break,
+                    ReadOutcome::BadPgn(message) => eprintln!("{}", message),
+                    ReadOutcome::Error(message) => return Err(Box::new(simple_error!(message))),
+                }
+            }
+        }
+        None => {
+            let mut store = PostgresStore::open(args.postgres_uri.as_str())?;
+            store.for_each(&args.query.to_query(), |pgn| {
+                print(&pgn);
+                Ok(())
+            })?;
+        }
+    }
+
+    Ok(())
+}
+
+fn analyze(args: &AnalyzeArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let (mut engine, limit) = args.engine.launch()?;
+
+    // Returns false for games that cannot be replayed, and fails at the first
+    // engine error as the engine is unlikely to recover.
+    let mut annotate_game = |pgn: &mut Pgn| -> Result<bool, Box<dyn std::error::Error>> {
+        match engine.analyze(pgn, limit) {
+            Ok(evals) => {
+                let values: Vec<Option<String>> = evals
+                    .iter()
+                    .map(|eval| eval.map(|e| e.to_string()))
+                    .collect();
+                pgn.moves_text = annotate(pgn.moves_text.as_str(), "eval", &values);
+                Ok(true)
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::InvalidData => {
+                eprintln!("{}: {}", pgn.id, e);
+                Ok(false)
+            }
+            Err(e) => Err(Box::new(e)),
+        }
+    };
+
+    if args.update {
+        let mut store = PostgresStore::open(args.postgres_uri.as_str())?;
+        let updated = store.update_each(&args.query.to_query(), |_, pgn| annotate_game(pgn))?;
+        println!("{}", updated);
+    } else {
+        let out = match &args.output {
+            Some(output) => create_output(Path::new(output.as_str()))?,
+            None => Output::stdout(),
+        };
+        let mut writer = Writer::new(out);
+        let mut write = |mut pgn: Pgn| -> Result<(), Box<dyn std::error::Error>> {
+            annotate_game(&mut pgn)?;
+            writer.write(&pgn)?;
+            Ok(())
+        };
+
+        match &args.pgn {
+            Some(pgnfile) => {
+                let mut reader = Reader::new(Path::new(pgnfile.as_str()))?;
+                loop {
+                    match reader.read_next() {
+                        ReadOutcome::Game(pgn) => write(pgn)?,
+                        ReadOutcome::Ended => break,
+                        ReadOutcome::BadPgn(message) => eprintln!("{}", message),
+                        ReadOutcome::Error(message) => {
+                            return Err(Box::new(simple_error!(message)))
+                        }
+                    }
+                }
+            }
+            None => {
+                let mut store = PostgresStore::open(args.postgres_uri.as_str())?;
+                store.for_each(&args.query.to_query(), write)?;
+            }
+        }
+        writer.finish()?;
+    }
+
+    engine.quit()?;
+    Ok(())
+}
+
+fn judge(args: &JudgeArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let thresholds = Thresholds {
+        inaccuracy: args.inaccuracy,
+        mistake: args.mistake,
+        blunder: args.blunder,
+    };
+
+    // Games without evaluations are skipped.
+    let judge_game = |pgn: &mut Pgn| -> Option<GameAnalysis> {
+        let evals = evals_from_comments(pgn);
+        match analyze_game(pgn, &evals, &thresholds) {
+            Ok(analysis) if !analysis.moves.is_empty() => {
+                let nags = analysis.nags(evals.len());
+                pgn.moves_text = set_nags(pgn.moves_text.as_str(), &nags);
+                Some(analysis)
+            }
+            Ok(_) => None,
+            Err(e) => {
+                eprintln!("{}: {}", pgn.id, e);
+                None
+            }
+        }
+    };
+
+    if args.update {
+        let mut store = PostgresStore::open(args.postgres_uri.as_str())?;
+        let mut analysed: usize = 0;
+        let updated = store.update_each(&args.query.to_query(), |store, pgn| {
+            let before = pgn.moves_text.clone();
+            match judge_game(pgn) {
+                Some(analysis) => {
+                    store.upsert_game_analysis(pgn.id.as_str(), &analysis)?;
+                    analysed += 1;
+                    Ok(pgn.moves_text != before)
+                }
+                None => Ok(false),
+            }
+        })?;
+        println!("{}\t{}", analysed, updated);
+        return Ok(());
+    }
+
+    let stdout = std::io::stdout();
+    let mut writer = Writer::new(stdout.lock());
+    let mut print = |mut pgn: Pgn| -> Result<(), Box<dyn std::error::Error>> {
+        let analysis = match judge_game(&mut pgn) {
+            Some(analysis) => analysis,
+            None => return Ok(()),
+        };
+        if args.nags {
+            writer.write(&pgn)?;
+        } else {
+            let totals = |player: &PlayerAnalysis| {
+                format!(
+                    "{:.1}\t{:.1}\t{}\t{}\t{}",
+                    player.acpl.unwrap_or_default(),
+                    player.accuracy.unwrap_or_default(),
+                    player.inaccuracies,
+                    player.mistakes,
+                    player.blunders
+                )
+            };
+            println!(
+                "{}\t{}\t{}",
+                pgn.id,
+                totals(&analysis.white),
+                totals(&analysis.black)
+            );
+        }
+        Ok(())
+    };
+
+    match &args.pgn {
+        Some(pgnfile) => {
+            let mut reader = Reader::new(Path::new(pgnfile.as_str()))?;
+            loop {
+                match reader.read_next() {
+                    ReadOutcome::Game(pgn) => print(pgn)?,
+                    ReadOutcome::Ended => break,
+                    ReadOutcome::BadPgn(message) => eprintln!("{}", message),
+                    ReadOutcome::Error(message) => return Err(Box::new(simple_error!(message))),
+                }
+            }
+        }
+        None => {
+            let mut store = PostgresStore::open(args.postgres_uri.as_str())?;
+            store.for_each(&args.query.to_query(), print)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn extract_puzzles(args: &ExtractPuzzlesArgs) -> Result<(), Box<dyn std::error::Error>> {
+    if !matches!(args.format, Format::Jsonl | Format::Pgn) {
+        return Err(Box::new(simple_error!(
+            "puzzles are written as jsonl or pgn"
+        )));
+    }
+
+    let (mut engine, limit) = args.engine.launch()?;
+    let finder = PuzzleFinder::new(limit)
+        .with_min_swing(args.min_swing)
+        .with_min_advantage(args.min_advantage)
+        .with_unique_margin(args.unique_margin)
+        .with_max_moves(args.max_moves);
+
+    let mut out = match &args.output {
+        Some(output) => create_output(Path::new(output.as_str()))?,
+        None => Output::stdout(),
+    };
+
+    // Games are analysed by the engine unless they have `[%eval]` comments.
+    let mut extract = |pgn: Pgn| -> Result<(), Box<dyn std::error::Error>> {
+        let mut evals = evals_from_comments(&pgn);
+        if evals.iter().all(|eval| eval.is_none()) {
+            engine.set_option("MultiPV", "1")?;
+            evals = match engine.analyze(&pgn, limit) {
+                Ok(evals) => evals,
+                Err(e) if e.kind() == std::io::ErrorKind::InvalidData => {
+                    eprintln!("{}: {}", pgn.id, e);
+                    return Ok(());
+                }
+                Err(e) => return Err(Box::new(e)),
+            };
+        }
+
+        let puzzles = match finder.find(&mut engine, &pgn, &evals) {
+            Ok(puzzles) => puzzles,
+            Err(e) if e.kind() == std::io::ErrorKind::InvalidData => {
+                eprintln!("{}: {}", pgn.id, e);
+                return Ok(());
+            }
+            Err(e) => return Err(Box::new(e)),
+        };
+        for puzzle in puzzles.iter() {
+            match args.format {
+                Format::Pgn => out.write_all(Writer::format(&puzzle.to_pgn()?).as_bytes())?,
+                _ => writeln!(out, "{}", serde_json::to_string(puzzle)?)?,
+            }
+        }
+        Ok(())
+    };
+
+    match &args.pgn {
+        Some(pgnfile) => {
+            let mut reader = Reader::new(Path::new(pgnfile.as_str()))?;
+            loop {
+                match reader.read_next() {
+                    ReadOutcome::Game(pgn) => extract(pgn)?,
+                    ReadOutcome::Ended => break,
+                    ReadOutcome::BadPgn(message) => eprintln!("{}", message),
+                    ReadOutcome::Error(message) => return Err(Box::new(simple_error!(message))),
+                }
+            }
+        }
+        None => {
+            let mut store = PostgresStore::open(args.postgres_uri.as_str())?;
+            store.for_each(&args.query.to_query(), extract)?;
+        }
+    }
+    out.finish()?;
+
+    engine.quit()?;
+    Ok(())
+}
+
+/// Adds the classifier to `reader` if asked to.
+fn with_classifier(reader: Reader, classify: bool, overwrite_eco: bool) -> Reader {
+    if classify {
+        reader.with_classifier(Classifier::default(), overwrite_eco)
+    } else {
+        reader
+    }
+}
+
+/// Output of `convert`.
+enum ConvertOutput {
+    Games(GameOutput),
+    #[cfg(feature = "parquet")]
+    Parquet(Box<ParquetWriter<std::fs::File>>),
+}
+
+impl ConvertOutput {
+    #[cfg(feature = "parquet")]
+    fn parquet(path: &Path, row_group_size: usize) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(ConvertOutput::Parquet(Box::new(ParquetWriter::create(
+            path,
+            row_group_size,
+        )?)))
+    }
+
+    #[cfg(not(feature = "parquet"))]
+    fn parquet(_path: &Path, _row_group_size: usize) -> Result<Self, Box<dyn std::error::Error>> {
+        Err(Box::new(simple_error!(
+            "parquet output requires the parquet feature"
+        )))
+    }
+
+    fn write(&mut self, pgn: &Pgn) -> Result<(), Box<dyn std::error::Error>> {
+        match self {
+            ConvertOutput::Games(output) => output.write(pgn)?,
+            #[cfg(feature = "parquet")]
+            ConvertOutput::Parquet(writer) => writer.write(pgn)?,
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<(), Box<dyn std::error::Error>> {
+        match self {
+            ConvertOutput::Games(output) => output.finish()?,
+            #[cfg(feature = "parquet")]
+            ConvertOutput::Parquet(writer) => writer.close()?,
+        }
+        Ok(())
+    }
+}
+
+fn convert(args: &ConvertArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let reader = Reader::new(Path::new(args.pgnfile.as_str()))?.with_uci_moves(args.uci);
+    let mut reader = with_classifier(reader, args.classify, args.overwrite_eco);
+    let output = Path::new(args.output.as_str());
+
+    // Options are checked before the output file is created.
+    let mut output = if args.to == "parquet" {
+        ConvertOutput::parquet(output, args.row_group_size)?
+    } else {
+        let format: Format = args.to.parse()?;
+        let columns = parse_columns(args.columns.as_str())?;
+        ConvertOutput::Games(GameOutput::new(
+            create_output(output)?,
+            format,
+            JsonOptions::default(),
+            columns,
+        )?)
+    };
+
+    let mut count: usize = 0;
+    loop {
+        match reader.read_next() {
+            ReadOutcome::Game(pgn) => {
+                output.write(&pgn)?;
+                count += 1;
+            }
+            ReadOutcome::Ended => break,
+            ReadOutcome::BadPgn(message) => eprintln!("{}", message),
+            ReadOutcome::Error(message) => return Err(Box::new(simple_error!(message))),
+        }
+    }
+    output.finish()?;
+
+    if args.count {
+        println!("{}", count);
+    }
+
+    Ok(())
+}
+
+fn split(args: &SplitArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let mut reader = Reader::new(Path::new(args.pgnfile.as_str()))?;
+    let mut splitter = Splitter::new(Path::new(args.output.as_str()), args.by.clone());
+
+    loop {
+        match reader.read_next() {
+            ReadOutcome::Game(pgn) => splitter.write(&pgn)?,
+            ReadOutcome::Ended => break,
+            ReadOutcome::BadPgn(message) => eprintln!("{}", message),
+            ReadOutcome::Error(message) => return Err(Box::new(simple_error!(message))),
+        }
+    }
+
+    let files = splitter.close()?;
+    if args.count {
+        for (path, games) in files.iter() {
+            println!("{}\t{}", path.display(), games);
+        }
+    }
+
+    Ok(())
+}
+
+fn merge(args: &MergeArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let out = match &args.output {
+        Some(output) => create_output(Path::new(output.as_str()))?,
+        None => Output::stdout(),
+    };
+    let mut writer = Writer::new(out);
+    let buffered = args.dedup || args.sort_date;
+
+    let mut games: Vec<Pgn> = Vec::new();
+    let mut written: usize = 0;
+    for pgnfile in args.pgnfiles.iter() {
+        let mut reader = Reader::new(Path::new(pgnfile.as_str()))?;
+        loop {
+            match reader.read_next() {
+                ReadOutcome::Game(pgn) if buffered => games.push(pgn),
+                ReadOutcome::Game(pgn) => {
+                    writer.write(&pgn)?;
+                    written += 1;
+                }
+                ReadOutcome::Ended => break,
+                ReadOutcome::BadPgn(message) => eprintln!("{}", message),
+                ReadOutcome::Error(message) => return Err(Box::new(simple_error!(message))),
+            }
+        }
+    }
+
+    let removed = if args.dedup {
+        merge::dedup(&mut games)
+    } else {
+        0
+    };
+    if args.sort_date {
+        merge::sort_by_date(&mut games);
+    }
+    for pgn in games.iter() {
+        writer.write(pgn)?;
+        written += 1;
+    }
+    writer.finish()?;
+
+    if args.count {
+        eprintln!("{}\t{}", written, removed);
+    }
+
+    Ok(())
+}
+
+fn filter(args: &FilterArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let filter: Filter = args.expression.parse()?;
+    let mut reader = Reader::new(Path::new(args.pgnfile.as_str()))?;
+
+    let out = match &args.output {
+        Some(output) => create_output(Path::new(output.as_str()))?,
+        None => Output::stdout(),
+    };
+    let mut writer = Writer::new(out);
+
+    let mut read: usize = 0;
+    let mut written: usize = 0;
+    loop {
+        match reader.read_next() {
+            ReadOutcome::Game(pgn) => {
+                read += 1;
+                if filter.matches(&pgn) {
+                    writer.write(&pgn)?;
+                    written += 1;
+                }
+            }
+            ReadOutcome::Ended => break,
+            ReadOutcome::BadPgn(message) => eprintln!("{}", message),
+            ReadOutcome::Error(message) => return Err(Box::new(simple_error!(message))),
+        }
+    }
+    writer.finish()?;
+
+    if args.count {
+        eprintln!("{}\t{}", read, written);
+    }
+
+    Ok(())
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -139,5 +1923,26 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     match &app.command {
         Commands::StorePgn(args) => store_pgn(args),
         Commands::ReadPgn(args) => read_pgn(args),
+        Commands::Dedup(args) => dedup(args),
+        Commands::Query(args) => query(args),
+        Commands::Convert(args) => convert(args),
+        Commands::Split(args) => split(args),
+        Commands::Merge(args) => merge(args),
+        Commands::Filter(args) => filter(args),
+        Commands::BuildBook(args) => build_book(args),
+        Commands::ProbeBook(args) => probe_book(args),
+        Commands::ExtractPositions(args) => extract_positions(args),
+        Commands::IndexPositions(args) => index_positions(args),
+        Commands::Explore(args) => explore(args),
+        Commands::Classify(args) => classify(args),
+        Commands::SearchPattern(args) => search_pattern(args),
+        Commands::SearchMoves(args) => search_moves(args),
+        Commands::Terminations(args) => terminations(args),
+        Commands::Player(args) => player(args),
+        Commands::Rate(args) => rate(args),
+        Commands::Analyze(args) => analyze(args),
+        Commands::Judge(args) => judge(args),
+        Commands::ExtractPuzzles(args) => extract_puzzles(args),
+        Commands::ExportPgn(args) => export_pgn(args),
     }
 }
@@ -0,0 +1,72 @@
+use std::io::Write;
+use std::str::FromStr;
+
+use simple_error::{simple_error, SimpleError};
+
+use mudfish::pgn::{to_json_line, Column, CsvWriter, JsonOptions, Output, Pgn, Writer};
+
+#[derive(Debug, Clone, Copy)]
+pub enum Format {
+    Pgn,
+    Jsonl,
+    Csv,
+    Tsv,
+}
+
+impl FromStr for Format {
+    type Err = SimpleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pgn" => Ok(Format::Pgn),
+            "jsonl" => Ok(Format::Jsonl),
+            "csv" => Ok(Format::Csv),
+            "tsv" => Ok(Format::Tsv),
+            _ => Err(simple_error!("unknown format: {}", s)),
+        }
+    }
+}
+
+/// Writes games in one of the output formats.
+pub enum GameOutput {
+    Pgn(Writer<Output>),
+    Jsonl(Output, JsonOptions),
+    Csv(CsvWriter<Output>),
+}
+
+impl GameOutput {
+    /// Header-table formats start with the row of column names.
+    pub fn new(
+        out: Output,
+        format: Format,
+        json_options: JsonOptions,
+        columns: Vec<Column>,
+    ) -> std::io::Result<Self> {
+        Ok(match format {
+            Format::Pgn => GameOutput::Pgn(Writer::new(out)),
+            Format::Jsonl => GameOutput::Jsonl(out, json_options),
+            Format::Csv | Format::Tsv => {
+                let delimiter = if let Format::Csv = format { ',' } else { '\t' };
+                let mut writer = CsvWriter::new(out, columns, delimiter);
+                writer.write_header()?;
+                GameOutput::Csv(writer)
+            }
+        })
+    }
+
+    pub fn write(&mut self, pgn: &Pgn) -> std::io::Result<()> {
+        match self {
+            GameOutput::Pgn(writer) => writer.write(pgn),
+            GameOutput::Jsonl(out, options) => writeln!(out, "{}", to_json_line(pgn, *options)),
+            GameOutput::Csv(writer) => writer.write(pgn),
+        }
+    }
+
+    pub fn finish(self) -> std::io::Result<()> {
+        match self {
+            GameOutput::Pgn(writer) => writer.finish(),
+            GameOutput::Jsonl(out, _) => out.finish(),
+            GameOutput::Csv(writer) => writer.into_inner().finish(),
+        }
+    }
+}
@@ -0,0 +1,184 @@
+use std::fmt;
+use std::io::Write;
+
+use regex::Regex;
+use shakmaty::fen::Epd;
+use shakmaty::{Chess, EnPassantMode};
+use simple_error::SimpleError;
+
+use super::{san, Replay};
+use crate::pgn::Pgn;
+
+/// A position with its operations, e.g. `bm Nf3; id "x";`.
+pub struct EpdRecord {
+    pub position: Chess,
+    pub operations: Vec<(String, String)>,
+}
+
+impl EpdRecord {
+    pub fn new(position: Chess) -> Self {
+        Self {
+            position,
+            operations: Vec::new(),
+        }
+    }
+
+    /// Adds an operation whose operand is written as is, e.g. a move.
+    pub fn with_operation(mut self, opcode: &str, operand: impl Into<String>) -> Self {
+        self.operations.push((opcode.to_string(), operand.into()));
+        self
+    }
+
+    /// Adds an operation whose operand is written as a quoted string.
+    pub fn with_string(self, opcode: &str, operand: &str) -> Self {
+        let quoted = format!("\"{}\"", operand.replace('\\', "\\\\").replace('"', "\\\""));
+        self.with_operation(opcode, quoted)
+    }
+}
+
+impl fmt::Display for EpdRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            Epd::from_position(&self.position, EnPassantMode::Legal)
+        )?;
+        for (opcode, operand) in self.operations.iter() {
+            write!(f, " {} {};", opcode, operand)?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes one EPD record per line.
+pub struct EpdWriter<W: Write> {
+    out: W,
+}
+
+impl<W: Write> EpdWriter<W> {
+    pub fn new(out: W) -> Self {
+        Self { out }
+    }
+
+    pub fn write(&mut self, record: &EpdRecord) -> std::io::Result<()> {
+        writeln!(self.out, "{}", record)
+    }
+
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.out.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+/// Picks positions of a game: at given plies, every `n`th ply, or after
+/// moves matching a pattern. Ply `n` is the position after `n` moves.
+#[derive(Debug, Default)]
+pub struct PositionSelector {
+    plies: Vec<usize>,
+    every: Option<usize>,
+    after: Option<Regex>,
+}
+
+impl PositionSelector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_plies(mut self, plies: Vec<usize>) -> Self {
+        self.plies = plies;
+        self
+    }
+
+    pub fn with_every(mut self, every: usize) -> Self {
+        self.every = Some(every);
+        self
+    }
+
+    /// Selects the positions after SAN moves matching `pattern` as a whole,
+    /// e.g. `Qx.*` or `O-O-O`.
+    pub fn with_after(mut self, pattern: &str) -> Result<Self, SimpleError> {
+        let re = Regex::new(format!("^(?:{})$", pattern).as_str())
+            .map_err(|e| SimpleError::new(format!("bad move pattern ({}): {}", pattern, e)))?;
+        self.after = Some(re);
+        Ok(self)
+    }
+
+    pub fn is_selected(&self, ply: usize, moves: &[String]) -> bool {
+        if self.plies.contains(&ply) {
+            return true;
+        }
+        if self
+            .every
+            .is_some_and(|every| every > 0 && ply > 0 && ply.is_multiple_of(every))
+        {
+            return true;
+        }
+        match &self.after {
+            Some(re) if ply > 0 => re.is_match(moves[ply - 1].as_str()),
+            _ => false,
+        }
+    }
+
+    /// Selected positions of `pgn`, identified by `id "{game id}:{ply}"` and
+    /// `c0 "{game id}"`, with the move played next as `bm`.
+    pub fn records(&self, pgn: &Pgn) -> Result<Vec<EpdRecord>, SimpleError> {
+        let replay = Replay::new(pgn)?;
+
+        let mut records: Vec<EpdRecord> = Vec::new();
+        for (ply, pos) in replay.positions.iter().enumerate() {
+            if !self.is_selected(ply, &pgn.moves) {
+                continue;
+            }
+
+            let mut record = EpdRecord::new(pos.clone());
+            if let Some(m) = replay.moves.get(ply) {
+                record = record.with_operation("bm", san(pos, *m));
+            }
+            records.push(
+                record
+                    .with_string("id", format!("{}:{}", pgn.id, ply).as_str())
+                    .with_string("c0", pgn.id.as_str()),
+            );
+        }
+
+        Ok(records)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn select_positions() {
+        let mut pgn = Pgn::new("game", 3);
+        pgn.moves = ["e4", "e5", "Nf3", "Nc6", "Bb5", "a6", "Bxc6"]
+            .iter()
+            .map(|m| m.to_string())
+            .collect();
+
+        let selector = PositionSelector::new()
+            .with_plies(vec![0])
+            .with_every(4)
+            .with_after("Bx.*")
+            .unwrap();
+        let lines: Vec<String> = selector
+            .records(&pgn)
+            .unwrap()
+            .iter()
+            .map(|r| r.to_string())
+            .collect();
+
+        assert_eq!(
+            lines,
+            vec![
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - bm e4; id \"game.3:0\"; c0 \"game.3\";",
+                "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - bm Bb5; id \"game.3:4\"; c0 \"game.3\";",
+                "r1bqkbnr/1ppp1ppp/p1B5/4p3/4P3/5N2/PPPP1PPP/RNBQK2R b KQkq - id \"game.3:7\"; c0 \"game.3\";",
+            ]
+        );
+    }
+}
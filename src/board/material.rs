@@ -0,0 +1,195 @@
+use std::fmt;
+use std::str::FromStr;
+
+use shakmaty::{Board, Color, Piece, Position, Role};
+use simple_error::SimpleError;
+
+use super::Replay;
+
+/// Roles in the order they appear in a material signature.
+const ROLES: [Role; 6] = [
+    Role::King,
+    Role::Queen,
+    Role::Rook,
+    Role::Bishop,
+    Role::Knight,
+    Role::Pawn,
+];
+
+/// Positions with at most this many queens, rooks, bishops and knights on
+/// the board are endgames.
+const MAX_ENDGAME_PIECES: usize = 6;
+
+/// Plies the material of an endgame position must stay the same for, so
+/// that positions in the middle of an exchange are skipped.
+const STABLE_PLIES: usize = 2;
+
+/// Kind of endgame by the pieces left besides kings and pawns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EndgameCategory {
+    /// Kings and pawns only.
+    Pawn,
+    /// Bishops and knights.
+    Minor,
+    Rook,
+    Queen,
+    /// Pieces of several kinds, e.g. rook against bishop.
+    Mixed,
+}
+
+impl EndgameCategory {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EndgameCategory::Pawn => "pawn",
+            EndgameCategory::Minor => "minor",
+            EndgameCategory::Rook => "rook",
+            EndgameCategory::Queen => "queen",
+            EndgameCategory::Mixed => "mixed",
+        }
+    }
+
+    pub fn of(board: &Board) -> Self {
+        let minors = board.bishops() | board.knights();
+        let pieces = board.occupied() & !board.kings() & !board.pawns();
+        if pieces.is_empty() {
+            EndgameCategory::Pawn
+        } else if pieces == minors {
+            EndgameCategory::Minor
+        } else if pieces == board.rooks() {
+            EndgameCategory::Rook
+        } else if pieces == board.queens() {
+            EndgameCategory::Queen
+        } else {
+            EndgameCategory::Mixed
+        }
+    }
+}
+
+impl fmt::Display for EndgameCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for EndgameCategory {
+    type Err = SimpleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pawn" => Ok(EndgameCategory::Pawn),
+            "minor" => Ok(EndgameCategory::Minor),
+            "rook" => Ok(EndgameCategory::Rook),
+            "queen" => Ok(EndgameCategory::Queen),
+            "mixed" => Ok(EndgameCategory::Mixed),
+            _ => Err(SimpleError::new(format!("unknown endgame category: {}", s))),
+        }
+    }
+}
+
+/// Pieces of each side, White first, e.g. `KRPPvKR`.
+pub fn material_signature(board: &Board) -> String {
+    let side = |color: Color| -> String {
+        ROLES
+            .iter()
+            .map(|role| {
+                let count = board.by_piece(Piece { color, role: *role }).count();
+                role.upper_char().to_string().repeat(count)
+            })
+            .collect()
+    };
+
+    format!("{}v{}", side(Color::White), side(Color::Black))
+}
+
+/// Whether few enough pieces are left for `board` to be an endgame.
+pub fn is_endgame(board: &Board) -> bool {
+    let pieces = board.occupied() & !board.kings() & !board.pawns();
+    pieces.count() <= MAX_ENDGAME_PIECES
+}
+
+/// Where a game entered an endgame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Endgame {
+    /// Number of moves played before the first endgame position.
+    pub ply: usize,
+    pub signature: String,
+    pub category: EndgameCategory,
+}
+
+impl Endgame {
+    /// First endgame position of the game whose material does not change
+    /// in the next two plies, or in the plies left if the game ends sooner.
+    pub fn find(replay: &Replay) -> Option<Self> {
+        let positions = &replay.positions;
+        let material: Vec<_> = positions.iter().map(|pos| pos.board().material()).collect();
+
+        (0..positions.len())
+            .find(|&ply| {
+                let last = (ply + STABLE_PLIES).min(positions.len() - 1);
+                is_endgame(positions[ply].board())
+                    && material[ply + 1..=last].iter().all(|m| *m == material[ply])
+            })
+            .map(|ply| {
+                let board = positions[ply].board();
+                Self {
+                    ply,
+                    signature: material_signature(board),
+                    category: EndgameCategory::of(board),
+                }
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::parse_fen;
+    use crate::pgn::Pgn;
+
+    #[test]
+    fn signatures() {
+        let pos = parse_fen("8/5k2/8/3r4/8/2R5/4PK2/8 w - - 0 1").unwrap();
+        assert_eq!(material_signature(pos.board()), "KRPvKR");
+        assert_eq!(EndgameCategory::of(pos.board()), EndgameCategory::Rook);
+        assert!(is_endgame(pos.board()));
+
+        let pos = parse_fen("8/5k2/8/3n4/8/2B5/4PK2/8 w - - 0 1").unwrap();
+        assert_eq!(EndgameCategory::of(pos.board()), EndgameCategory::Minor);
+        let pos = parse_fen("8/5k2/8/3n4/8/2R5/4PK2/8 w - - 0 1").unwrap();
+        assert_eq!(EndgameCategory::of(pos.board()), EndgameCategory::Mixed);
+
+        assert!(!is_endgame(shakmaty::Chess::default().board()));
+    }
+
+    #[test]
+    fn find_endgame() {
+        let mut pgn = Pgn::new("game", 1);
+        pgn.tags.insert(
+            "FEN".to_string(),
+            "4k3/8/8/8/8/8/4P3/RN2K2R w - - 0 1".to_string(),
+        );
+        pgn.moves = vec!["Rh8+".to_string(), "Kd7".to_string()];
+        let replay = Replay::new(&pgn).unwrap();
+        let endgame = Endgame::find(&replay).unwrap();
+        assert_eq!(endgame.ply, 0);
+        assert_eq!(endgame.signature, "KRRNPvK");
+        assert_eq!(endgame.category, EndgameCategory::Mixed);
+
+        // Positions between the two captures are skipped.
+        pgn.tags.insert(
+            "FEN".to_string(),
+            "r3k2r/8/8/8/8/8/4P3/R3K2R w - - 0 1".to_string(),
+        );
+        pgn.moves = ["Rxa8+", "Ke7", "R1xh8", "Kd6", "Rh6+"]
+            .iter()
+            .map(|m| m.to_string())
+            .collect();
+        let endgame = Endgame::find(&Replay::new(&pgn).unwrap()).unwrap();
+        assert_eq!(endgame.ply, 3);
+        assert_eq!(endgame.signature, "KRRPvK");
+
+        pgn.tags.clear();
+        pgn.moves = vec!["e4".to_string()];
+        assert_eq!(Endgame::find(&Replay::new(&pgn).unwrap()), None);
+    }
+}
@@ -0,0 +1,237 @@
+use shakmaty::fen::Fen;
+use shakmaty::san::{San, SanPlus};
+use shakmaty::uci::UciMove;
+use shakmaty::zobrist::{Zobrist64, ZobristHash};
+use shakmaty::{CastlingMode, Chess, EnPassantMode, Move, Position};
+use simple_error::SimpleError;
+
+use crate::pgn::movetext::{tokenize, Token};
+use crate::pgn::Pgn;
+
+pub mod epd;
+pub mod material;
+pub mod pattern;
+pub mod termination;
+
+/// Positions reached in a game, replayed on a board.
+pub struct Replay {
+    /// Starting position followed by the position after each ply.
+    pub positions: Vec<Chess>,
+    /// Move played at each ply.
+    pub moves: Vec<Move>,
+}
+
+impl Replay {
+    /// Plays the moves of `pgn` from its starting position, which is given by
+    /// the `FEN` tag if present.
+    pub fn new(pgn: &Pgn) -> Result<Self, SimpleError> {
+        let mut pos = start_position(pgn)?;
+
+        let mut positions: Vec<Chess> = Vec::with_capacity(pgn.moves.len() + 1);
+        let mut moves: Vec<Move> = Vec::with_capacity(pgn.moves.len());
+        positions.push(pos.clone());
+
+        for (ply, san) in pgn.moves.iter().enumerate() {
+            let m = parse_san(&pos, san)
+                .map_err(|e| SimpleError::new(format!("ply {}: {}: {}", ply + 1, san, e)))?;
+            pos.play_unchecked(m);
+            moves.push(m);
+            positions.push(pos.clone());
+        }
+
+        Ok(Self { positions, moves })
+    }
+
+    pub fn last(&self) -> &Chess {
+        self.positions.last().unwrap()
+    }
+
+    /// FEN of the position after each ply.
+    pub fn fens(&self) -> Vec<String> {
+        self.positions.iter().skip(1).map(fen).collect()
+    }
+
+    /// Moves in UCI notation.
+    pub fn uci_moves(&self) -> Vec<String> {
+        self.moves.iter().map(|m| uci(*m)).collect()
+    }
+}
+
+/// Starting position of `pgn`: the `FEN` tag if present, the standard
+/// position otherwise.
+pub fn start_position(pgn: &Pgn) -> Result<Chess, SimpleError> {
+    match pgn.tags.get("FEN") {
+        Some(text) => parse_fen(text),
+        None => Ok(Chess::default()),
+    }
+}
+
+pub fn parse_fen(text: &str) -> Result<Chess, SimpleError> {
+    let parsed: Fen = text
+        .parse()
+        .map_err(|e| SimpleError::new(format!("bad FEN ({}): {}", text, e)))?;
+
+    parsed
+        .into_position(CastlingMode::Standard)
+        .map_err(|e| SimpleError::new(format!("bad FEN ({}): {}", text, e)))
+}
+
+pub fn fen(pos: &Chess) -> String {
+    Fen::from_position(pos, EnPassantMode::Legal).to_string()
+}
+
+/// Position given as a FEN, or as SAN moves played from the standard
+/// position, e.g. `1. e4 c5 2. Nf3`.
+pub fn parse_position(text: &str) -> Result<Chess, SimpleError> {
+    if text.contains('/') {
+        return parse_fen(text.trim());
+    }
+
+    let mut pos = Chess::default();
+    for token in tokenize(text) {
+        if let Token::San(san) = token {
+            let m = parse_san(&pos, san.as_str())?;
+            pos.play_unchecked(m);
+        }
+    }
+
+    Ok(pos)
+}
+
+/// Zobrist hash of `pos` with the Polyglot keys, as used by opening books.
+pub fn polyglot_key(pos: &Chess) -> u64 {
+    let hash: Zobrist64 = pos.zobrist_hash(EnPassantMode::PseudoLegal);
+    hash.0
+}
+
+/// Legal move of `pos` written as `san`.
+pub fn parse_san(pos: &Chess, san: &str) -> Result<Move, SimpleError> {
+    let parsed: San = san
+        .parse()
+        .map_err(|_| SimpleError::new(format!("bad SAN: {}", san)))?;
+
+    parsed
+        .to_move(pos)
+        .map_err(|e| SimpleError::new(format!("illegal move {}: {}", san, e)))
+}
+
+/// Legal move of `pos` written as `uci`, e.g. `e2e4` or `e7e8q`.
+pub fn parse_uci(pos: &Chess, uci: &str) -> Result<Move, SimpleError> {
+    let parsed: UciMove = uci
+        .parse()
+        .map_err(|_| SimpleError::new(format!("bad UCI move: {}", uci)))?;
+
+    parsed
+        .to_move(pos)
+        .map_err(|e| SimpleError::new(format!("illegal move {}: {}", uci, e)))
+}
+
+/// UCI notation of `m`; castling is written as the king move, e.g. `e1g1`.
+pub fn uci(m: Move) -> String {
+    m.to_uci(CastlingMode::Standard).to_string()
+}
+
+/// SAN of `m` in `pos`, with check and mate suffixes.
+pub fn san(pos: &Chess, m: Move) -> String {
+    SanPlus::from_move(pos.clone(), m).to_string()
+}
+
+pub fn san_to_uci(pos: &Chess, san: &str) -> Result<String, SimpleError> {
+    parse_san(pos, san).map(uci)
+}
+
+pub fn uci_to_san(pos: &Chess, uci: &str) -> Result<String, SimpleError> {
+    parse_uci(pos, uci).map(|m| san(pos, m))
+}
+
+/// SAN of UCI moves played from `pos`.
+pub fn uci_moves_to_san(pos: &Chess, uci_moves: &[String]) -> Result<Vec<String>, SimpleError> {
+    let mut pos = pos.clone();
+    let mut sans: Vec<String> = Vec::with_capacity(uci_moves.len());
+
+    for (ply, uci) in uci_moves.iter().enumerate() {
+        let m = parse_uci(&pos, uci)
+            .map_err(|e| SimpleError::new(format!("ply {}: {}", ply + 1, e)))?;
+        sans.push(san(&pos, m));
+        pos.play_unchecked(m);
+    }
+
+    Ok(sans)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replay_moves() {
+        let mut pgn = Pgn::new("test", 1);
+        pgn.moves = ["e4", "e5", "Nf3", "Nc6"]
+            .iter()
+            .map(|m| m.to_string())
+            .collect();
+
+        let replay = Replay::new(&pgn).unwrap();
+        assert_eq!(replay.positions.len(), 5);
+        assert_eq!(
+            replay.fens()[3],
+            "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3"
+        );
+
+        pgn.moves.push("Ke3".to_string());
+        assert!(Replay::new(&pgn).is_err());
+    }
+
+    #[test]
+    fn positions() {
+        let after_moves = parse_position("1. e4 c5 2. Nf3").unwrap();
+        assert_eq!(
+            fen(&after_moves),
+            "rnbqkbnr/pp1ppppp/8/2p5/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2"
+        );
+        assert_eq!(
+            fen(&parse_position(fen(&after_moves).as_str()).unwrap()),
+            fen(&after_moves)
+        );
+        assert!(parse_position("e4 e4").is_err());
+    }
+
+    #[test]
+    fn polyglot_keys() {
+        let mut pos = Chess::default();
+        assert_eq!(polyglot_key(&pos), 0x463b96181691fc9c);
+
+        for (san, key) in [
+            ("e4", 0x823c9b50fd114196),
+            ("d5", 0x0756b94461c50fb0),
+            ("e5", 0x662fafb965db29d4),
+            ("f5", 0x22a48b5a8e47ff78),
+        ] {
+            let m = parse_san(&pos, san).unwrap();
+            pos.play_unchecked(m);
+            assert_eq!(polyglot_key(&pos), key, "after {}", san);
+        }
+    }
+
+    #[test]
+    fn uci_notation() {
+        let pos = parse_fen("r3k3/1P6/8/8/8/8/8/4K2R w Kq - 0 1").unwrap();
+        assert_eq!(san_to_uci(&pos, "O-O").unwrap(), "e1g1");
+        assert_eq!(san_to_uci(&pos, "bxa8=Q+").unwrap(), "b7a8q");
+        assert_eq!(uci_to_san(&pos, "b7a8n").unwrap(), "bxa8=N");
+        assert_eq!(uci_to_san(&pos, "e1h1").unwrap(), "O-O");
+        assert!(uci_to_san(&pos, "e1e3").is_err());
+
+        let mut pgn = Pgn::new("test", 1);
+        pgn.moves = ["f3", "e5", "g4", "Qh4#"]
+            .iter()
+            .map(|m| m.to_string())
+            .collect();
+        let uci_moves = Replay::new(&pgn).unwrap().uci_moves();
+        assert_eq!(uci_moves, vec!["f2f3", "e7e5", "g2g4", "d8h4"]);
+        assert_eq!(
+            uci_moves_to_san(&Chess::default(), &uci_moves).unwrap(),
+            pgn.moves
+        );
+    }
+}
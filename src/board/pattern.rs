@@ -0,0 +1,334 @@
+use std::collections::BTreeSet;
+use std::str::FromStr;
+
+use shakmaty::{Bitboard, Board, Chess, Color, File, Piece, Position, Role, Square};
+use simple_error::SimpleError;
+
+use super::Replay;
+
+/// Comparison of a piece count.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CountOp {
+    Eq,
+    AtLeast,
+    AtMost,
+}
+
+/// A condition on a position.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Condition {
+    PieceOn(Piece, Square),
+    Count(Piece, CountOp, usize),
+    /// A pawn on the d-file without pawns of the same side on the c- and
+    /// e-files.
+    IsolatedQueenPawn(Color),
+    /// Each side has one bishop, on squares of opposite colours.
+    OppositeBishops,
+    Not(Box<Condition>),
+}
+
+impl Condition {
+    pub fn matches(&self, board: &Board) -> bool {
+        match self {
+            Condition::PieceOn(piece, square) => board.piece_at(*square) == Some(*piece),
+            Condition::Count(piece, op, n) => {
+                let count = board.by_piece(*piece).count();
+                match op {
+                    CountOp::Eq => count == *n,
+                    CountOp::AtLeast => count >= *n,
+                    CountOp::AtMost => count <= *n,
+                }
+            }
+            Condition::IsolatedQueenPawn(color) => {
+                let pawns = board.by_piece(color.pawn());
+                let neighbours = Bitboard::from_file(File::C) | Bitboard::from_file(File::E);
+                (pawns & Bitboard::from_file(File::D)).any() && (pawns & neighbours).is_empty()
+            }
+            Condition::OppositeBishops => {
+                let white = board.by_piece(Color::White.bishop());
+                let black = board.by_piece(Color::Black.bishop());
+                white.count() == 1
+                    && black.count() == 1
+                    && (white & Bitboard::LIGHT_SQUARES).any()
+                        != (black & Bitboard::LIGHT_SQUARES).any()
+            }
+            Condition::Not(condition) => !condition.matches(board),
+        }
+    }
+
+    /// Index codes that every game with a matching position has, see
+    /// `game_codes`.
+    fn required_codes(&self) -> Vec<i32> {
+        match self {
+            Condition::PieceOn(piece, square) => vec![square_code(*piece, *square)],
+            Condition::Count(piece, op, n) => {
+                let start = start_count(*piece);
+                match op {
+                    CountOp::Eq | CountOp::AtMost if *n < start => vec![at_most_code(*piece, *n)],
+                    CountOp::Eq | CountOp::AtLeast if *n > start => {
+                        vec![at_least_code(*piece, *n)]
+                    }
+                    _ => Vec::new(),
+                }
+            }
+            // Pawns on the d-file only.
+            Condition::IsolatedQueenPawn(color) => vec![pawn_files_code(*color, 0b010)],
+            Condition::OppositeBishops => vec![OPPOSITE_BISHOPS_CODE],
+            Condition::Not(_) => Vec::new(),
+        }
+    }
+}
+
+impl FromStr for Condition {
+    type Err = SimpleError;
+
+    /// Parses `Bg5` (white bishop on g5), `qd8` (black queen on d8), `R`
+    /// (at least one white rook), `r=2`, `P>=6`, `N<=1`, `iqp-white`,
+    /// `iqp-black`, `opposite-bishops`, or any of them negated with `!`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bad = || SimpleError::new(format!("bad pattern condition: {}", s));
+
+        if let Some(negated) = s.strip_prefix('!') {
+            return Ok(Condition::Not(Box::new(negated.parse()?)));
+        }
+        match s {
+            "iqp-white" => return Ok(Condition::IsolatedQueenPawn(Color::White)),
+            "iqp-black" => return Ok(Condition::IsolatedQueenPawn(Color::Black)),
+            "opposite-bishops" => return Ok(Condition::OppositeBishops),
+            _ => {}
+        }
+
+        let mut chars = s.chars();
+        let piece = chars.next().and_then(Piece::from_char).ok_or_else(bad)?;
+        let rest = chars.as_str();
+
+        if rest.is_empty() {
+            return Ok(Condition::Count(piece, CountOp::AtLeast, 1));
+        }
+        if let Ok(square) = rest.parse::<Square>() {
+            return Ok(Condition::PieceOn(piece, square));
+        }
+        let (op, count) = if let Some(count) = rest.strip_prefix(">=") {
+            (CountOp::AtLeast, count)
+        } else if let Some(count) = rest.strip_prefix("<=") {
+            (CountOp::AtMost, count)
+        } else if let Some(count) = rest.strip_prefix('=') {
+            (CountOp::Eq, count)
+        } else {
+            return Err(bad());
+        };
+        let count = count.parse::<usize>().map_err(|_| bad())?;
+        Ok(Condition::Count(piece, op, count))
+    }
+}
+
+/// Conditions that must all hold in one position, written separated by
+/// spaces or commas, e.g. `Bg5 Nf3 qd8` or `opposite-bishops R r`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pattern {
+    pub conditions: Vec<Condition>,
+}
+
+impl FromStr for Pattern {
+    type Err = SimpleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let conditions = s
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|word| !word.is_empty())
+            .map(|word| word.parse())
+            .collect::<Result<Vec<Condition>, SimpleError>>()?;
+
+        if conditions.is_empty() {
+            return Err(SimpleError::new("empty pattern"));
+        }
+        Ok(Self { conditions })
+    }
+}
+
+impl Pattern {
+    pub fn matches(&self, pos: &Chess) -> bool {
+        self.conditions.iter().all(|c| c.matches(pos.board()))
+    }
+
+    /// Ply of the first position of `replay` matching the pattern.
+    pub fn first_match(&self, replay: &Replay) -> Option<usize> {
+        replay.positions.iter().position(|pos| self.matches(pos))
+    }
+
+    /// Index codes, as given by `game_codes`, of every game with a position
+    /// matching the pattern.
+    pub fn required_codes(&self) -> Vec<i32> {
+        let start = position_codes(&Board::default());
+        let codes: BTreeSet<i32> = self
+            .conditions
+            .iter()
+            .flat_map(|c| c.required_codes())
+            .filter(|code| !start.contains(code))
+            .collect();
+        codes.into_iter().collect()
+    }
+}
+
+/// Codes of the features of the positions of `replay` that patterns look
+/// for: pieces on squares, piece counts, pawns on the centre files and
+/// opposite-coloured bishops. A game can only match a pattern if it has all
+/// of the pattern's required codes. Codes of the starting position are left
+/// out, as every game would have them.
+pub fn game_codes(replay: &Replay) -> Vec<i32> {
+    let start = position_codes(&Board::default());
+    let mut codes: BTreeSet<i32> = BTreeSet::new();
+    for pos in replay.positions.iter() {
+        codes.extend(position_codes(pos.board()));
+    }
+    codes.retain(|code| !start.contains(code));
+    codes.into_iter().collect()
+}
+
+/// Files of the pawn files codes, in the order of the bits of their masks.
+const CENTRE_FILES: [File; 3] = [File::C, File::D, File::E];
+
+/// Piece counts above which codes are not distinguished.
+const MAX_COUNT: usize = 15;
+
+const AT_MOST_CODES: i32 = 12 * 64;
+const AT_LEAST_CODES: i32 = AT_MOST_CODES + 12 * 16;
+const PAWN_FILES_CODES: i32 = AT_LEAST_CODES + 12 * 16;
+const OPPOSITE_BISHOPS_CODE: i32 = PAWN_FILES_CODES + 2 * 8;
+
+fn position_codes(board: &Board) -> BTreeSet<i32> {
+    let mut codes: BTreeSet<i32> = BTreeSet::new();
+    for (square, piece) in board {
+        codes.insert(square_code(piece, square));
+    }
+    for piece in Color::ALL
+        .into_iter()
+        .flat_map(|color| Role::ALL.map(|role| role.of(color)))
+    {
+        let count = board.by_piece(piece).count();
+        let start = start_count(piece);
+        codes.extend((count..start).map(|n| at_most_code(piece, n)));
+        codes.extend((start + 1..=count).map(|n| at_least_code(piece, n)));
+    }
+    for color in Color::ALL {
+        let pawns = board.by_piece(color.pawn());
+        let mask = CENTRE_FILES
+            .iter()
+            .enumerate()
+            .filter(|(_, file)| (pawns & Bitboard::from_file(**file)).any())
+            .fold(0, |mask, (i, _)| mask | 1 << i);
+        codes.insert(pawn_files_code(color, mask));
+    }
+    if Condition::OppositeBishops.matches(board) {
+        codes.insert(OPPOSITE_BISHOPS_CODE);
+    }
+    codes
+}
+
+fn piece_index(piece: Piece) -> i32 {
+    piece.color.fold_wb(0, 6) + piece.role as i32 - Role::Pawn as i32
+}
+
+fn start_count(piece: Piece) -> usize {
+    Board::default().by_piece(piece).count()
+}
+
+fn square_code(piece: Piece, square: Square) -> i32 {
+    piece_index(piece) * 64 + u32::from(square) as i32
+}
+
+/// Code of positions with at most `n` of `piece`, below the starting count.
+fn at_most_code(piece: Piece, n: usize) -> i32 {
+    AT_MOST_CODES + piece_index(piece) * 16 + n.min(MAX_COUNT) as i32
+}
+
+/// Code of positions with at least `n` of `piece`, above the starting
+/// count.
+fn at_least_code(piece: Piece, n: usize) -> i32 {
+    AT_LEAST_CODES + piece_index(piece) * 16 + n.min(MAX_COUNT) as i32
+}
+
+/// Code of the centre files, as a mask in the order of `CENTRE_FILES`, with
+/// pawns of `color`.
+fn pawn_files_code(color: Color, mask: i32) -> i32 {
+    PAWN_FILES_CODES + color.fold_wb(0, 8) + mask
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::parse_fen;
+    use crate::pgn::Pgn;
+
+    #[test]
+    fn parse_and_match() {
+        let pattern: Pattern = "Bg5, Nf3 qd8 !Pe4".parse().unwrap();
+        assert_eq!(pattern.conditions.len(), 4);
+        assert!(matches!(
+            pattern.conditions[3],
+            Condition::Not(ref c) if **c == Condition::PieceOn(Color::White.pawn(), Square::E4)
+        ));
+        assert!("Xg5".parse::<Pattern>().is_err());
+        assert!("".parse::<Pattern>().is_err());
+
+        let mut pgn = Pgn::new("game", 1);
+        pgn.moves = ["d4", "d5", "c4", "e6", "Nc3", "Nf6", "Bg5", "Be7", "Nf3"]
+            .iter()
+            .map(|m| m.to_string())
+            .collect();
+        let replay = Replay::new(&pgn).unwrap();
+        assert_eq!(pattern.first_match(&replay), Some(9));
+
+        let codes = game_codes(&replay);
+        assert!(pattern
+            .required_codes()
+            .iter()
+            .all(|code| codes.contains(code)));
+
+        let iqp = parse_fen("4k3/pp3ppp/8/8/3P4/8/PP3PPP/4K3 w - - 0 1").unwrap();
+        assert!("iqp-white".parse::<Pattern>().unwrap().matches(&iqp));
+        assert!(!"iqp-black".parse::<Pattern>().unwrap().matches(&iqp));
+
+        let bishops = parse_fen("r3k3/8/3b4/8/8/3B4/8/R3K3 w - - 0 1").unwrap();
+        let pattern: Pattern = "opposite-bishops R r r<=1 P=0".parse().unwrap();
+        assert!(pattern.matches(&bishops));
+        let same = parse_fen("r3k3/8/2b5/8/8/3B4/8/R3K3 w - - 0 1").unwrap();
+        assert!(!pattern.matches(&same));
+    }
+
+    #[test]
+    fn selective_codes() {
+        let codes = |fen: &str| {
+            let mut pgn = Pgn::new("game", 1);
+            pgn.tags.insert("FEN".to_string(), fen.to_string());
+            game_codes(&Replay::new(&pgn).unwrap())
+        };
+        let indexed = |pattern: &str, codes: &[i32]| {
+            let required = pattern.parse::<Pattern>().unwrap().required_codes();
+            assert!(!required.is_empty(), "{}", pattern);
+            required.iter().all(|code| codes.contains(code))
+        };
+
+        let start = codes("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        assert!(start.is_empty());
+        assert!("Ra1 R k=1 P>=8"
+            .parse::<Pattern>()
+            .unwrap()
+            .required_codes()
+            .is_empty());
+
+        let iqp = codes("4k3/pp3ppp/8/8/3P4/8/PP3PPP/4K3 w - - 0 1");
+        assert!(indexed("iqp-white", &iqp));
+        assert!(!indexed("iqp-black", &iqp));
+        assert!(indexed("P=6 n=0 Pd4", &iqp));
+        assert!(!indexed("P=5", &iqp));
+
+        let bishops = codes("r3k3/8/3b4/8/8/3B4/8/R3K3 w - - 0 1");
+        assert!(indexed("opposite-bishops r<=1", &bishops));
+        let same = codes("r3k3/8/2b5/8/8/3B4/8/R3K3 w - - 0 1");
+        assert!(!indexed("opposite-bishops", &same));
+        let promoted = codes("4k3/8/8/8/8/8/8/QQQ1K3 w - - 0 1");
+        assert!(indexed("Q>=3", &promoted));
+        assert!(!indexed("Q=4", &promoted));
+    }
+}
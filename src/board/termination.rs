@@ -0,0 +1,220 @@
+use std::fmt;
+
+use shakmaty::zobrist::{Zobrist64, ZobristHash};
+use shakmaty::{Color, EnPassantMode, Position};
+use simple_error::SimpleError;
+
+use super::Replay;
+use crate::pgn::Pgn;
+
+/// How a game ended, worked out from its final position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Termination {
+    Checkmate,
+    Stalemate,
+    /// Neither side can mate.
+    InsufficientMaterial,
+    /// 75 moves without a capture or pawn move, drawn automatically.
+    SeventyFiveMoves,
+    /// The final position occurred five times, drawn automatically.
+    FivefoldRepetition,
+    /// 50 moves without a capture or pawn move, a draw could be claimed.
+    FiftyMoves,
+    /// The final position occurred three times, a draw could be claimed.
+    ThreefoldRepetition,
+    /// Decisive result with the `Termination` tag saying so.
+    TimeForfeit,
+    /// Decisive result otherwise.
+    Resignation,
+    /// Drawn result otherwise.
+    Agreement,
+    Unfinished,
+}
+
+impl Termination {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Termination::Checkmate => "checkmate",
+            Termination::Stalemate => "stalemate",
+            Termination::InsufficientMaterial => "insufficient-material",
+            Termination::SeventyFiveMoves => "75-moves",
+            Termination::FivefoldRepetition => "fivefold-repetition",
+            Termination::FiftyMoves => "50-moves",
+            Termination::ThreefoldRepetition => "threefold-repetition",
+            Termination::TimeForfeit => "time-forfeit",
+            Termination::Resignation => "resignation",
+            Termination::Agreement => "agreement",
+            Termination::Unfinished => "unfinished",
+        }
+    }
+}
+
+impl fmt::Display for Termination {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Termination of a game and what contradicts its result, if anything.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TerminationAnalysis {
+    pub termination: Termination,
+    pub contradiction: Option<String>,
+}
+
+/// Replays `pgn` and works out how it ended. Rules ending the game by
+/// themselves take precedence over draws that could be claimed, which take
+/// precedence over the result and `Termination` tags.
+pub fn analyze_termination(pgn: &Pgn) -> Result<TerminationAnalysis, SimpleError> {
+    let replay = Replay::new(pgn)?;
+    let last = replay.last();
+    let result = pgn.tags.get("Result").map_or("*", |r| r.as_str());
+
+    let key = |pos: &shakmaty::Chess| -> u64 {
+        let hash: Zobrist64 = pos.zobrist_hash(EnPassantMode::Legal);
+        hash.0
+    };
+    let final_key = key(last);
+    let repetitions = replay
+        .positions
+        .iter()
+        .filter(|pos| key(pos) == final_key)
+        .count();
+
+    let termination = if last.is_checkmate() {
+        Termination::Checkmate
+    } else if last.is_stalemate() {
+        Termination::Stalemate
+    } else if last.is_insufficient_material() {
+        Termination::InsufficientMaterial
+    } else if last.halfmoves() >= 150 {
+        Termination::SeventyFiveMoves
+    } else if repetitions >= 5 {
+        Termination::FivefoldRepetition
+    } else if last.halfmoves() >= 100 {
+        Termination::FiftyMoves
+    } else if repetitions >= 3 {
+        Termination::ThreefoldRepetition
+    } else {
+        let time_forfeit = pgn
+            .tags
+            .get("Termination")
+            .is_some_and(|t| t.eq_ignore_ascii_case("time forfeit"));
+        match result {
+            "1-0" | "0-1" if time_forfeit => Termination::TimeForfeit,
+            "1-0" | "0-1" => Termination::Resignation,
+            "1/2-1/2" => Termination::Agreement,
+            _ => Termination::Unfinished,
+        }
+    };
+
+    let winner = match result {
+        "1-0" => Some(Color::White),
+        "0-1" => Some(Color::Black),
+        _ => None,
+    };
+
+    let contradiction = match termination {
+        Termination::Checkmate => {
+            let expected = last.turn().fold_wb("0-1", "1-0");
+            (result != expected).then(|| format!("{} on checkmate, expected {}", result, expected))
+        }
+        Termination::Stalemate
+        | Termination::InsufficientMaterial
+        | Termination::SeventyFiveMoves
+        | Termination::FivefoldRepetition
+            if result != "1/2-1/2" =>
+        {
+            Some(format!("{} on {}, expected 1/2-1/2", result, termination))
+        }
+        // Only a flag fall needs the winner to have mating material: a
+        // player may resign a position the opponent cannot force mate in.
+        Termination::TimeForfeit => winner
+            .filter(|colour| last.has_insufficient_material(*colour))
+            .map(|colour| {
+                format!(
+                    "{} but {} has no mating material",
+                    result,
+                    colour.fold_wb("white", "black")
+                )
+            }),
+        _ => None,
+    };
+
+    Ok(TerminationAnalysis {
+        termination,
+        contradiction,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn game(fen: Option<&str>, moves: &str, result: &str) -> Pgn {
+        let mut pgn = Pgn::new("game", 1);
+        if let Some(fen) = fen {
+            pgn.tags.insert("FEN".to_string(), fen.to_string());
+        }
+        pgn.tags.insert("Result".to_string(), result.to_string());
+        pgn.moves = moves.split_whitespace().map(|m| m.to_string()).collect();
+        pgn
+    }
+
+    #[test]
+    fn terminations() {
+        let mate = analyze_termination(&game(None, "f3 e5 g4 Qh4#", "0-1")).unwrap();
+        assert_eq!(mate.termination, Termination::Checkmate);
+        assert_eq!(mate.contradiction, None);
+
+        let stalemate =
+            analyze_termination(&game(Some("k7/8/1K6/8/8/8/8/2Q5 w - - 0 1"), "Qc7", "1-0"))
+                .unwrap();
+        assert_eq!(stalemate.termination, Termination::Stalemate);
+        assert_eq!(
+            stalemate.contradiction.as_deref(),
+            Some("1-0 on stalemate, expected 1/2-1/2")
+        );
+
+        let repetition =
+            analyze_termination(&game(None, "Nf3 Nf6 Ng1 Ng8 Nf3 Nf6 Ng1 Ng8", "1/2-1/2")).unwrap();
+        assert_eq!(repetition.termination, Termination::ThreefoldRepetition);
+
+        let mut bare_king = game(Some("4k3/8/8/8/8/8/4P3/4K3 b - - 0 1"), "Kd7", "0-1");
+        bare_king
+            .tags
+            .insert("Termination".to_string(), "Time forfeit".to_string());
+        let bare_king = analyze_termination(&bare_king).unwrap();
+        assert_eq!(bare_king.termination, Termination::TimeForfeit);
+        assert_eq!(
+            bare_king.contradiction.as_deref(),
+            Some("0-1 but black has no mating material")
+        );
+
+        // Black resigns a king and pawn against a lone bishop.
+        let resigned = analyze_termination(&game(
+            Some("4k3/8/8/8/8/8/4p3/3BK3 b - - 0 1"),
+            "Kd7",
+            "1-0",
+        ))
+        .unwrap();
+        assert_eq!(resigned.termination, Termination::Resignation);
+        assert_eq!(resigned.contradiction, None);
+
+        // White resigns a king and pawn against a bare king.
+        let resigned =
+            analyze_termination(&game(Some("4k3/8/8/8/8/8/4P3/4K3 b - - 0 1"), "Kd7", "0-1"))
+                .unwrap();
+        assert_eq!(resigned.termination, Termination::Resignation);
+        assert_eq!(resigned.contradiction, None);
+
+        let mut flagged = game(None, "e4 e5", "1-0");
+        flagged
+            .tags
+            .insert("Termination".to_string(), "Time forfeit".to_string());
+        assert_eq!(
+            analyze_termination(&flagged).unwrap().termination,
+            Termination::TimeForfeit
+        );
+    }
+}
@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+
+use shakmaty::{Color, Position};
+use simple_error::SimpleError;
+
+use crate::board::{polyglot_key, Replay};
+use crate::pgn::Pgn;
+
+mod polyglot;
+pub use polyglot::{create_book, decode_move, encode_move, write_book, Book, Entry, ENTRY_SIZE};
+
+#[derive(Debug, Clone, Copy, Default)]
+struct MoveStats {
+    games: usize,
+    wins: usize,
+    draws: usize,
+}
+
+/// Aggregates the moves of games into Polyglot book entries.
+///
+/// A move is weighted by its score for the side playing it, two points per
+/// win and one per draw, so that losing moves stay in the book with no
+/// weight.
+#[derive(Debug, Default)]
+pub struct BookBuilder {
+    min_elo: Option<i32>,
+    min_games: usize,
+    colour: Option<Color>,
+    max_ply: Option<usize>,
+    moves: HashMap<(u64, u16), MoveStats>,
+}
+
+impl BookBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Skips games unless both players are rated at least `min_elo`.
+    pub fn with_min_elo(mut self, min_elo: i32) -> Self {
+        self.min_elo = Some(min_elo);
+        self
+    }
+
+    /// Leaves out moves played in fewer than `min_games` games.
+    pub fn with_min_games(mut self, min_games: usize) -> Self {
+        self.min_games = min_games;
+        self
+    }
+
+    /// Only records the moves of one side.
+    pub fn with_colour(mut self, colour: Color) -> Self {
+        self.colour = Some(colour);
+        self
+    }
+
+    /// Only records the first `max_ply` plies of each game.
+    pub fn with_max_ply(mut self, max_ply: usize) -> Self {
+        self.max_ply = Some(max_ply);
+        self
+    }
+
+    /// Records the moves of `pgn`. Returns false if the game is filtered
+    /// out or has no decisive or drawn result.
+    pub fn add(&mut self, pgn: &Pgn) -> Result<bool, SimpleError> {
+        if let Some(min_elo) = self.min_elo {
+            let rated = ["WhiteElo", "BlackElo"].iter().all(|tag| {
+                pgn.tags
+                    .get(*tag)
+                    .and_then(|elo| elo.parse::<i32>().ok())
+                    .is_some_and(|elo| elo >= min_elo)
+            });
+            if !rated {
+                return Ok(false);
+            }
+        }
+
+        let winner = match pgn.tags.get("Result").map(|r| r.as_str()) {
+            Some("1-0") => Some(Color::White),
+            Some("0-1") => Some(Color::Black),
+            Some("1/2-1/2") => None,
+            _ => return Ok(false),
+        };
+
+        let replay = Replay::new(pgn)?;
+        let plies = self
+            .max_ply
+            .map_or(replay.moves.len(), |max| max.min(replay.moves.len()));
+
+        for ply in 0..plies {
+            let pos = &replay.positions[ply];
+            let turn = pos.turn();
+            if self.colour.is_some_and(|colour| colour != turn) {
+                continue;
+            }
+
+            let stats = self
+                .moves
+                .entry((polyglot_key(pos), encode_move(replay.moves[ply])))
+                .or_default();
+            stats.games += 1;
+            match winner {
+                Some(colour) if colour == turn => stats.wins += 1,
+                Some(_) => {}
+                None => stats.draws += 1,
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Entries sorted by key, heaviest move first. Weights are scaled down
+    /// if the largest does not fit in 16 bits.
+    pub fn build(&self) -> Vec<Entry> {
+        let scores: Vec<(u64, u16, usize)> = self
+            .moves
+            .iter()
+            .filter(|(_, stats)| stats.games >= self.min_games)
+            .map(|((key, raw_move), stats)| (*key, *raw_move, 2 * stats.wins + stats.draws))
+            .collect();
+
+        let max_score = scores.iter().map(|s| s.2).max().unwrap_or(0);
+        let scale = |score: usize| -> u16 {
+            if max_score <= u16::MAX as usize {
+                score as u16
+            } else {
+                (score * u16::MAX as usize / max_score) as u16
+            }
+        };
+
+        let mut entries: Vec<Entry> = scores
+            .into_iter()
+            .map(|(key, raw_move, score)| Entry {
+                key,
+                raw_move,
+                weight: scale(score),
+                learn: 0,
+            })
+            .collect();
+        entries.sort_by(|a, b| {
+            a.key
+                .cmp(&b.key)
+                .then(b.weight.cmp(&a.weight))
+                .then(a.raw_move.cmp(&b.raw_move))
+        });
+
+        entries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::{parse_fen, parse_san, san, start_position};
+
+    fn game(moves: &[&str], result: &str, elo: &str) -> Pgn {
+        let mut pgn = Pgn::new("test", 1);
+        pgn.moves = moves.iter().map(|m| m.to_string()).collect();
+        pgn.tags.insert("Result".to_string(), result.to_string());
+        pgn.tags.insert("WhiteElo".to_string(), elo.to_string());
+        pgn.tags.insert("BlackElo".to_string(), elo.to_string());
+        pgn
+    }
+
+    #[test]
+    fn build_and_probe() {
+        let mut builder = BookBuilder::new().with_min_elo(2000).with_min_games(2);
+        assert!(builder.add(&game(&["e4", "e5"], "1-0", "2500")).unwrap());
+        assert!(builder
+            .add(&game(&["e4", "c5"], "1/2-1/2", "2500"))
+            .unwrap());
+        assert!(builder.add(&game(&["d4", "d5"], "0-1", "2500")).unwrap());
+        assert!(builder.add(&game(&["d4", "Nf6"], "0-1", "2500")).unwrap());
+        assert!(!builder.add(&game(&["c4"], "1-0", "1500")).unwrap());
+        assert!(!builder.add(&game(&["c4"], "*", "2500")).unwrap());
+
+        let mut bytes: Vec<u8> = Vec::new();
+        write_book(&mut bytes, &builder.build()).unwrap();
+        assert_eq!(bytes.len(), 2 * ENTRY_SIZE);
+
+        let book = Book::from_bytes(&bytes).unwrap();
+        let pos = start_position(&Pgn::new("test", 1)).unwrap();
+        let moves: Vec<(String, u16)> = book
+            .probe(&pos)
+            .into_iter()
+            .map(|(m, weight)| (san(&pos, m), weight))
+            .collect();
+        assert_eq!(moves, vec![("e4".to_string(), 3), ("d4".to_string(), 0)]);
+    }
+
+    #[test]
+    fn castling_moves() {
+        let pos = parse_fen("4k3/8/8/8/8/8/8/4K2R w K - 0 1").unwrap();
+        let castle = parse_san(&pos, "O-O").unwrap();
+        // e1h1
+        assert_eq!(encode_move(castle), 7 | (4 << 6));
+        assert_eq!(decode_move(&pos, encode_move(castle)), Some(castle));
+    }
+}
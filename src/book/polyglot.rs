@@ -0,0 +1,131 @@
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+use std::path::Path;
+
+use shakmaty::{Chess, Move, Position, Role};
+use simple_error::SimpleError;
+
+use crate::board::polyglot_key;
+
+/// Size of an entry in a `.bin` file.
+pub const ENTRY_SIZE: usize = 16;
+
+/// An entry of a Polyglot book: a move for the position with `key`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Entry {
+    pub key: u64,
+    /// Encoded move, see `encode_move`.
+    pub raw_move: u16,
+    pub weight: u16,
+    pub learn: u32,
+}
+
+impl Entry {
+    fn to_bytes(self) -> [u8; ENTRY_SIZE] {
+        let mut bytes = [0u8; ENTRY_SIZE];
+        bytes[0..8].copy_from_slice(&self.key.to_be_bytes());
+        bytes[8..10].copy_from_slice(&self.raw_move.to_be_bytes());
+        bytes[10..12].copy_from_slice(&self.weight.to_be_bytes());
+        bytes[12..16].copy_from_slice(&self.learn.to_be_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Self {
+        Self {
+            key: u64::from_be_bytes(bytes[0..8].try_into().unwrap()),
+            raw_move: u16::from_be_bytes(bytes[8..10].try_into().unwrap()),
+            weight: u16::from_be_bytes(bytes[10..12].try_into().unwrap()),
+            learn: u32::from_be_bytes(bytes[12..16].try_into().unwrap()),
+        }
+    }
+}
+
+/// Polyglot encoding of `m`: destination in bits 0-5, origin in bits 6-11
+/// and promotion piece in bits 12-14. Castling is written as the king
+/// capturing its own rook.
+pub fn encode_move(m: Move) -> u16 {
+    let from = m.from().map_or(0, u16::from);
+    let to = u16::from(m.to());
+    let promotion = match m.promotion() {
+        Some(Role::Knight) => 1,
+        Some(Role::Bishop) => 2,
+        Some(Role::Rook) => 3,
+        Some(Role::Queen) => 4,
+        _ => 0,
+    };
+
+    to | (from << 6) | (promotion << 12)
+}
+
+/// Legal move of `pos` encoded as `raw_move`.
+pub fn decode_move(pos: &Chess, raw_move: u16) -> Option<Move> {
+    pos.legal_moves()
+        .into_iter()
+        .find(|m| encode_move(*m) == raw_move)
+}
+
+/// Writes `entries`, which must be sorted by key, as a `.bin` book.
+pub fn write_book<W: Write>(out: &mut W, entries: &[Entry]) -> std::io::Result<()> {
+    for entry in entries.iter() {
+        out.write_all(&entry.to_bytes())?;
+    }
+    out.flush()
+}
+
+pub fn create_book(path: &Path, entries: &[Entry]) -> std::io::Result<()> {
+    write_book(&mut BufWriter::new(File::create(path)?), entries)
+}
+
+/// A Polyglot book loaded in memory.
+pub struct Book {
+    entries: Vec<Entry>,
+}
+
+impl Book {
+    pub fn open(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut bytes: Vec<u8> = Vec::new();
+        File::open(path)?.read_to_end(&mut bytes)?;
+        Ok(Self::from_bytes(&bytes)?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SimpleError> {
+        if !bytes.len().is_multiple_of(ENTRY_SIZE) {
+            return Err(SimpleError::new(format!(
+                "book size ({}) is not a multiple of {}",
+                bytes.len(),
+                ENTRY_SIZE
+            )));
+        }
+
+        Ok(Self {
+            entries: bytes.chunks(ENTRY_SIZE).map(Entry::from_bytes).collect(),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Entries for `key`, in file order.
+    pub fn entries(&self, key: u64) -> &[Entry] {
+        let start = self.entries.partition_point(|e| e.key < key);
+        let end = self.entries.partition_point(|e| e.key <= key);
+        &self.entries[start..end]
+    }
+
+    /// Book moves of `pos` with their weights, heaviest first. Entries that
+    /// do not decode to a legal move are skipped.
+    pub fn probe(&self, pos: &Chess) -> Vec<(Move, u16)> {
+        let mut moves: Vec<(Move, u16)> = self
+            .entries(polyglot_key(pos))
+            .iter()
+            .filter_map(|e| decode_move(pos, e.raw_move).map(|m| (m, e.weight)))
+            .collect();
+        moves.sort_by_key(|(_, weight)| std::cmp::Reverse(*weight));
+        moves
+    }
+}
@@ -0,0 +1,240 @@
+use std::collections::HashMap;
+
+use shakmaty::{Chess, Position};
+use simple_error::SimpleError;
+
+use crate::board::{parse_san, polyglot_key, Replay};
+use crate::pgn::movetext::{tokenize, Token};
+use crate::pgn::Pgn;
+
+/// Table shipped with the crate, in the format of the Lichess
+/// `chess-openings` files: a header line, then `eco`, `name` and `pgn`
+/// separated by tabs.
+const BUILTIN_TABLE: &str = include_str!("eco.tsv");
+
+/// An entry of the opening table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Opening {
+    pub eco: String,
+    /// Full name, e.g. `Sicilian Defense: Najdorf Variation`.
+    pub name: String,
+    pub moves: Vec<String>,
+}
+
+impl Opening {
+    /// Name of the opening, without the variation.
+    pub fn opening(&self) -> &str {
+        self.name.split(':').next().unwrap_or("").trim()
+    }
+
+    /// Name of the variation, if any.
+    pub fn variation(&self) -> Option<&str> {
+        self.name
+            .split_once(':')
+            .map(|(_, variation)| variation.trim())
+    }
+}
+
+/// Classifies games by the positions of an opening table, so that
+/// transpositions are recognised.
+pub struct Classifier {
+    openings: Vec<Opening>,
+    /// Index in `openings` by key of the position the moves lead to.
+    positions: HashMap<u64, usize>,
+    /// Fewest moves of the lines of each ECO code.
+    shortest: HashMap<String, usize>,
+}
+
+impl Default for Classifier {
+    fn default() -> Self {
+        Self::from_tsv(BUILTIN_TABLE).unwrap()
+    }
+}
+
+impl Classifier {
+    /// Reads a table in the format of `eco.tsv`. When several lines lead to
+    /// the same position, the first one is used.
+    pub fn from_tsv(text: &str) -> Result<Self, SimpleError> {
+        let mut classifier = Self {
+            openings: Vec::new(),
+            positions: HashMap::new(),
+            shortest: HashMap::new(),
+        };
+
+        for (number, line) in text.lines().enumerate().skip(1) {
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split('\t').collect();
+            if fields.len() < 3 {
+                return Err(SimpleError::new(format!(
+                    "line {}: expected eco, name and pgn",
+                    number + 1
+                )));
+            }
+
+            let mut pos = Chess::default();
+            let mut moves: Vec<String> = Vec::new();
+            for token in tokenize(fields[2]) {
+                if let Token::San(san) = token {
+                    let m = parse_san(&pos, san.as_str())
+                        .map_err(|e| SimpleError::new(format!("line {}: {}", number + 1, e)))?;
+                    pos.play_unchecked(m);
+                    moves.push(san);
+                }
+            }
+
+            classifier
+                .positions
+                .entry(polyglot_key(&pos))
+                .or_insert(classifier.openings.len());
+            let shortest = classifier
+                .shortest
+                .entry(fields[0].trim().to_string())
+                .or_insert(moves.len());
+            *shortest = (*shortest).min(moves.len());
+            classifier.openings.push(Opening {
+                eco: fields[0].trim().to_string(),
+                name: fields[1].trim().to_string(),
+                moves,
+            });
+        }
+
+        Ok(classifier)
+    }
+
+    pub fn len(&self) -> usize {
+        self.openings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.openings.is_empty()
+    }
+
+    /// Opening of the position reached last in the game that is in the
+    /// table. `None` if no position matches or the moves cannot be replayed.
+    pub fn classify(&self, pgn: &Pgn) -> Option<&Opening> {
+        let replay = Replay::new(pgn).ok()?;
+
+        replay
+            .positions
+            .iter()
+            .rev()
+            .find_map(|pos| self.positions.get(&polyglot_key(pos)))
+            .map(|index| &self.openings[*index])
+    }
+
+    /// Sets the `ECO`, `Opening` and `Variation` tags of `pgn` that are
+    /// missing. With `overwrite`, all three are replaced when the `ECO` tag
+    /// disagrees with the classification, unless the classification is
+    /// coarser: the `ECO` code is not in the table or all its lines are
+    /// longer than the one found. Returns the opening found.
+    pub fn apply(&self, pgn: &mut Pgn, overwrite: bool) -> Option<&Opening> {
+        let opening = self.classify(pgn)?;
+
+        let replace = overwrite
+            && pgn.tags.get("ECO").is_some_and(|eco| {
+                let eco = eco.trim();
+                eco != opening.eco
+                    && self
+                        .shortest
+                        .get(eco)
+                        .is_some_and(|&moves| moves <= opening.moves.len())
+            });
+
+        for (name, value) in [
+            ("ECO", Some(opening.eco.as_str())),
+            ("Opening", Some(opening.opening())),
+            ("Variation", opening.variation()),
+        ] {
+            let missing = pgn.tags.get(name).is_none_or(|v| v.trim().is_empty());
+            match value {
+                Some(value) if missing || replace => pgn.set_tag(name, value),
+                None if replace => pgn.remove_tag(name),
+                _ => {}
+            }
+        }
+
+        Some(opening)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn game(moves: &str) -> Pgn {
+        let mut pgn = Pgn::new("game", 1);
+        pgn.moves = moves.split(' ').map(|m| m.to_string()).collect();
+        pgn
+    }
+
+    #[test]
+    fn builtin_table() {
+        let classifier = Classifier::default();
+        assert!(classifier.len() > 1500);
+
+        let najdorf = game("e4 c5 Nf3 d6 d4 cxd4 Nxd4 Nf6 Nc3 a6 h3 e5");
+        let opening = classifier.classify(&najdorf).unwrap();
+        assert_eq!(opening.eco, "B90");
+        assert_eq!(opening.opening(), "Sicilian Defense");
+        assert_eq!(opening.variation(), Some("Najdorf Variation, Adams Attack"));
+
+        // Reaches the Four Knights Game from the Vienna Game.
+        let four_knights = game("e4 e5 Nc3 Nf6 Nf3 Nc6 Bb5");
+        assert_eq!(classifier.classify(&four_knights).unwrap().eco, "C48");
+
+        let king_indian = game("d4 Nf6 c4 g6 Nc3 Bg7 e4 d6 Nf3 O-O Be2 e5 O-O exd4 Nxd4 Re8");
+        assert_eq!(classifier.classify(&king_indian).unwrap().eco, "E94");
+
+        let zaitsev = game(
+            "e4 e5 Nf3 Nc6 Bb5 a6 Ba4 Nf6 O-O Be7 Re1 b5 Bb3 d6 c3 O-O h3 \
+            Bb7 d4 Re8",
+        );
+        let opening = classifier.classify(&zaitsev).unwrap();
+        assert_eq!(opening.eco, "C92");
+        assert_eq!(opening.variation(), Some("Closed, Zaitsev System"));
+    }
+
+    #[test]
+    fn fill_tags() {
+        let classifier = Classifier::default();
+
+        let mut pgn = game("d4 Nf6 c4 e6 Nc3 Bb4 Qc2");
+        pgn.tags.insert("ECO".to_string(), "A00".to_string());
+        pgn.tags_text = "[ECO \"A00\"]\n[Variation \"Mistake\"]\n".to_string();
+        pgn.tags
+            .insert("Variation".to_string(), "Mistake".to_string());
+
+        classifier.apply(&mut pgn, false);
+        assert_eq!(pgn.tags["ECO"], "A00");
+        assert_eq!(pgn.tags["Opening"], "Nimzo-Indian Defense");
+        assert_eq!(pgn.tags["Variation"], "Mistake");
+
+        classifier.apply(&mut pgn, true);
+        assert_eq!(pgn.tags["ECO"], "E32");
+        assert_eq!(pgn.tags["Variation"], "Classical Variation");
+        assert_eq!(
+            pgn.tags_text,
+            "[ECO \"E32\"]\n[Variation \"Classical Variation\"]\n\
+            [Opening \"Nimzo-Indian Defense\"]\n"
+        );
+
+        // C84 is not in a table that only knows the Ruy Lopez up to C60.
+        let ruy_lopez =
+            Classifier::from_tsv("eco\tname\tpgn\nC60\tRuy Lopez\t1. e4 e5 2. Nf3 Nc6 3. Bb5\n")
+                .unwrap();
+        let mut pgn = game("e4 e5 Nf3 Nc6 Bb5 a6 Ba4 Nf6 O-O Be7");
+        pgn.set_tag("ECO", "C84");
+        ruy_lopez.apply(&mut pgn, true);
+        assert_eq!(pgn.tags["ECO"], "C84");
+        assert_eq!(pgn.tags["Opening"], "Ruy Lopez");
+
+        // The Najdorf lines are longer than the Sicilian line found.
+        let mut pgn = game("e4 c5 Nf3 d6");
+        pgn.set_tag("ECO", "B90");
+        classifier.apply(&mut pgn, true);
+        assert_eq!(pgn.tags["ECO"], "B90");
+    }
+}
@@ -1,5 +1,11 @@
+pub mod analysis;
+pub mod board;
+pub mod book;
+pub mod eco;
 pub mod pgn;
+pub mod rating;
 pub mod store;
+pub mod uci;
 
 #[cfg(test)]
 mod tests {
@@ -0,0 +1,293 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::OnceLock;
+
+use std::ops::Range;
+
+use super::movetext::{tokenize_spans, Token};
+
+/// Engine evaluation from the point of view of White.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Eval {
+    /// Advantage in centipawns.
+    Cp(i32),
+    /// Moves to mate, negative when Black mates.
+    Mate(i32),
+}
+
+impl fmt::Display for Eval {
+    /// Formats as in `[%eval]` commands: pawns with two decimals, or `#n`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Eval::Cp(cp) => {
+                let sign = if *cp < 0 { "-" } else { "" };
+                write!(f, "{}{}.{:02}", sign, cp.abs() / 100, cp.abs() % 100)
+            }
+            Eval::Mate(n) => write!(f, "#{}", n),
+        }
+    }
+}
+
+fn command_re() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"\[%(\w+)\s+([^\]]*)\]").unwrap())
+}
+
+/// Argument of the `[%name ...]` command embedded in `comment`.
+pub fn command<'a>(comment: &'a str, name: &str) -> Option<&'a str> {
+    command_re()
+        .captures_iter(comment)
+        .find(|caps| &caps[1] == name)
+        .map(|caps| caps.get(2).unwrap().as_str().trim())
+}
+
+/// `comment` without its `[%name ...]` commands.
+pub fn remove_command(comment: &str, name: &str) -> String {
+    let removed = command_re().replace_all(comment, |caps: &regex::Captures| {
+        if &caps[1] == name {
+            String::new()
+        } else {
+            caps[0].to_string()
+        }
+    });
+    removed.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+/// Sets the `[%name value]` command after each move of the main line, one
+/// value per ply, replacing the command if present. Comments following a
+/// move are kept and the command is added to the first of them. The rest of
+/// the text is kept as it is.
+pub fn annotate(moves_text: &str, name: &str, values: &[Option<String>]) -> String {
+    let mut editor = Editor::new(moves_text);
+    let mut depth: usize = 0;
+    let mut ply: usize = 0;
+    // Command to add after the last move of the main line, and where.
+    let mut pending: Option<(String, usize)> = None;
+
+    for (token, span) in tokenize_spans(moves_text) {
+        if depth == 0 {
+            match &token {
+                Token::Comment(comment) => {
+                    if let Some((command, _)) = pending.take() {
+                        let rest = remove_command(comment, name);
+                        let merged = if rest.is_empty() {
+                            command
+                        } else {
+                            format!("{} {}", command, rest)
+                        };
+                        editor.replace(&span, format!("{{{}}}", merged).as_str());
+                        continue;
+                    }
+                }
+                Token::Nag(_) => {
+                    if let Some((_, at)) = pending.as_mut() {
+                        *at = span.end;
+                    }
+                }
+                _ => {
+                    if let Some((command, at)) = pending.take() {
+                        editor.insert(at, format!("{{{}}}", command).as_str());
+                    }
+                }
+            }
+        }
+
+        match &token {
+            Token::VariationStart => depth += 1,
+            Token::VariationEnd => depth = depth.saturating_sub(1),
+            Token::San(_) if depth == 0 => {
+                pending = values
+                    .get(ply)
+                    .and_then(|v| v.as_ref())
+                    .map(|value| (format!("[%{} {}]", name, value), span.end));
+                ply += 1;
+            }
+            _ => {}
+        }
+    }
+    if let Some((command, at)) = pending {
+        editor.insert(at, format!("{{{}}}", command).as_str());
+    }
+
+    editor.finish()
+}
+
+/// Sets a move assessment NAG ($1 to $6) after each move of the main line
+/// with a value, replacing the assessment already there, written as a NAG or
+/// as `!` and `?`. Other NAGs, moves without a value and the rest of the text
+/// are kept as they are.
+pub fn set_nags(moves_text: &str, nags: &[Option<u8>]) -> String {
+    let mut editor = Editor::new(moves_text);
+    let mut depth: usize = 0;
+    let mut ply: usize = 0;
+    // Whether assessments following the current move are dropped.
+    let mut replacing = false;
+
+    for (token, span) in tokenize_spans(moves_text) {
+        match &token {
+            Token::Nag(nag) if replacing && (1..=6).contains(nag) => {
+                editor.remove(&span);
+                continue;
+            }
+            Token::Nag(_) => {}
+            _ => replacing = false,
+        }
+
+        match &token {
+            Token::VariationStart => depth += 1,
+            Token::VariationEnd => depth = depth.saturating_sub(1),
+            Token::San(_) if depth == 0 => {
+                if let Some(nag) = nags.get(ply).copied().flatten() {
+                    editor.insert(span.end, format!("${}", nag).as_str());
+                    replacing = true;
+                }
+                ply += 1;
+            }
+            _ => {}
+        }
+    }
+
+    editor.finish()
+}
+
+/// Builds a changed copy of move text, copying the text between the changes
+/// as it is.
+struct Editor<'a> {
+    text: &'a str,
+    edited: String,
+    /// Bytes of `text` copied so far.
+    copied: usize,
+}
+
+impl<'a> Editor<'a> {
+    fn new(text: &'a str) -> Self {
+        Self {
+            text,
+            edited: String::with_capacity(text.len()),
+            copied: 0,
+        }
+    }
+
+    fn copy_to(&mut self, end: usize) {
+        if end > self.copied {
+            self.edited.push_str(&self.text[self.copied..end]);
+            self.copied = end;
+        }
+    }
+
+    /// Inserts `word` at byte `at`, after a space.
+    fn insert(&mut self, at: usize, word: &str) {
+        self.copy_to(at);
+        self.edited.push(' ');
+        self.edited.push_str(word);
+    }
+
+    /// Replaces the text of `span` with `word`.
+    fn replace(&mut self, span: &Range<usize>, word: &str) {
+        self.copy_to(span.start);
+        self.edited.push_str(word);
+        self.copied = span.end;
+    }
+
+    /// Removes the text of `span` and the spaces before it.
+    fn remove(&mut self, span: &Range<usize>) {
+        self.copy_to(span.start);
+        let kept = self.edited.trim_end_matches([' ', '\t']).len();
+        self.edited.truncate(kept);
+        self.copied = span.end;
+    }
+
+    fn finish(mut self) -> String {
+        self.copy_to(self.text.len());
+        self.edited
+    }
+}
+
+/// Remaining time in seconds from a `[%clk h:mm:ss]` command.
+pub fn parse_clock(comment: &str) -> Option<f64> {
+    let value = command(comment, "clk")?;
+
+    let mut seconds = 0.0;
+    for part in value.split(':') {
+        seconds = seconds * 60.0 + part.parse::<f64>().ok()?;
+    }
+    Some(seconds)
+}
+
+pub fn format_clock(seconds: f64) -> String {
+    let whole = seconds as u64;
+    let fraction = seconds - whole as f64;
+    let mut text = format!("{}:{:02}:{:02}", whole / 3600, whole / 60 % 60, whole % 60);
+    if fraction > 0.0 {
+        text.push_str(&format!("{:.1}", fraction)[1..]);
+    }
+    text
+}
+
+/// Evaluation from an `[%eval 0.25]` or `[%eval #-3]` command.
+pub fn parse_eval(comment: &str) -> Option<Eval> {
+    let value = command(comment, "eval")?;
+    let value = value.split(',').next()?.trim();
+
+    if let Some(mate) = value.strip_prefix('#') {
+        return mate.parse::<i32>().ok().map(Eval::Mate);
+    }
+
+    let pawns = value.parse::<f64>().ok()?;
+    Some(Eval::Cp((pawns * 100.0).round() as i32))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_commands() {
+        let comment = "[%eval -0.35] [%clk 1:02:03.5] good";
+
+        assert_eq!(parse_eval(comment), Some(Eval::Cp(-35)));
+        assert_eq!(parse_clock(comment), Some(3723.5));
+        assert_eq!(parse_eval("[%eval #-3]"), Some(Eval::Mate(-3)));
+        assert_eq!(parse_clock("no clock"), None);
+
+        assert_eq!(Eval::Cp(-35).to_string(), "-0.35");
+        assert_eq!(Eval::Mate(2).to_string(), "#2");
+        assert_eq!(format_clock(3723.5), "1:02:03.5");
+        assert_eq!(format_clock(180.0), "0:03:00");
+    }
+
+    #[test]
+    fn replace_nags() {
+        assert_eq!(
+            set_nags(
+                "1. e4 $1 $14 e5 (1... c5 $2) 2. Nf3 $6 *",
+                &[Some(2), Some(4), None]
+            ),
+            "1. e4 $2 $14 e5 $4 (1... c5 $2) 2. Nf3 $6 *"
+        );
+        assert_eq!(
+            set_nags("1. e4! e5?!\n2. Nf3 *\n", &[Some(5), None, Some(1)]),
+            "1. e4 $5 e5?!\n2. Nf3 $1 *\n"
+        );
+    }
+
+    #[test]
+    fn annotate_moves() {
+        let values = vec![Some("0.30".to_string()), None, Some("#2".to_string())];
+        assert_eq!(
+            annotate(
+                "1. e4 {[%eval 0.10] [%clk 0:03:00] fine} e5 (1... c5 {x}) 2. Nf3 $1 1-0",
+                "eval",
+                &values
+            ),
+            "1. e4 {[%eval 0.30] [%clk 0:03:00] fine} e5 (1... c5 {x}) \
+            2. Nf3 $1 {[%eval #2]} 1-0"
+        );
+        assert_eq!(
+            annotate("1. e4!? e5\n2. Nf3 ; home\n*\n", "eval", &values),
+            "1. e4!? {[%eval 0.30]} e5\n2. Nf3 {[%eval #2] home}\n*\n"
+        );
+    }
+}
@@ -0,0 +1,257 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+
+use arrow_array::builder::{
+    Date32Builder, Int32Builder, ListBuilder, StringBuilder, UInt64Builder,
+};
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::{Compression, ZstdLevel};
+use parquet::errors::ParquetError;
+use parquet::file::properties::WriterProperties;
+
+use super::Pgn;
+
+pub const DEFAULT_ROW_GROUP_SIZE: usize = 100_000;
+
+// Tags written as text columns, with their column names.
+const TEXT_TAGS: [(&str, &str); 10] = [
+    ("Event", "event"),
+    ("Site", "site"),
+    ("Round", "round"),
+    ("White", "white"),
+    ("Black", "black"),
+    ("Result", "result"),
+    ("ECO", "eco"),
+    ("Opening", "opening"),
+    ("Variation", "variation"),
+    ("TimeControl", "time_control"),
+];
+
+/// Writes games to a Parquet file with typed columns. Games are buffered
+/// until a row group is full, so memory use does not grow with the number
+/// of games.
+pub struct ParquetWriter<W: Write + Send> {
+    writer: ArrowWriter<W>,
+    schema: SchemaRef,
+    row_group_size: usize,
+    rows: usize,
+    id: StringBuilder,
+    texts: Vec<StringBuilder>,
+    date: Date32Builder,
+    white_elo: Int32Builder,
+    black_elo: Int32Builder,
+    time_control_class: StringBuilder,
+    plies: Int32Builder,
+    fingerprint: UInt64Builder,
+    moves: ListBuilder<StringBuilder>,
+    uci_moves: ListBuilder<StringBuilder>,
+}
+
+impl ParquetWriter<File> {
+    pub fn create(path: &Path, row_group_size: usize) -> Result<Self, ParquetError> {
+        Self::new(File::create(path)?, row_group_size)
+    }
+}
+
+impl<W: Write + Send> ParquetWriter<W> {
+    pub fn new(out: W, row_group_size: usize) -> Result<Self, ParquetError> {
+        let mut fields = vec![Field::new("id", DataType::Utf8, false)];
+        for (_, column) in TEXT_TAGS.iter() {
+            fields.push(Field::new(*column, DataType::Utf8, true));
+        }
+        fields.extend([
+            Field::new("date", DataType::Date32, true),
+            Field::new("white_elo", DataType::Int32, true),
+            Field::new("black_elo", DataType::Int32, true),
+            Field::new("time_control_class", DataType::Utf8, true),
+            Field::new("plies", DataType::Int32, false),
+            Field::new("fingerprint", DataType::UInt64, false),
+            Field::new(
+                "moves",
+                DataType::List(Arc::new(Field::new("item", DataType::Utf8, true))),
+                false,
+            ),
+            Field::new(
+                "uci_moves",
+                DataType::List(Arc::new(Field::new("item", DataType::Utf8, true))),
+                true,
+            ),
+        ]);
+        let schema: SchemaRef = Arc::new(Schema::new(fields));
+
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::ZSTD(ZstdLevel::default()))
+            .set_max_row_group_row_count(Some(row_group_size))
+            .build();
+
+        Ok(Self {
+            writer: ArrowWriter::try_new(out, schema.clone(), Some(properties))?,
+            schema,
+            row_group_size,
+            rows: 0,
+            id: StringBuilder::new(),
+            texts: TEXT_TAGS.iter().map(|_| StringBuilder::new()).collect(),
+            date: Date32Builder::new(),
+            white_elo: Int32Builder::new(),
+            black_elo: Int32Builder::new(),
+            time_control_class: StringBuilder::new(),
+            plies: Int32Builder::new(),
+            fingerprint: UInt64Builder::new(),
+            moves: ListBuilder::new(StringBuilder::new()),
+            uci_moves: ListBuilder::new(StringBuilder::new()),
+        })
+    }
+
+    pub fn write(&mut self, pgn: &Pgn) -> Result<(), ParquetError> {
+        self.id.append_value(pgn.id.as_str());
+        for ((tag, _), builder) in TEXT_TAGS.iter().zip(self.texts.iter_mut()) {
+            builder.append_option(pgn.tags.get(*tag));
+        }
+        self.date
+            .append_option(pgn.tags.get("Date").and_then(|d| days_since_epoch(d)));
+        self.white_elo
+            .append_option(pgn.tags.get("WhiteElo").and_then(|e| e.parse().ok()));
+        self.black_elo
+            .append_option(pgn.tags.get("BlackElo").and_then(|e| e.parse().ok()));
+        self.time_control_class.append_option(
+            pgn.time_control()
+                .and_then(|tc| tc.class())
+                .map(|class| class.as_str()),
+        );
+        self.plies.append_value(pgn.moves.len() as i32);
+        self.fingerprint.append_value(pgn.moves_fingerprint);
+        for m in pgn.moves.iter() {
+            self.moves.values().append_value(m);
+        }
+        self.moves.append(true);
+        match &pgn.uci_moves {
+            Some(uci_moves) => {
+                for m in uci_moves.iter() {
+                    self.uci_moves.values().append_value(m);
+                }
+                self.uci_moves.append(true);
+            }
+            None => self.uci_moves.append(false),
+        }
+
+        self.rows += 1;
+        if self.rows >= self.row_group_size {
+            self.write_batch()?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes buffered games and the file footer.
+    pub fn close(self) -> Result<(), ParquetError> {
+        self.into_inner().map(|_| ())
+    }
+
+    /// Writes buffered games and the file footer, returning the output.
+    pub fn into_inner(mut self) -> Result<W, ParquetError> {
+        self.write_batch()?;
+        self.writer.into_inner()
+    }
+
+    fn write_batch(&mut self) -> Result<(), ParquetError> {
+        if self.rows == 0 {
+            return Ok(());
+        }
+
+        let mut columns: Vec<ArrayRef> = vec![Arc::new(self.id.finish())];
+        for builder in self.texts.iter_mut() {
+            columns.push(Arc::new(builder.finish()));
+        }
+        columns.push(Arc::new(self.date.finish()));
+        columns.push(Arc::new(self.white_elo.finish()));
+        columns.push(Arc::new(self.black_elo.finish()));
+        columns.push(Arc::new(self.time_control_class.finish()));
+        columns.push(Arc::new(self.plies.finish()));
+        columns.push(Arc::new(self.fingerprint.finish()));
+        columns.push(Arc::new(self.moves.finish()));
+        columns.push(Arc::new(self.uci_moves.finish()));
+
+        let batch = RecordBatch::try_new(self.schema.clone(), columns)?;
+        self.writer.write(&batch)?;
+        self.writer.flush()?;
+        self.rows = 0;
+
+        Ok(())
+    }
+}
+
+/// Days from 1970-01-01 to a `YYYY.MM.DD` date; `None` if any part is
+/// unknown, the date does not exist or it is out of `Date32` range.
+fn days_since_epoch(date: &str) -> Option<i32> {
+    // Parsing as `i32` bounds the year so the arithmetic below cannot
+    // overflow an `i64`.
+    let mut parts = date
+        .split('.')
+        .map(|p| p.parse::<i32>().ok().map(i64::from));
+    let (y, m, d) = (parts.next()??, parts.next()??, parts.next()??);
+    let leap = y % 4 == 0 && (y % 100 != 0 || y % 400 == 0);
+    let month_days = match m {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        1..=12 => 31,
+        _ => return None,
+    };
+    if !(1..=month_days).contains(&d) {
+        return None;
+    }
+
+    // Days from civil, proleptic Gregorian calendar.
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (m + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+
+    i32::try_from(era * 146097 + doe - 719468).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    #[test]
+    fn dates() {
+        assert_eq!(days_since_epoch("1970.01.01"), Some(0));
+        assert_eq!(days_since_epoch("2000.03.01"), Some(11017));
+        assert_eq!(days_since_epoch("1852.??.??"), None);
+        assert_eq!(days_since_epoch("2023.02.31"), None);
+        assert_eq!(days_since_epoch("1900.02.29"), None);
+        assert_eq!(days_since_epoch("2000.02.29"), Some(11016));
+        assert_eq!(days_since_epoch("9999999.01.01"), None);
+        assert_eq!(days_since_epoch("99999999999999999.01.01"), None);
+    }
+
+    #[test]
+    fn row_groups() {
+        let path =
+            std::env::temp_dir().join(format!("mudfish_row_groups_{}.parquet", std::process::id()));
+
+        let mut writer = ParquetWriter::create(&path, 2).unwrap();
+        for i in 0..5 {
+            let mut pgn = Pgn::new("game", i);
+            pgn.tags.insert("WhiteElo".to_string(), "2500".to_string());
+            pgn.moves = vec!["e4".to_string()];
+            writer.write(&pgn).unwrap();
+        }
+        writer.close().unwrap();
+
+        let reader = SerializedFileReader::new(File::open(&path).unwrap()).unwrap();
+        let metadata = reader.metadata();
+        assert_eq!(metadata.num_row_groups(), 3);
+        assert_eq!(metadata.file_metadata().num_rows(), 5);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
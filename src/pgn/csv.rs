@@ -0,0 +1,190 @@
+use std::io::Write;
+use std::str::FromStr;
+
+use simple_error::SimpleError;
+
+use super::Pgn;
+use crate::board::termination::analyze_termination;
+
+/// Column of a header table: a tag, or a value derived from the game.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Column {
+    Tag(String),
+    Id,
+    Plies,
+    Fingerprint,
+    TimeControlBase,
+    TimeControlIncrement,
+    TimeControlClass,
+    /// Space separated moves in UCI notation.
+    UciMoves,
+    /// How the game ended, see `analyze_termination`.
+    Termination,
+}
+
+impl Column {
+    pub fn name(&self) -> &str {
+        match self {
+            Column::Tag(name) => name.as_str(),
+            Column::Id => "id",
+            Column::Plies => "plies",
+            Column::Fingerprint => "fingerprint",
+            Column::TimeControlBase => "time_control_base",
+            Column::TimeControlIncrement => "time_control_increment",
+            Column::TimeControlClass => "time_control_class",
+            Column::UciMoves => "uci_moves",
+            Column::Termination => "termination",
+        }
+    }
+
+    pub fn value(&self, pgn: &Pgn) -> String {
+        match self {
+            Column::Tag(name) => pgn.tags.get(name).cloned().unwrap_or_default(),
+            Column::Id => pgn.id.clone(),
+            Column::Plies => pgn.moves.len().to_string(),
+            Column::Fingerprint => format!("{:016x}", pgn.moves_fingerprint),
+            Column::TimeControlBase => pgn
+                .time_control()
+                .and_then(|tc| tc.base())
+                .map(|base| base.to_string())
+                .unwrap_or_default(),
+            Column::TimeControlIncrement => pgn
+                .time_control()
+                .and_then(|tc| tc.increment())
+                .map(|increment| increment.to_string())
+                .unwrap_or_default(),
+            Column::TimeControlClass => pgn
+                .time_control()
+                .and_then(|tc| tc.class())
+                .map(|class| class.to_string())
+                .unwrap_or_default(),
+            Column::UciMoves => pgn
+                .uci_moves
+                .as_ref()
+                .map(|moves| moves.join(" "))
+                .unwrap_or_default(),
+            Column::Termination => analyze_termination(pgn)
+                .map(|analysis| analysis.termination.to_string())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+impl FromStr for Column {
+    type Err = SimpleError;
+
+    /// Derived columns are lower case, anything else names a tag.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "" => Err(SimpleError::new("empty column name")),
+            "id" => Ok(Column::Id),
+            "plies" => Ok(Column::Plies),
+            "fingerprint" => Ok(Column::Fingerprint),
+            "time_control_base" => Ok(Column::TimeControlBase),
+            "time_control_increment" => Ok(Column::TimeControlIncrement),
+            "time_control_class" => Ok(Column::TimeControlClass),
+            "uci_moves" => Ok(Column::UciMoves),
+            "termination" => Ok(Column::Termination),
+            name => Ok(Column::Tag(name.to_string())),
+        }
+    }
+}
+
+/// Parses a comma separated list of columns.
+pub fn parse_columns(list: &str) -> Result<Vec<Column>, SimpleError> {
+    list.split(',').map(Column::from_str).collect()
+}
+
+pub const DEFAULT_COLUMNS: &str =
+    "id,Event,Site,Date,Round,White,Black,Result,WhiteElo,BlackElo,ECO,plies";
+
+/// Writes one row of headers per game, as CSV (RFC 4180) or tab separated
+/// values quoted the same way.
+pub struct CsvWriter<W: Write> {
+    out: W,
+    columns: Vec<Column>,
+    delimiter: char,
+}
+
+impl<W: Write> CsvWriter<W> {
+    pub fn new(out: W, columns: Vec<Column>, delimiter: char) -> Self {
+        Self {
+            out,
+            columns,
+            delimiter,
+        }
+    }
+
+    pub fn write_header(&mut self) -> std::io::Result<()> {
+        let names: Vec<String> = self.columns.iter().map(|c| c.name().to_string()).collect();
+        self.write_record(&names)
+    }
+
+    pub fn write(&mut self, pgn: &Pgn) -> std::io::Result<()> {
+        let values: Vec<String> = self.columns.iter().map(|c| c.value(pgn)).collect();
+        self.write_record(&values)
+    }
+
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.out.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    fn write_record(&mut self, fields: &[String]) -> std::io::Result<()> {
+        for (i, field) in fields.iter().enumerate() {
+            if i > 0 {
+                write!(self.out, "{}", self.delimiter)?;
+            }
+            if field.contains([self.delimiter, '"', '\r', '\n']) {
+                write!(self.out, "\"{}\"", field.replace('"', "\"\""))?;
+            } else {
+                self.out.write_all(field.as_bytes())?;
+            }
+        }
+        // RFC 4180 asks for CRLF, tab separated files usually end lines
+        // with LF.
+        if self.delimiter == ',' {
+            self.out.write_all(b"\r\n")
+        } else {
+            self.out.write_all(b"\n")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quote_fields() {
+        let mut pgn = Pgn::new("game", 7);
+        pgn.tags
+            .insert("White".to_string(), "Carlsen, Magnus".to_string());
+        pgn.tags
+            .insert("Black".to_string(), "So \"Wesley\"".to_string());
+        pgn.tags
+            .insert("TimeControl".to_string(), "180+2".to_string());
+        pgn.moves = vec!["e4".to_string(), "e5".to_string()];
+
+        let columns = parse_columns("id,White,Black,Site,plies,time_control_class").unwrap();
+
+        let mut writer = CsvWriter::new(Vec::new(), columns.clone(), ',');
+        writer.write_header().unwrap();
+        writer.write(&pgn).unwrap();
+        assert_eq!(
+            String::from_utf8(writer.into_inner()).unwrap(),
+            "id,White,Black,Site,plies,time_control_class\r\n\
+            game.7,\"Carlsen, Magnus\",\"So \"\"Wesley\"\"\",,2,blitz\r\n"
+        );
+
+        let mut writer = CsvWriter::new(Vec::new(), columns, '\t');
+        writer.write(&pgn).unwrap();
+        assert_eq!(
+            String::from_utf8(writer.into_inner()).unwrap(),
+            "game.7\tCarlsen, Magnus\t\"So \"\"Wesley\"\"\"\t\t2\tblitz\n"
+        );
+    }
+}
@@ -5,6 +5,7 @@ const RE_INDEX: &str = r#"(?:(\d+)\.+)"#;
 const RE_CASTLE: &str = r#"(?:O-O(?:-O)?[\+\#]?)"#;
 const RE_SUFFIX: &str = r#"(?:[!?][!?]?)"#;
 const RE_ANNOTATION: &str = r#"(?:\{[^{]+\})"#;
+const RE_NAG: &str = r#"(?:\$\d+)"#;
 const RE_RESULT: &str = r#"((?:0-1)|(?:1-0)|(?:1/2-1/2)|\*)"#;
 
 pub struct Extractor {
@@ -15,10 +16,11 @@ pub struct Extractor {
 impl Default for Extractor {
     fn default() -> Self {
         let m = format!(
-            r#"(?:({san}|{castle}){suffix}?(?:\s*{annotation})?)"#,
+            r#"(?:({san}|{castle}){suffix}?(?:\s*(?:{nag}|{annotation}))*)"#,
             san = RE_SAN,
             castle = RE_CASTLE,
             suffix = RE_SUFFIX,
+            nag = RE_NAG,
             annotation = RE_ANNOTATION
         );
 
@@ -142,12 +144,18 @@ Bd4 c5 23. bxc6 Nxc6 24. Bxa7 Rxa7 25. Ndc4 Rd8 26. Qb3 Raa8 27. Rfd1 Kh8 28.
 Rab1 Rxd1+ 29. Qxd1 Rd8 30. Qb3 Ng6 31. Rb2 Rd7 32. Nb6 Rd8 33. Nbc4 Rd7 34. Nb6
 Rd8 35. Nbc4 Rd7 1/2-1/2"#;
 
-        let ex = ExtractMove::new();
+        let ex = Extractor::default();
         let opt = ex.extract(pgn);
         assert!(opt.is_some());
 
-        let (m, r) = opt.unwrap();
+        let (m, last_index, r) = opt.unwrap();
         assert_eq!(r, "1/2-1/2");
+        assert_eq!(last_index, 35);
         assert_eq!(m.len(), 35 * 2);
+
+        let annotated = "1. e4 $1 {good} e5 2. Qh5 $6 $14 {[%eval -0.40]} 2... g6 0-1";
+        let (m, last_index, _) = ex.extract(annotated).unwrap();
+        assert_eq!(m, vec!["e4", "e5", "Qh5", "g6"]);
+        assert_eq!(last_index, 2);
     }
 }
@@ -0,0 +1,329 @@
+use std::cmp::Ordering;
+use std::str::FromStr;
+
+use regex::Regex;
+use simple_error::SimpleError;
+
+use super::{Column, Pgn};
+
+/// Comparison of two values. Values that both parse as numbers are compared
+/// as numbers, others as strings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl CompareOp {
+    fn holds(&self, ordering: Ordering) -> bool {
+        match self {
+            CompareOp::Eq => ordering == Ordering::Equal,
+            CompareOp::Ne => ordering != Ordering::Equal,
+            CompareOp::Lt => ordering == Ordering::Less,
+            CompareOp::Le => ordering != Ordering::Greater,
+            CompareOp::Gt => ordering == Ordering::Greater,
+            CompareOp::Ge => ordering != Ordering::Less,
+        }
+    }
+}
+
+/// Operand of a comparison: a tag or derived value of the game, named as in
+/// `Column`, or a literal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operand {
+    Column(Column),
+    Literal(String),
+}
+
+impl Operand {
+    fn value(&self, pgn: &Pgn) -> String {
+        match self {
+            Operand::Column(column) => column.value(pgn),
+            Operand::Literal(value) => value.clone(),
+        }
+    }
+}
+
+/// Predicate on the tags and derived values of a game, e.g.
+/// `WhiteElo >= 2500 && Event ~ "Olympiad" && plies > 40`.
+///
+/// Comparisons are `==`, `!=`, `<`, `<=`, `>`, `>=`, and `~` or `!~` for a
+/// match or no match of a regular expression. A name alone holds when the
+/// game has a non-empty value for it. Predicates combine with `&&`, `||`,
+/// `!` and parentheses. Ordering comparisons do not hold when either value
+/// is missing.
+#[derive(Debug, Clone)]
+pub enum Filter {
+    Compare(Operand, CompareOp, Operand),
+    Matches(Operand, Regex),
+    Present(Column),
+    Not(Box<Filter>),
+    And(Box<Filter>, Box<Filter>),
+    Or(Box<Filter>, Box<Filter>),
+}
+
+impl Filter {
+    pub fn matches(&self, pgn: &Pgn) -> bool {
+        match self {
+            Filter::Compare(left, op, right) => {
+                let left = left.value(pgn);
+                let right = right.value(pgn);
+                let ordered = !matches!(op, CompareOp::Eq | CompareOp::Ne);
+                if ordered && (left.is_empty() || right.is_empty()) {
+                    return false;
+                }
+                let ordering = match (left.parse::<f64>(), right.parse::<f64>()) {
+                    (Ok(left), Ok(right)) => left.total_cmp(&right),
+                    _ => left.cmp(&right),
+                };
+                op.holds(ordering)
+            }
+            Filter::Matches(operand, regex) => regex.is_match(operand.value(pgn).as_str()),
+            Filter::Present(column) => !column.value(pgn).is_empty(),
+            Filter::Not(filter) => !filter.matches(pgn),
+            Filter::And(left, right) => left.matches(pgn) && right.matches(pgn),
+            Filter::Or(left, right) => left.matches(pgn) || right.matches(pgn),
+        }
+    }
+}
+
+impl FromStr for Filter {
+    type Err = SimpleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = FilterParser {
+            tokens: tokenize(s)?,
+            next: 0,
+        };
+        let filter = parser.or()?;
+        match parser.peek() {
+            None => Ok(filter),
+            Some(token) => Err(SimpleError::new(format!("unexpected {}", token))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum FilterToken {
+    Name(String),
+    String(String),
+    Number(String),
+    Symbol(&'static str),
+}
+
+impl std::fmt::Display for FilterToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FilterToken::Name(name) | FilterToken::Number(name) => write!(f, "{}", name),
+            FilterToken::String(value) => write!(f, "\"{}\"", value),
+            FilterToken::Symbol(symbol) => write!(f, "{}", symbol),
+        }
+    }
+}
+
+/// Operators, longest first so that `<=` is not read as `<`.
+const SYMBOLS: [&str; 13] = [
+    "&&", "||", "==", "!=", "<=", ">=", "!~", "<", ">", "~", "!", "(", ")",
+];
+
+fn tokenize(s: &str) -> Result<Vec<FilterToken>, SimpleError> {
+    let mut tokens: Vec<FilterToken> = Vec::new();
+    let mut rest = s.trim_start();
+
+    while let Some(c) = rest.chars().next() {
+        if c == '"' {
+            let mut value = String::new();
+            let mut chars = rest[1..].char_indices();
+            let end = loop {
+                match chars.next() {
+                    Some((i, '"')) => break i + 2,
+                    Some((_, '\\')) => match chars.next() {
+                        Some((_, escaped)) => value.push(escaped),
+                        None => return Err(SimpleError::new("unterminated string")),
+                    },
+                    Some((_, c)) => value.push(c),
+                    None => return Err(SimpleError::new("unterminated string")),
+                }
+            };
+            tokens.push(FilterToken::String(value));
+            rest = &rest[end..];
+        } else if c.is_ascii_digit() || c == '-' {
+            let end = rest[1..]
+                .find(|c: char| !c.is_ascii_digit() && c != '.')
+                .map_or(rest.len(), |i| i + 1);
+            tokens.push(FilterToken::Number(rest[..end].to_string()));
+            rest = &rest[end..];
+        } else if c.is_alphabetic() || c == '_' {
+            let end = rest
+                .find(|c: char| !c.is_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            tokens.push(FilterToken::Name(rest[..end].to_string()));
+            rest = &rest[end..];
+        } else if let Some(symbol) = SYMBOLS.iter().find(|symbol| rest.starts_with(**symbol)) {
+            tokens.push(FilterToken::Symbol(symbol));
+            rest = &rest[symbol.len()..];
+        } else {
+            return Err(SimpleError::new(format!("unexpected character: {}", c)));
+        }
+        rest = rest.trim_start();
+    }
+
+    Ok(tokens)
+}
+
+/// Recursive descent parser, `||` binding looser than `&&`, and `&&` looser
+/// than `!` and comparisons.
+struct FilterParser {
+    tokens: Vec<FilterToken>,
+    next: usize,
+}
+
+impl FilterParser {
+    fn peek(&self) -> Option<&FilterToken> {
+        self.tokens.get(self.next)
+    }
+
+    fn take(&mut self) -> Result<FilterToken, SimpleError> {
+        let token = self
+            .tokens
+            .get(self.next)
+            .cloned()
+            .ok_or_else(|| SimpleError::new("unexpected end of filter"))?;
+        self.next += 1;
+        Ok(token)
+    }
+
+    fn accept(&mut self, symbol: &'static str) -> bool {
+        let found = self.peek() == Some(&FilterToken::Symbol(symbol));
+        if found {
+            self.next += 1;
+        }
+        found
+    }
+
+    fn or(&mut self) -> Result<Filter, SimpleError> {
+        let mut filter = self.and()?;
+        while self.accept("||") {
+            filter = Filter::Or(Box::new(filter), Box::new(self.and()?));
+        }
+        Ok(filter)
+    }
+
+    fn and(&mut self) -> Result<Filter, SimpleError> {
+        let mut filter = self.unary()?;
+        while self.accept("&&") {
+            filter = Filter::And(Box::new(filter), Box::new(self.unary()?));
+        }
+        Ok(filter)
+    }
+
+    fn unary(&mut self) -> Result<Filter, SimpleError> {
+        if self.accept("!") {
+            return Ok(Filter::Not(Box::new(self.unary()?)));
+        }
+        if self.accept("(") {
+            let filter = self.or()?;
+            if !self.accept(")") {
+                return Err(SimpleError::new("missing )"));
+            }
+            return Ok(filter);
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Filter, SimpleError> {
+        let left = self.operand()?;
+        let op = match self.peek() {
+            Some(FilterToken::Symbol(symbol)) => *symbol,
+            _ => "",
+        };
+
+        let op = match op {
+            "==" => CompareOp::Eq,
+            "!=" => CompareOp::Ne,
+            "<" => CompareOp::Lt,
+            "<=" => CompareOp::Le,
+            ">" => CompareOp::Gt,
+            ">=" => CompareOp::Ge,
+            "~" | "!~" => {
+                self.next += 1;
+                let pattern = match self.take()? {
+                    FilterToken::String(pattern) => pattern,
+                    token => {
+                        return Err(SimpleError::new(format!(
+                            "expected a string after {}, found {}",
+                            op, token
+                        )))
+                    }
+                };
+                let regex = Regex::new(pattern.as_str())
+                    .map_err(|e| SimpleError::new(format!("bad regular expression: {}", e)))?;
+                let matches = Filter::Matches(left, regex);
+                return Ok(match op {
+                    "~" => matches,
+                    _ => Filter::Not(Box::new(matches)),
+                });
+            }
+            _ => {
+                return match left {
+                    Operand::Column(column) => Ok(Filter::Present(column)),
+                    Operand::Literal(value) => Err(SimpleError::new(format!(
+                        "expected a comparison after {}",
+                        value
+                    ))),
+                }
+            }
+        };
+        self.next += 1;
+
+        Ok(Filter::Compare(left, op, self.operand()?))
+    }
+
+    fn operand(&mut self) -> Result<Operand, SimpleError> {
+        match self.take()? {
+            FilterToken::Name(name) => Ok(Operand::Column(name.parse()?)),
+            FilterToken::String(value) | FilterToken::Number(value) => Ok(Operand::Literal(value)),
+            FilterToken::Symbol(symbol) => Err(SimpleError::new(format!("unexpected {}", symbol))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_match() {
+        let mut pgn = Pgn::new("game", 1);
+        pgn.tags.insert("WhiteElo".to_string(), "2612".to_string());
+        pgn.tags
+            .insert("Event".to_string(), "44th \"Chess\" Olympiad".to_string());
+        pgn.tags
+            .insert("Date".to_string(), "2022.08.01".to_string());
+        pgn.moves = vec!["e4".to_string(); 41];
+
+        let matches = |s: &str| s.parse::<Filter>().unwrap().matches(&pgn);
+        assert!(matches(
+            "WhiteElo >= 2500 && Event ~ \"Olympiad\" && plies > 40"
+        ));
+        assert!(!matches("WhiteElo >= 2500 && plies > 41"));
+        assert!(matches("WhiteElo<2500||plies==41"));
+        assert!(matches("Event == \"44th \\\"Chess\\\" Olympiad\""));
+        assert!(matches("Date >= \"2022.01.01\" && Date < \"2023\""));
+        assert!(matches("!(BlackElo > 0) && !BlackElo && WhiteElo"));
+        assert!(matches("Event !~ \"^Titled\" && -1 < plies"));
+        assert!(!matches("BlackElo < 2500"));
+        assert!(matches("BlackElo != 2500"));
+
+        assert!("WhiteElo >=".parse::<Filter>().is_err());
+        assert!("(WhiteElo > 1".parse::<Filter>().is_err());
+        assert!("Event ~ Site".parse::<Filter>().is_err());
+        assert!("Event ~ \"(\"".parse::<Filter>().is_err());
+        assert!("\"a\" \"b\"".parse::<Filter>().is_err());
+        assert!("WhiteElo = 1".parse::<Filter>().is_err());
+    }
+}
@@ -0,0 +1,243 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use super::annotation::{format_clock, parse_clock, parse_eval, Eval};
+use super::movetext::{mainline, tokenize, Ply};
+use super::writer::first_ply;
+use super::Pgn;
+use crate::board::Replay;
+
+/// Optional per-ply fields of `JsonGame`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonOptions {
+    pub fens: bool,
+    pub clocks: bool,
+    pub evals: bool,
+}
+
+/// A game as one JSON Lines record.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JsonGame {
+    pub id: String,
+    pub tags: BTreeMap<String, String>,
+    pub moves: Vec<String>,
+    /// `Pgn::moves_fingerprint` as 16 hex digits: JSON readers that parse
+    /// numbers as doubles would round it.
+    #[serde(with = "hex")]
+    pub fingerprint: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uci_moves: Option<Vec<String>>,
+    /// FEN after each ply.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fens: Option<Vec<String>>,
+    /// Remaining seconds after each ply, from `[%clk]` comments.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clocks: Option<Vec<Option<f64>>>,
+    /// Evaluation after each ply, from `[%eval]` comments.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub evals: Option<Vec<Option<Eval>>>,
+}
+
+impl JsonGame {
+    /// Fens are left out if the moves cannot be replayed.
+    pub fn new(pgn: &Pgn, options: JsonOptions) -> Self {
+        let plies = if options.clocks || options.evals {
+            mainline(&tokenize(pgn.moves_text.as_str()))
+        } else {
+            Vec::new()
+        };
+
+        Self {
+            id: pgn.id.clone(),
+            tags: pgn
+                .tags
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
+            moves: pgn.moves.clone(),
+            fingerprint: pgn.moves_fingerprint,
+            uci_moves: pgn.uci_moves.clone(),
+            fens: if options.fens {
+                Replay::new(pgn).ok().map(|replay| replay.fens())
+            } else {
+                None
+            },
+            clocks: if options.clocks {
+                Some(per_ply(&plies, parse_clock))
+            } else {
+                None
+            },
+            evals: if options.evals {
+                Some(per_ply(&plies, parse_eval))
+            } else {
+                None
+            },
+        }
+    }
+
+    /// Rebuilds the game, with clocks and evaluations as comments.
+    pub fn into_pgn(self) -> Pgn {
+        let mut tags_text = String::new();
+        for (name, value) in self.tags.iter() {
+            writeln!(
+                tags_text,
+                "[{} \"{}\"]",
+                name,
+                value.replace('\\', "\\\\").replace('"', "\\\"")
+            )
+            .unwrap();
+        }
+
+        let first_ply = first_ply(self.tags.get("FEN").map(|fen| fen.as_str()));
+        let mut moves_text = String::new();
+        for (ply, san) in self.moves.iter().enumerate() {
+            let game_ply = first_ply + ply;
+            if game_ply.is_multiple_of(2) {
+                write!(moves_text, "{}. ", game_ply / 2 + 1).unwrap();
+            } else if ply == 0 {
+                write!(moves_text, "{}... ", game_ply / 2 + 1).unwrap();
+            }
+            moves_text.push_str(san);
+
+            let mut commands: Vec<String> = Vec::new();
+            if let Some(Some(eval)) = self.evals.as_ref().and_then(|e| e.get(ply)) {
+                commands.push(format!("[%eval {}]", eval));
+            }
+            if let Some(Some(clock)) = self.clocks.as_ref().and_then(|c| c.get(ply)) {
+                commands.push(format!("[%clk {}]", format_clock(*clock)));
+            }
+            if !commands.is_empty() {
+                write!(moves_text, " {{ {} }}", commands.join(" ")).unwrap();
+            }
+            moves_text.push(' ');
+        }
+        moves_text.push_str(self.tags.get("Result").map_or("*", |r| r.as_str()));
+        moves_text.push('\n');
+
+        Pgn {
+            id: self.id,
+            tags: self.tags.into_iter().collect(),
+            moves: self.moves,
+            tags_text,
+            moves_text,
+            moves_fingerprint: self.fingerprint,
+            uci_moves: self.uci_moves,
+        }
+    }
+}
+
+mod hex {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(format!("{:016x}", value).as_str())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        u64::from_str_radix(s.as_str(), 16).map_err(D::Error::custom)
+    }
+}
+
+/// First value found by `parse` in the comments of each ply.
+fn per_ply<T>(plies: &[Ply], parse: fn(&str) -> Option<T>) -> Vec<Option<T>> {
+    plies
+        .iter()
+        .map(|ply| ply.comments.iter().find_map(|c| parse(c.as_str())))
+        .collect()
+}
+
+/// Serialises `pgn` as a single line of JSON.
+pub fn to_json_line(pgn: &Pgn, options: JsonOptions) -> String {
+    serde_json::to_string(&JsonGame::new(pgn, options)).unwrap()
+}
+
+/// Reads a game written by `to_json_line`.
+pub fn from_json_line(line: &str) -> Result<Pgn, serde_json::Error> {
+    serde_json::from_str::<JsonGame>(line).map(JsonGame::into_pgn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pgn::Parser;
+
+    #[test]
+    fn round_trip() {
+        let mut pgn = Parser::default()
+            .parse(
+                "game.1".to_string(),
+                "[White \"A \\\"B\\\"\"]\n[Result \"0-1\"]\n".to_string(),
+                "1. f3 { [%clk 0:03:00] } e5 { [%eval 0.3] [%clk 0:02:58] } 2. g4 Qh4# 0-1\n"
+                    .to_string(),
+            )
+            .unwrap();
+        pgn.fill_uci_moves().unwrap();
+
+        let options = JsonOptions {
+            fens: true,
+            clocks: true,
+            evals: true,
+        };
+        let line = to_json_line(&pgn, options);
+        assert!(!line.contains('\n'));
+
+        assert!(
+            line.contains(format!("\"fingerprint\":\"{:016x}\"", pgn.moves_fingerprint).as_str())
+        );
+
+        let game: JsonGame = serde_json::from_str(line.as_str()).unwrap();
+        assert_eq!(game.moves, vec!["f3", "e5", "g4", "Qh4#"]);
+        assert_eq!(
+            game.uci_moves,
+            Some(vec![
+                "f2f3".to_string(),
+                "e7e5".to_string(),
+                "g2g4".to_string(),
+                "d8h4".to_string()
+            ])
+        );
+        assert_eq!(
+            game.clocks,
+            Some(vec![Some(180.0), Some(178.0), None, None])
+        );
+        assert_eq!(game.evals, Some(vec![None, Some(Eval::Cp(30)), None, None]));
+        assert_eq!(
+            game.fens.as_ref().unwrap()[3],
+            "rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3"
+        );
+
+        let back = from_json_line(line.as_str()).unwrap();
+        assert_eq!(back.id, "game.1");
+        assert_eq!(back.tags, pgn.tags);
+        assert_eq!(back.moves, pgn.moves);
+        assert_eq!(back.moves_fingerprint, pgn.moves_fingerprint);
+        assert_eq!(back.uci_moves, pgn.uci_moves);
+        assert_eq!(JsonGame::new(&back, options), game);
+    }
+
+    #[test]
+    fn numbers_from_setup() {
+        let mut tags = BTreeMap::new();
+        tags.insert("Result".to_string(), "0-1".to_string());
+        tags.insert(
+            "FEN".to_string(),
+            "4k3/8/8/8/8/8/4q3/4K3 b - - 0 30".to_string(),
+        );
+        let game = JsonGame {
+            id: "puzzle.1".to_string(),
+            tags,
+            moves: vec!["Kd7".to_string(), "Kxe2".to_string(), "Ke6".to_string()],
+            fingerprint: 0,
+            uci_moves: None,
+            fens: None,
+            clocks: None,
+            evals: None,
+        };
+
+        let pgn = game.into_pgn();
+        assert_eq!(pgn.moves_text, "30... Kd7 31. Kxe2 Ke6 0-1\n");
+    }
+}
@@ -0,0 +1,77 @@
+use std::collections::HashMap;
+
+use super::{date_sort_key, Pgn};
+
+/// Removes copies of the same game, i.e. games with the same moves, players
+/// and date, keeping the richest copy of each at the place of the first.
+/// Returns the number of games removed.
+pub fn dedup(games: &mut Vec<Pgn>) -> usize {
+    let mut kept: HashMap<(u64, &str, &str, &str), usize> = HashMap::new();
+    let mut keep: Vec<usize> = Vec::with_capacity(games.len());
+
+    for (i, pgn) in games.iter().enumerate() {
+        let tag = |name: &str| pgn.tags.get(name).map_or("", |v| v.as_str());
+        let key = (
+            pgn.moves_fingerprint,
+            tag("White"),
+            tag("Black"),
+            tag("Date"),
+        );
+        match kept.get(&key) {
+            Some(&slot) => {
+                if pgn.richness() > games[keep[slot]].richness() {
+                    keep[slot] = i;
+                }
+            }
+            None => {
+                kept.insert(key, keep.len());
+                keep.push(i);
+            }
+        }
+    }
+
+    let removed = games.len() - keep.len();
+    let mut games_by_index: Vec<Option<Pgn>> = games.drain(..).map(Some).collect();
+    games.extend(keep.iter().filter_map(|&i| games_by_index[i].take()));
+    removed
+}
+
+/// Sorts games by their `Date` tag as the store does, see `date_sort_key`,
+/// keeping the order of games of the same date.
+pub fn sort_by_date(games: &mut [Pgn]) {
+    games.sort_by_cached_key(|pgn| date_sort_key(pgn.tags.get("Date").map(|v| v.as_str())));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dedup_and_sort() {
+        let game = |index: usize, date: &str, comments: &str| {
+            let mut pgn = Pgn::new("game", index);
+            pgn.set_tag("White", "ann");
+            pgn.set_tag("Date", date);
+            pgn.moves_fingerprint = 1;
+            pgn.moves_text = format!("1. e4 {} *", comments);
+            pgn
+        };
+        let mut games = vec![
+            game(1, "2024.02.01", ""),
+            game(2, "2023.05.01", ""),
+            game(3, "2024.02.01", "{ best }"),
+            game(4, "????.??.??", ""),
+            game(5, "2023.05.01", ""),
+            game(6, "", ""),
+            game(7, "2024.??.??", ""),
+        ];
+
+        assert_eq!(dedup(&mut games), 2);
+        let ids: Vec<&str> = games.iter().map(|pgn| pgn.id.as_str()).collect();
+        assert_eq!(ids, vec!["game.3", "game.2", "game.4", "game.6", "game.7"]);
+
+        sort_by_date(&mut games);
+        let ids: Vec<&str> = games.iter().map(|pgn| pgn.id.as_str()).collect();
+        assert_eq!(ids, vec!["game.2", "game.7", "game.3", "game.6", "game.4"]);
+    }
+}
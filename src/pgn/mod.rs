@@ -1,4 +1,37 @@
 use std::collections::HashMap;
+use std::hash::Hasher;
+use std::str::FromStr;
+
+use seahash::SeaHasher;
+use simple_error::SimpleError;
+
+use crate::board::Replay;
+
+// Tags identifying a game whichever file it came from.
+const CONTENT_ID_TAGS: [&str; 5] = ["White", "Black", "Date", "Round", "Result"];
+
+/// How the reader assigns `Pgn::id`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IdStrategy {
+    /// `{file stem}.{index in file}`.
+    #[default]
+    Positional,
+    /// Hash of the canonical tags and the move fingerprint, see
+    /// `Pgn::content_id`.
+    Content,
+}
+
+impl FromStr for IdStrategy {
+    type Err = SimpleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "positional" => Ok(IdStrategy::Positional),
+            "content" => Ok(IdStrategy::Content),
+            _ => Err(SimpleError::new(format!("unknown id strategy: {}", s))),
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct Pgn {
@@ -8,6 +41,9 @@ pub struct Pgn {
     pub tags_text: String,
     pub moves_text: String,
     pub moves_fingerprint: u64,
+    /// Moves in UCI notation, if requested from the reader or filled by
+    /// `Pgn::fill_uci_moves`.
+    pub uci_moves: Option<Vec<String>>,
 }
 
 impl Pgn {
@@ -19,11 +55,263 @@ impl Pgn {
             moves_text: String::new(),
             moves: Vec::new(),
             moves_fingerprint: 0,
+            uci_moves: None,
         }
     }
+
+    /// Sets `uci_moves` by replaying the moves on a board.
+    pub fn fill_uci_moves(&mut self) -> Result<(), SimpleError> {
+        self.uci_moves = Some(Replay::new(self)?.uci_moves());
+        Ok(())
+    }
+
+    /// Sets a tag in both `tags` and `tags_text`, replacing the line of the
+    /// tag if present.
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        let line = format!(
+            "[{} \"{}\"]",
+            name,
+            value.replace('\\', "\\\\").replace('"', "\\\"")
+        );
+
+        let mut found = false;
+        let mut tags_text = String::with_capacity(self.tags_text.len() + line.len() + 1);
+        for l in self.tags_text.lines() {
+            if is_tag_line(l, name) {
+                if !found {
+                    tags_text.push_str(line.as_str());
+                    tags_text.push('\n');
+                }
+                found = true;
+            } else {
+                tags_text.push_str(l);
+                tags_text.push('\n');
+            }
+        }
+        if !found {
+            tags_text.push_str(line.as_str());
+            tags_text.push('\n');
+        }
+
+        self.tags_text = tags_text;
+        self.tags.insert(name.to_string(), value.to_string());
+    }
+
+    /// Removes a tag from both `tags` and `tags_text`.
+    pub fn remove_tag(&mut self, name: &str) {
+        let mut tags_text = String::with_capacity(self.tags_text.len());
+        for l in self.tags_text.lines().filter(|l| !is_tag_line(l, name)) {
+            tags_text.push_str(l);
+            tags_text.push('\n');
+        }
+
+        self.tags_text = tags_text;
+        self.tags.remove(name);
+    }
+
+    /// Parses the `TimeControl` tag, if present and well formed.
+    pub fn time_control(&self) -> Option<TimeControl> {
+        self.tags.get("TimeControl")?.parse().ok()
+    }
+
+    /// Id derived from the players, date, round, result and moves of the
+    /// game, so that the same game gets the same id in any file.
+    pub fn content_id(&self) -> String {
+        let mut hasher = SeaHasher::new();
+
+        for name in CONTENT_ID_TAGS.iter() {
+            let value = self.tags.get(*name).map_or("", |v| v.as_str());
+            for word in value.split_whitespace() {
+                hasher.write(word.as_bytes());
+                hasher.write_u8(b' ');
+            }
+            hasher.write_u8(0);
+        }
+        hasher.write_u64(self.moves_fingerprint);
+
+        format!("{:016x}", hasher.finish())
+    }
+
+    /// How much information the game carries besides its moves; used to pick
+    /// which copy to keep when deduplicating.
+    pub fn richness(&self) -> usize {
+        richness(self.tags_text.as_str(), self.moves_text.as_str())
+    }
+}
+
+fn is_tag_line(line: &str, name: &str) -> bool {
+    line.trim_start()
+        .strip_prefix('[')
+        .and_then(|rest| rest.trim_start().strip_prefix(name))
+        .is_some_and(|rest| rest.starts_with(char::is_whitespace))
+}
+
+/// Number of tags plus number of comments in the raw text of a game.
+pub fn richness(tags_text: &str, moves_text: &str) -> usize {
+    let tags = tags_text.lines().filter(|l| !l.trim().is_empty()).count();
+    let comments = moves_text.chars().filter(|&c| c == '{' || c == ';').count();
+
+    tags + comments
+}
+
+/// Index of the copy to keep among copies of a game of the given richness:
+/// the richest, or the first of the richest.
+pub fn richest<I: IntoIterator<Item = usize>>(richness: I) -> Option<usize> {
+    richness
+        .into_iter()
+        .enumerate()
+        .max_by_key(|(i, richness)| (*richness, std::cmp::Reverse(*i)))
+        .map(|(i, _)| i)
+}
+
+/// Sort key of a `Date` tag value: dates without a known year, including
+/// missing and blank ones, sort last in either direction, and unknown months
+/// and days count as `00`, so that `2024.??.??` sorts before `2024.01.05`.
+pub fn date_sort_key(date: Option<&str>) -> (bool, String) {
+    let date = date.map_or("", |date| date.trim());
+    (
+        date.is_empty() || date.starts_with('?'),
+        date.replace('?', "0"),
+    )
 }
 
+/// The first part of `date_sort_key` over the `date` column of the store.
+pub(crate) const DATE_UNKNOWN_SQL: &str = "(btrim(date) = '' OR btrim(date) LIKE '?%')";
+
+/// The second part of `date_sort_key` over the `date` column of the store.
+pub(crate) const DATE_ORDER_SQL: &str = "translate(btrim(date), '?', '0')";
+
+pub mod annotation;
+
+#[cfg(feature = "parquet")]
+mod columnar;
+#[cfg(feature = "parquet")]
+pub use columnar::{ParquetWriter, DEFAULT_ROW_GROUP_SIZE};
+
+mod csv;
+pub use self::csv::{parse_columns, Column, CsvWriter, DEFAULT_COLUMNS};
+
+mod json;
+pub use json::{from_json_line, to_json_line, JsonGame, JsonOptions};
+
+mod filter;
+pub use filter::{CompareOp, Filter, Operand};
+
+pub mod merge;
+
+pub mod movetext;
+
+mod output;
+pub use output::{create_output, Output};
+
+mod parser;
+pub use parser::Parser;
+
 mod reader;
 pub use reader::{ReadOutcome, Reader};
 
+pub mod sequence;
+
+mod split;
+pub use split::{SplitBy, Splitter};
+
+mod writer;
+pub use writer::Writer;
+
+mod time_control;
+pub use time_control::{TimeControl, TimeControlClass, TimeControlPeriod};
+
 pub(crate) mod extractor;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(tags_text: &str, moves_text: &str) -> Pgn {
+        Parser::default()
+            .parse(
+                "game.1".to_string(),
+                tags_text.to_string(),
+                moves_text.to_string(),
+            )
+            .unwrap()
+    }
+
+    #[test]
+    fn content_id() {
+        let game = parse(
+            "[White \"Carlsen, Magnus\"]\n[Black \"Caruana, Fabiano\"]\n[Date \"2024.01.05\"]\n[Result \"1-0\"]\n",
+            "1. e4 e5 2. Nf3 1-0\n",
+        );
+        let reordered = parse(
+            "[Result \"1-0\"]\n[Date \"2024.01.05\"]\n[Black \"Caruana,  Fabiano \"]\n[Event \"Blitz\"]\n[White \" Carlsen, Magnus\"]\n",
+            "1. e4  e5\n2. Nf3 {a comment} 1-0\n",
+        );
+        assert_eq!(game.content_id(), reordered.content_id());
+        assert_eq!(game.content_id().len(), 16);
+
+        let other_moves = parse(
+            "[White \"Carlsen, Magnus\"]\n[Black \"Caruana, Fabiano\"]\n[Date \"2024.01.05\"]\n[Result \"1-0\"]\n",
+            "1. e4 e5 2. Nc3 1-0\n",
+        );
+        let other_round = parse(
+            "[White \"Carlsen, Magnus\"]\n[Black \"Caruana, Fabiano\"]\n[Date \"2024.01.05\"]\n[Round \"2\"]\n[Result \"1-0\"]\n",
+            "1. e4 e5 2. Nf3 1-0\n",
+        );
+        let swapped = parse(
+            "[White \"Caruana, Fabiano\"]\n[Black \"Carlsen, Magnus\"]\n[Date \"2024.01.05\"]\n[Result \"1-0\"]\n",
+            "1. e4 e5 2. Nf3 1-0\n",
+        );
+        for other in [&other_moves, &other_round, &swapped] {
+            assert_ne!(game.content_id(), other.content_id());
+        }
+    }
+
+    #[test]
+    fn richest_copy() {
+        let bare = parse("[White \"A\"]\n[Result \"*\"]\n", "1. e4 e5 *\n");
+        let annotated = parse(
+            "[White \"A\"]\n[Result \"*\"]\n[Event \"Open\"]\n",
+            "1. e4 {best by test} e5 ; main line\n*\n",
+        );
+        assert_eq!(bare.richness(), 2);
+        assert_eq!(annotated.richness(), 5);
+
+        assert_eq!(richest([bare.richness(), annotated.richness()]), Some(1));
+        assert_eq!(richest([5, 2, 5]), Some(0));
+        assert_eq!(richest([]), None);
+    }
+
+    #[test]
+    fn id_strategies() {
+        assert_eq!(
+            "positional".parse::<IdStrategy>().unwrap(),
+            IdStrategy::Positional
+        );
+        assert_eq!(
+            "content".parse::<IdStrategy>().unwrap(),
+            IdStrategy::Content
+        );
+        assert_eq!(IdStrategy::default(), IdStrategy::Positional);
+        assert!("hash".parse::<IdStrategy>().is_err());
+    }
+
+    #[test]
+    fn date_order() {
+        let mut dates = [
+            Some("2024.01.05"),
+            Some(""),
+            Some("2024.??.??"),
+            None,
+            Some("????.??.??"),
+            Some("2023.12.31"),
+            Some("  "),
+        ];
+        dates.sort_by_key(|date| date_sort_key(*date));
+        assert_eq!(
+            &dates[..3],
+            &[Some("2023.12.31"), Some("2024.??.??"), Some("2024.01.05")]
+        );
+        assert!(dates[3..].iter().all(|date| date_sort_key(*date).0));
+    }
+}
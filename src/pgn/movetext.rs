@@ -0,0 +1,317 @@
+use std::ops::Range;
+
+/// A lexical element of PGN move text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token {
+    /// Move number indication, e.g. `12.` or `12...`; the flag is set for the
+    /// latter form.
+    MoveNumber(usize, bool),
+    San(String),
+    /// Numeric annotation glyph; `!`, `?` and their combinations are
+    /// converted to NAGs 1 to 6.
+    Nag(u8),
+    Comment(String),
+    VariationStart,
+    VariationEnd,
+    Result(String),
+}
+
+/// A move of the main line with the NAGs and comments that follow it.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Ply {
+    pub san: String,
+    pub nags: Vec<u8>,
+    pub comments: Vec<String>,
+}
+
+/// Moves of the main line, skipping variations. Comments before the first
+/// move are dropped.
+pub fn mainline(tokens: &[Token]) -> Vec<Ply> {
+    let mut plies: Vec<Ply> = Vec::new();
+    let mut depth: usize = 0;
+
+    for token in tokens {
+        match token {
+            Token::VariationStart => depth += 1,
+            Token::VariationEnd => depth = depth.saturating_sub(1),
+            _ if depth > 0 => {}
+            Token::San(san) => plies.push(Ply {
+                san: san.clone(),
+                ..Ply::default()
+            }),
+            Token::Nag(nag) => {
+                if let Some(ply) = plies.last_mut() {
+                    ply.nags.push(*nag);
+                }
+            }
+            Token::Comment(comment) => {
+                if let Some(ply) = plies.last_mut() {
+                    ply.comments.push(comment.clone());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    plies
+}
+
+/// Move text of `tokens` on a single line.
+pub fn to_text(tokens: &[Token]) -> String {
+    let mut text = String::new();
+    for token in tokens {
+        let word = match token {
+            Token::MoveNumber(number, false) => format!("{}.", number),
+            Token::MoveNumber(number, true) => format!("{}...", number),
+            Token::San(san) => san.clone(),
+            Token::Nag(nag) => format!("${}", nag),
+            Token::Comment(comment) => format!("{{{}}}", comment.replace('}', "")),
+            Token::VariationStart => "(".to_string(),
+            Token::VariationEnd => ")".to_string(),
+            Token::Result(result) => result.clone(),
+        };
+        if !text.is_empty() && !text.ends_with('(') && *token != Token::VariationEnd {
+            text.push(' ');
+        }
+        text.push_str(word.as_str());
+    }
+
+    text
+}
+
+const RESULTS: [&str; 4] = ["1-0", "0-1", "1/2-1/2", "*"];
+
+/// Splits move text into tokens. Unrecognised characters are skipped.
+pub fn tokenize(text: &str) -> Vec<Token> {
+    tokenize_spans(text)
+        .into_iter()
+        .map(|(token, _)| token)
+        .collect()
+}
+
+/// `tokenize`, with the byte range of `text` each token was read from.
+pub fn tokenize_spans(text: &str) -> Vec<(Token, Range<usize>)> {
+    let mut tokens: Vec<(Token, Range<usize>)> = Vec::new();
+    let chars: Vec<char> = text.chars().collect();
+    // Byte offset of each char, and of the end of the text.
+    let offsets: Vec<usize> = text
+        .char_indices()
+        .map(|(offset, _)| offset)
+        .chain(std::iter::once(text.len()))
+        .collect();
+    let mut i = 0;
+    let mut line_start = true;
+
+    while i < chars.len() {
+        let c = chars[i];
+
+        // Escape mechanism: a line starting with `%` is ignored.
+        if line_start && c == '%' {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+            continue;
+        }
+        line_start = c == '\n';
+
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        let start = i;
+        match c {
+            '{' => {
+                i += 1;
+                while i < chars.len() && chars[i] != '}' {
+                    i += 1;
+                }
+                let comment: String = chars[start + 1..i].iter().collect();
+                i = (i + 1).min(chars.len());
+                tokens.push((
+                    Token::Comment(comment.trim().to_string()),
+                    offsets[start]..offsets[i],
+                ));
+            }
+            ';' => {
+                i += 1;
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+                let comment: String = chars[start + 1..i].iter().collect();
+                tokens.push((
+                    Token::Comment(comment.trim().to_string()),
+                    offsets[start]..offsets[i],
+                ));
+            }
+            '(' => {
+                i += 1;
+                tokens.push((Token::VariationStart, offsets[start]..offsets[i]));
+            }
+            ')' => {
+                i += 1;
+                tokens.push((Token::VariationEnd, offsets[start]..offsets[i]));
+            }
+            '$' => {
+                i += 1;
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    i += 1;
+                }
+                let nag: String = chars[start + 1..i].iter().collect();
+                if let Ok(nag) = nag.parse::<u8>() {
+                    tokens.push((Token::Nag(nag), offsets[start]..offsets[i]));
+                }
+            }
+            '!' | '?' => {
+                while i < chars.len() && (chars[i] == '!' || chars[i] == '?') {
+                    i += 1;
+                }
+                let suffix: String = chars[start..i].iter().collect();
+                if let Some(nag) = suffix_to_nag(suffix.as_str()) {
+                    tokens.push((Token::Nag(nag), offsets[start]..offsets[i]));
+                }
+            }
+            _ => {
+                while i < chars.len()
+                    && !chars[i].is_whitespace()
+                    && !matches!(chars[i], '{' | '}' | ';' | '(' | ')' | '$' | '!' | '?')
+                {
+                    i += 1;
+                }
+                push_word(
+                    &mut tokens,
+                    &text[offsets[start]..offsets[i]],
+                    offsets[start],
+                );
+                if i == start {
+                    i += 1;
+                }
+            }
+        }
+    }
+
+    tokens
+}
+
+/// Pushes the tokens of `word`, found at byte `offset`.
+fn push_word(tokens: &mut Vec<(Token, Range<usize>)>, word: &str, offset: usize) {
+    if word.is_empty() {
+        return;
+    }
+    let span = offset..offset + word.len();
+
+    if RESULTS.contains(&word) {
+        tokens.push((Token::Result(word.to_string()), span));
+        return;
+    }
+
+    let digits = word.chars().take_while(|c| c.is_ascii_digit()).count();
+    if digits > 0 {
+        let (number, rest) = word.split_at(digits);
+        let dots = rest.chars().take_while(|&c| c == '.').count();
+        if dots > 0 || rest.is_empty() {
+            tokens.push((
+                Token::MoveNumber(number.parse().unwrap_or(0), dots >= 3),
+                offset..offset + digits + dots,
+            ));
+            push_word(tokens, &rest[dots..], offset + digits + dots);
+            return;
+        }
+    }
+
+    // Some files write castling with zeros.
+    let san = match word {
+        "0-0" => "O-O".to_string(),
+        "0-0-0" => "O-O-O".to_string(),
+        _ => word.to_string(),
+    };
+    tokens.push((Token::San(san), span));
+}
+
+pub fn suffix_to_nag(suffix: &str) -> Option<u8> {
+    match suffix {
+        "!" => Some(1),
+        "?" => Some(2),
+        "!!" => Some(3),
+        "??" => Some(4),
+        "!?" => Some(5),
+        "?!" => Some(6),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mainline_plies() {
+        let plies = mainline(&tokenize("1. e4 $1 (1. d4 {no}) e5 {[%clk 0:03:00]} 1-0"));
+
+        assert_eq!(
+            plies,
+            vec![
+                Ply {
+                    san: "e4".to_string(),
+                    nags: vec![1],
+                    comments: vec![],
+                },
+                Ply {
+                    san: "e5".to_string(),
+                    nags: vec![],
+                    comments: vec!["[%clk 0:03:00]".to_string()],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn tokens_to_text() {
+        let text = "1. e4 $1 {best} (1. d4) 1... e5 1-0";
+        assert_eq!(to_text(&tokenize(text)), text);
+    }
+
+    #[test]
+    fn token_spans() {
+        let text = "12.Nf3!? {déjà vu}\n(12. d4) *";
+        let spans: Vec<&str> = tokenize_spans(text)
+            .into_iter()
+            .map(|(_, span)| &text[span])
+            .collect();
+        assert_eq!(
+            spans,
+            vec!["12.", "Nf3", "!?", "{déjà vu}", "(", "12.", "d4", ")", "*"]
+        );
+    }
+
+    #[test]
+    fn tokenize_movetext() {
+        let tokens = tokenize(
+            "1. e4 {best by test} e5 2. Nf3!? $14 (2. f4 exf4; gambit\n) 2... Nc6 0-0 1-0",
+        );
+
+        assert_eq!(
+            tokens,
+            vec![
+                Token::MoveNumber(1, false),
+                Token::San("e4".to_string()),
+                Token::Comment("best by test".to_string()),
+                Token::San("e5".to_string()),
+                Token::MoveNumber(2, false),
+                Token::San("Nf3".to_string()),
+                Token::Nag(5),
+                Token::Nag(14),
+                Token::VariationStart,
+                Token::MoveNumber(2, false),
+                Token::San("f4".to_string()),
+                Token::San("exf4".to_string()),
+                Token::Comment("gambit".to_string()),
+                Token::VariationEnd,
+                Token::MoveNumber(2, true),
+                Token::San("Nc6".to_string()),
+                Token::San("O-O".to_string()),
+                Token::Result("1-0".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,104 @@
+use bzip2::write::BzEncoder;
+use bzip2::Compression;
+use std::fs::File;
+use std::io::{BufWriter, StdoutLock, Write};
+use std::path::Path;
+
+/// Destination of written games: a file, compressed or not, or the standard
+/// output. Compressed streams are only complete once `finish` returns.
+pub enum Output {
+    File(BufWriter<File>),
+    Bzip2(BufWriter<BzEncoder<File>>),
+    Zstd(BufWriter<zstd::Encoder<'static, File>>),
+    Stdout(StdoutLock<'static>),
+}
+
+/// Creates `path` for writing, compressed with bzip2 or zstd when its
+/// extension is `bz2` or `zst`.
+pub fn create_output(path: &Path) -> std::io::Result<Output> {
+    let f = File::create(path)?;
+
+    Output::from_file(path, f)
+}
+
+impl Output {
+    pub fn stdout() -> Self {
+        Output::Stdout(std::io::stdout().lock())
+    }
+
+    /// Wraps `f`, opened for writing at `path`, compressed as the extension
+    /// of `path` says.
+    pub(crate) fn from_file(path: &Path, f: File) -> std::io::Result<Self> {
+        Ok(match path.extension().and_then(|e| e.to_str()) {
+            Some("bz2") => Output::Bzip2(BufWriter::new(BzEncoder::new(f, Compression::default()))),
+            Some("zst") => Output::Zstd(BufWriter::new(zstd::Encoder::new(f, 0)?)),
+            _ => Output::File(BufWriter::new(f)),
+        })
+    }
+
+    /// Flushes the buffered data and ends the compressed stream, reporting
+    /// errors that dropping the output would ignore.
+    pub fn finish(self) -> std::io::Result<()> {
+        match self {
+            Output::File(mut out) => out.flush(),
+            Output::Bzip2(out) => out
+                .into_inner()
+                .map_err(|e| e.into_error())?
+                .finish()
+                .map(|_| ()),
+            Output::Zstd(out) => out
+                .into_inner()
+                .map_err(|e| e.into_error())?
+                .finish()
+                .map(|_| ()),
+            Output::Stdout(mut out) => out.flush(),
+        }
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Output::File(out) => out.write(buf),
+            Output::Bzip2(out) => out.write(buf),
+            Output::Zstd(out) => out.write(buf),
+            Output::Stdout(out) => out.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Output::File(out) => out.flush(),
+            Output::Bzip2(out) => out.flush(),
+            Output::Zstd(out) => out.flush(),
+            Output::Stdout(out) => out.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pgn::{ReadOutcome, Reader};
+
+    #[test]
+    fn compressed_round_trip() {
+        let dir = std::env::temp_dir().join(format!("mudfish-output-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        for name in ["games.pgn", "games.pgn.bz2", "games.pgn.zst"] {
+            let path = dir.join(name);
+            let mut out = create_output(&path).unwrap();
+            out.write_all(b"[White \"A\"]\n[Result \"1-0\"]\n\n1. e4 1-0\n\n")
+                .unwrap();
+            out.finish().unwrap();
+
+            let mut reader = Reader::new(&path).unwrap();
+            match reader.read_next() {
+                ReadOutcome::Game(pgn) => assert_eq!(pgn.moves, vec!["e4"]),
+                outcome => panic!("{}: {:?}", name, outcome),
+            }
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
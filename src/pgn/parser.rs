@@ -0,0 +1,120 @@
+use regex::Regex;
+use seahash::SeaHasher;
+use std::hash::Hasher;
+
+use super::extractor::Extractor;
+use super::Pgn;
+
+/// Parses tag lines and validates the move text of games.
+pub struct Parser {
+    re_tag: Regex,
+    extractor: Extractor,
+}
+
+impl Default for Parser {
+    fn default() -> Self {
+        Self {
+            re_tag: Regex::new(r#"\[([[:word:]]+)\s+"((?:[^"\\]|\\.)*)"\]"#).unwrap(),
+            extractor: Extractor::default(),
+        }
+    }
+}
+
+impl Parser {
+    /// Returns the name and unescaped value of a tag pair line.
+    pub fn parse_tag(&self, line: &str) -> Option<(String, String)> {
+        self.re_tag
+            .captures(line)
+            .map(|caps| (caps[1].to_string(), unescape(&caps[2])))
+    }
+
+    /// Builds a game from the texts of its tag pairs and move text, as
+    /// stored in `Pgn::tags_text` and `Pgn::moves_text`.
+    pub fn parse(&self, id: String, tags_text: String, moves_text: String) -> Result<Pgn, String> {
+        let mut pgn = Pgn {
+            id,
+            tags: tags_text
+                .lines()
+                .filter_map(|line| self.parse_tag(line.trim()))
+                .collect(),
+            moves: Vec::new(),
+            tags_text,
+            moves_text,
+            moves_fingerprint: 0,
+            uci_moves: None,
+        };
+
+        self.validate(&mut pgn)?;
+        Ok(pgn)
+    }
+
+    /// Checks the result and extracts the moves of `pgn`.
+    pub fn validate(&self, pgn: &mut Pgn) -> Result<(), String> {
+        let result_tag = pgn
+            .tags
+            .get("Result")
+            .ok_or_else(|| "missing result tag".to_string())?;
+
+        if result_tag != "1-0"
+            && result_tag != "0-1"
+            && result_tag != "1/2-1/2"
+            && result_tag != "*"
+        {
+            return Err(format!("bad result tag ({})", result_tag));
+        }
+
+        let (moves, last_index, result) = self
+            .extractor
+            .extract(pgn.moves_text.as_str())
+            .ok_or_else(|| "cannot extract move list".to_string())?;
+
+        if &result != result_tag {
+            return Err(format!(
+                "result tag ({}) != result sentinel ({})",
+                result_tag, result
+            ));
+        }
+
+        if last_index * 2 != moves.len() && last_index * 2 - 1 != moves.len() {
+            return Err(format!(
+                "last move index == {}, but # of moves (white + black) == {}",
+                last_index,
+                moves.len()
+            ));
+        }
+
+        pgn.moves = moves;
+        pgn.moves_fingerprint = moves_fingerprint(&pgn.moves);
+
+        Ok(())
+    }
+}
+
+fn unescape(value: &str) -> String {
+    if !value.contains('\\') {
+        return value.to_string();
+    }
+
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            if let Some(escaped) = chars.next() {
+                unescaped.push(escaped);
+            }
+        } else {
+            unescaped.push(c);
+        }
+    }
+    unescaped
+}
+
+fn moves_fingerprint(moves: &[String]) -> u64 {
+    let mut hasher = SeaHasher::new();
+
+    for m in moves.iter() {
+        hasher.write(m.as_bytes());
+    }
+
+    hasher.finish()
+}
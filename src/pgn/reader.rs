@@ -1,14 +1,12 @@
-use bzip2::read::BzDecoder;
-use regex::Regex;
-use seahash::SeaHasher;
+use bzip2::read::MultiBzDecoder;
 use std::fs::File;
-use std::hash::Hasher;
 use std::io::BufRead;
 use std::io::BufReader;
 use std::path::Path;
 
-use super::extractor::Extractor;
-use super::Pgn;
+use super::parser::Parser;
+use super::{IdStrategy, Pgn};
+use crate::eco::Classifier;
 
 #[derive(PartialEq, Debug)]
 enum ReaderState {
@@ -22,11 +20,13 @@ pub struct Reader {
     prefix: String,
     buf: Box<dyn BufRead>,
     state: ReaderState,
-    re_tag: Regex,
     line_number: usize,
     count: usize,
     last_pgn: Option<Pgn>,
-    extractor: Extractor,
+    parser: Parser,
+    id_strategy: IdStrategy,
+    uci_moves: bool,
+    classifier: Option<(Classifier, bool)>,
 }
 
 #[derive(Debug)]
@@ -38,13 +38,16 @@ pub enum ReadOutcome {
 }
 
 impl Reader {
+    /// Opens `path` for reading, decompressing it with bzip2 or zstd when its
+    /// extension is `bz2` or `zst`. Compressed files can be several streams
+    /// one after the other, e.g. written by parallel compressors.
     pub fn new(path: &Path) -> std::io::Result<Self> {
         let f = File::open(path)?;
 
-        let buf: Box<dyn BufRead> = if path.extension().unwrap() == "bz2" {
-            Box::new(BufReader::new(BzDecoder::new(f)))
-        } else {
-            Box::new(BufReader::new(f))
+        let buf: Box<dyn BufRead> = match path.extension().and_then(|e| e.to_str()) {
+            Some("bz2") => Box::new(BufReader::new(MultiBzDecoder::new(f))),
+            Some("zst") => Box::new(BufReader::new(zstd::Decoder::new(f)?)),
+            _ => Box::new(BufReader::new(f)),
         };
 
         let prefix: String = path
@@ -61,14 +64,34 @@ impl Reader {
             buf,
             prefix,
             state: ReaderState::Start,
-            re_tag: Regex::new(r#"\[([[:word:]]+)\s+"([^"]*)"\]"#).unwrap(),
             line_number: 0,
             count: 0,
             last_pgn: None,
-            extractor: Extractor::default(),
+            parser: Parser::default(),
+            id_strategy: IdStrategy::default(),
+            uci_moves: false,
+            classifier: None,
         })
     }
 
+    pub fn with_id_strategy(mut self, id_strategy: IdStrategy) -> Self {
+        self.id_strategy = id_strategy;
+        self
+    }
+
+    /// Fills `Pgn::uci_moves`; games whose moves cannot be replayed are
+    /// then reported as bad.
+    pub fn with_uci_moves(mut self, uci_moves: bool) -> Self {
+        self.uci_moves = uci_moves;
+        self
+    }
+
+    /// Fills missing opening tags with `classifier`, see `Classifier::apply`.
+    pub fn with_classifier(mut self, classifier: Classifier, overwrite: bool) -> Self {
+        self.classifier = Some((classifier, overwrite));
+        self
+    }
+
     pub fn read_next(&mut self) -> ReadOutcome {
         if self.state == ReaderState::Ended {
             return ReadOutcome::Ended;
@@ -119,15 +142,13 @@ impl Reader {
                 continue;
             }
 
-            if let Some(caps) = self.re_tag.captures(trimmed) {
+            if let Some((name, value)) = self.parser.parse_tag(trimmed) {
                 match self.state {
                     ReaderState::Moves => {
                         self.state = ReaderState::Tags;
                         self.count += 1;
                         let mut new_pgn = Pgn::new(self.prefix.as_str(), self.count);
-                        new_pgn
-                            .tags
-                            .insert(caps[1].to_string(), caps[2].to_string());
+                        new_pgn.tags.insert(name, value);
                         new_pgn.tags_text.push_str(trimmed);
                         new_pgn.tags_text.push('\n');
                         self.last_pgn = Some(new_pgn);
@@ -138,7 +159,7 @@ impl Reader {
                         self.state = ReaderState::Tags;
                         pgn.tags_text.push_str(trimmed);
                         pgn.tags_text.push('\n');
-                        pgn.tags.insert(caps[1].to_string(), caps[2].to_string());
+                        pgn.tags.insert(name, value);
                         continue;
                     }
                 }
@@ -166,67 +187,31 @@ impl Reader {
     }
 
     fn badpgn(&self, pgn: &Pgn, message: String) -> ReadOutcome {
-        return ReadOutcome::BadPgn(format!(
+        ReadOutcome::BadPgn(format!(
             "Line {}: invalid pgn: {}\n{}\n{}\n",
             self.line_number, message, pgn.tags_text, pgn.moves_text
-        ));
+        ))
     }
 
     fn postprocess(&self, mut pgn: Pgn) -> ReadOutcome {
-        let result_tag_opt = pgn.tags.get("Result");
-        if result_tag_opt.is_none() {
-            return self.badpgn(&pgn, "missing result tag".to_string());
-        }
-
-        let result_tag = result_tag_opt.unwrap();
-        if result_tag != "1-0"
-            && result_tag != "0-1"
-            && result_tag != "1/2-1/2"
-            && result_tag != "*"
-        {
-            return self.badpgn(&pgn, format!("bad result tag ({})", result_tag));
+        if let Err(message) = self.parser.validate(&mut pgn) {
+            return self.badpgn(&pgn, message);
         }
 
-        let extracted = self.extractor.extract(pgn.moves_text.as_str());
-        if extracted.is_none() {
-            return self.badpgn(&pgn, "cannot extract move list".to_string());
+        if self.uci_moves {
+            if let Err(e) = pgn.fill_uci_moves() {
+                return self.badpgn(&pgn, e.to_string());
+            }
         }
 
-        let (moves, last_index, result) = extracted.unwrap();
-        if &result != result_tag {
-            return self.badpgn(
-                &pgn,
-                format!(
-                    "result tag ({}) != result sentinel ({})",
-                    result_tag, result
-                ),
-            );
+        if let Some((classifier, overwrite)) = &self.classifier {
+            classifier.apply(&mut pgn, *overwrite);
         }
 
-        if last_index * 2 != moves.len() && last_index * 2 - 1 != moves.len() {
-            return self.badpgn(
-                &pgn,
-                format!(
-                    "last move index == {}, but # of moves (white + black) == {}",
-                    last_index,
-                    moves.len()
-                ),
-            );
+        if self.id_strategy == IdStrategy::Content {
+            pgn.id = pgn.content_id();
         }
 
-        pgn.moves = moves;
-        pgn.moves_fingerprint = moves_fingerprint(&pgn.moves);
-
         ReadOutcome::Game(pgn)
     }
 }
-
-fn moves_fingerprint(moves: &Vec<String>) -> u64 {
-    let mut hasher = SeaHasher::new();
-
-    for m in moves.iter() {
-        hasher.write(m.as_bytes());
-    }
-
-    return hasher.finish();
-}
@@ -0,0 +1,190 @@
+use std::hash::Hasher;
+use std::str::FromStr;
+
+use seahash::SeaHasher;
+use simple_error::SimpleError;
+
+use super::Pgn;
+
+/// Longest run of moves indexed as one n-gram.
+pub const MAX_NGRAM: usize = 2;
+
+/// SAN without check, mate and annotation suffixes, so that `Bxh2` and
+/// `Bxh2+` are the same move.
+pub fn normalize_san(san: &str) -> &str {
+    san.trim_end_matches(['+', '#', '!', '?'])
+}
+
+/// Index key of a run of moves.
+pub fn ngram_key(moves: &[String]) -> i64 {
+    let mut hasher = SeaHasher::new();
+    for m in moves.iter() {
+        hasher.write(normalize_san(m).as_bytes());
+        hasher.write_u8(b' ');
+    }
+    hasher.finish() as i64
+}
+
+/// Runs of one to `MAX_NGRAM` moves of `moves`, as key, 1-based ply of the
+/// first move and length.
+pub fn ngrams(moves: &[String]) -> Vec<(i64, i32, i16)> {
+    let mut ngrams: Vec<(i64, i32, i16)> = Vec::new();
+    for start in 0..moves.len() {
+        for n in 1..=MAX_NGRAM.min(moves.len() - start) {
+            ngrams.push((
+                ngram_key(&moves[start..start + n]),
+                start as i32 + 1,
+                n as i16,
+            ));
+        }
+    }
+    ngrams
+}
+
+/// Consecutive moves to look for in games, e.g. `Nxf7 Kxf7`, `... Bxh2+`
+/// for a move of Black, or `12. Nxf7 Kxf7` for moves from a given move
+/// number.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MoveSequence {
+    pub moves: Vec<String>,
+    /// Whether the first move is White's, if given.
+    pub white_first: Option<bool>,
+    /// Move number of the first move, if given.
+    pub move_number: Option<usize>,
+}
+
+impl FromStr for MoveSequence {
+    type Err = SimpleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut sequence = Self {
+            moves: Vec::new(),
+            white_first: None,
+            move_number: None,
+        };
+
+        for word in s.split_whitespace() {
+            // A move number can be written apart from its move or against
+            // it, as in `12...Bxh2+`.
+            let number = word.trim_start_matches(|c: char| c.is_ascii_digit());
+            let san = number.trim_start_matches('.');
+            let dots = number.len() - san.len();
+            if dots == 0 {
+                sequence.moves.push(normalize_san(word).to_string());
+                continue;
+            }
+            // Numbers after the first move are only there for reading.
+            if sequence.moves.is_empty() {
+                sequence.white_first = Some(dots < 3);
+                let digits = &word[..word.len() - number.len()];
+                if !digits.is_empty() {
+                    let number = digits
+                        .parse::<usize>()
+                        .map_err(|_| SimpleError::new(format!("bad move number: {}", word)))?;
+                    sequence.move_number = Some(number);
+                }
+            }
+            if !san.is_empty() {
+                sequence.moves.push(normalize_san(san).to_string());
+            }
+        }
+
+        if sequence.moves.is_empty() {
+            return Err(SimpleError::new("no moves to search"));
+        }
+        Ok(sequence)
+    }
+}
+
+impl MoveSequence {
+    /// Key and length of the n-gram every matching game has at the ply of
+    /// the first move.
+    pub fn index_key(&self) -> (i64, i16) {
+        let n = self.moves.len().min(MAX_NGRAM);
+        (ngram_key(&self.moves[..n]), n as i16)
+    }
+
+    /// 1-based plies of `pgn` where the sequence starts, within the
+    /// inclusive range of plies given.
+    pub fn find(&self, pgn: &Pgn, min_ply: Option<usize>, max_ply: Option<usize>) -> Vec<usize> {
+        let first = super::writer::first_ply(pgn.tags.get("FEN").map(|fen| fen.as_str()));
+
+        let len = self.moves.len();
+        (0..(pgn.moves.len() + 1).saturating_sub(len))
+            .filter(|start| {
+                let ply = start + 1;
+                // Plies counted from the first move of White at move 1.
+                let game_ply = first + start;
+                min_ply.is_none_or(|min| ply >= min)
+                    && max_ply.is_none_or(|max| ply <= max)
+                    && self
+                        .white_first
+                        .is_none_or(|white| white == game_ply.is_multiple_of(2))
+                    && self
+                        .move_number
+                        .is_none_or(|number| number == game_ply / 2 + 1)
+                    && pgn.moves[*start..start + len]
+                        .iter()
+                        .zip(self.moves.iter())
+                        .all(|(played, wanted)| normalize_san(played) == wanted)
+            })
+            .map(|start| start + 1)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_sequences() {
+        let mut pgn = Pgn::new("game", 1);
+        pgn.moves = [
+            "e4", "e5", "Nf3", "Nc6", "Bc4", "Nd4", "Nxe5", "Qg5", "Nxf7", "Qxg2",
+        ]
+        .iter()
+        .map(|m| m.to_string())
+        .collect();
+
+        let sequence: MoveSequence = "Nxf7 Qxg2+".parse().unwrap();
+        assert_eq!(sequence.moves, vec!["Nxf7", "Qxg2"]);
+        assert_eq!(sequence.find(&pgn, None, None), vec![9]);
+        assert_eq!(sequence.find(&pgn, None, Some(8)), Vec::<usize>::new());
+
+        let black: MoveSequence = "... Nd4".parse().unwrap();
+        assert_eq!(black.white_first, Some(false));
+        assert_eq!(black.find(&pgn, None, None), vec![6]);
+        let white: MoveSequence = "3. Nd4".parse().unwrap();
+        assert_eq!(white.find(&pgn, None, None), Vec::<usize>::new());
+        let numbered: MoveSequence = "3... Nd4 4. Nxe5".parse().unwrap();
+        assert_eq!(numbered.move_number, Some(3));
+        assert_eq!(numbered.find(&pgn, None, None), vec![6]);
+        assert!("4.".parse::<MoveSequence>().is_err());
+
+        let joined: MoveSequence = "12...Bxh2+ 13.Kxh2".parse().unwrap();
+        assert_eq!(joined.moves, vec!["Bxh2", "Kxh2"]);
+        assert_eq!(joined.white_first, Some(false));
+        assert_eq!(joined.move_number, Some(12));
+        let joined: MoveSequence = "5.Nxf7".parse().unwrap();
+        assert_eq!(joined.moves, vec!["Nxf7"]);
+        assert_eq!(joined.white_first, Some(true));
+        assert_eq!(joined.move_number, Some(5));
+        assert_eq!(joined.find(&pgn, None, None), vec![9]);
+
+        // A fullmove number of 0 counts as move 1.
+        let mut from_fen = Pgn::new("fen", 1);
+        from_fen.tags.insert(
+            "FEN".to_string(),
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 0".to_string(),
+        );
+        from_fen.moves = vec!["e5".to_string(), "Nf3".to_string()];
+        let reply: MoveSequence = "1... e5 2. Nf3".parse().unwrap();
+        assert_eq!(reply.find(&from_fen, None, None), vec![1]);
+
+        let keys = ngrams(&pgn.moves);
+        assert_eq!(keys.len(), 19);
+        assert!(keys.contains(&(sequence.index_key().0, 9, 2)));
+        assert!(keys.contains(&(black.index_key().0, 6, 1)));
+    }
+}
@@ -0,0 +1,293 @@
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use simple_error::SimpleError;
+
+use super::output::{create_output, Output};
+use super::writer::Writer;
+use super::Pgn;
+
+/// How `Splitter` cuts games into files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SplitBy {
+    /// At most this many games per file.
+    Games(usize),
+    /// At most this many bytes of PGN per file before compression, or a
+    /// single game if it is larger.
+    Size(usize),
+    /// One file per value of a tag.
+    Tag(String),
+    /// One file per month of the `Date` tag.
+    Month,
+}
+
+impl FromStr for SplitBy {
+    type Err = SimpleError;
+
+    /// Parses `games:N`, `size:N` with an optional `K`, `M` or `G` suffix,
+    /// `tag:NAME` or `month`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bad = || SimpleError::new(format!("bad split: {}", s));
+
+        if s == "month" {
+            return Ok(SplitBy::Month);
+        }
+        let (kind, value) = s.split_once(':').ok_or_else(bad)?;
+        match kind {
+            "games" => match value.parse::<usize>() {
+                Ok(n) if n > 0 => Ok(SplitBy::Games(n)),
+                _ => Err(bad()),
+            },
+            "size" => {
+                let (digits, unit) = match value.char_indices().last() {
+                    Some((i, 'K' | 'k')) => (&value[..i], 1 << 10),
+                    Some((i, 'M' | 'm')) => (&value[..i], 1 << 20),
+                    Some((i, 'G' | 'g')) => (&value[..i], 1 << 30),
+                    _ => (value, 1),
+                };
+                match digits.parse::<usize>() {
+                    Ok(n) if n > 0 => n.checked_mul(unit).map(SplitBy::Size).ok_or_else(bad),
+                    _ => Err(bad()),
+                }
+            }
+            "tag" if !value.is_empty() => Ok(SplitBy::Tag(value.to_string())),
+            _ => Err(bad()),
+        }
+    }
+}
+
+/// Most files kept open at once when splitting by tag or month. Files
+/// closed to stay under it are reopened to append to them, as another
+/// compressed stream if they are compressed.
+const MAX_OPEN_FILES: usize = 64;
+
+/// Writes games to several files named after a template path, e.g.
+/// `out/games-0001.pgn.zst` or `out/games-Olympiad.pgn.zst` for
+/// `out/games.pgn.zst`, compressed as the template is. Games are written in
+/// the export format of `Writer`.
+pub struct Splitter {
+    by: SplitBy,
+    template: PathBuf,
+    /// Index in `files` of the file of each key.
+    keys: HashMap<String, usize>,
+    /// Open files by index in `files`, least recently written first.
+    open: Vec<(usize, Output)>,
+    /// Files created and the number of games written to each.
+    files: Vec<(PathBuf, usize)>,
+    part: usize,
+    part_bytes: usize,
+}
+
+impl Splitter {
+    pub fn new(template: &Path, by: SplitBy) -> Self {
+        Self {
+            by,
+            template: template.to_path_buf(),
+            keys: HashMap::new(),
+            open: Vec::new(),
+            files: Vec::new(),
+            part: 0,
+            part_bytes: 0,
+        }
+    }
+
+    pub fn write(&mut self, pgn: &Pgn) -> std::io::Result<()> {
+        let text = Writer::format(pgn);
+
+        let key = match &self.by {
+            SplitBy::Games(n) => {
+                let full = self.files.last().is_some_and(|(_, games)| games >= n);
+                self.next_part(self.files.is_empty() || full)?
+            }
+            SplitBy::Size(size) => {
+                let full = self.part_bytes > 0 && self.part_bytes + text.len() > *size;
+                self.next_part(self.files.is_empty() || full)?
+            }
+            SplitBy::Tag(name) => file_key(pgn.tags.get(name).map_or("", |v| v.as_str())),
+            SplitBy::Month => {
+                let date = pgn.tags.get("Date").map_or("", |v| v.as_str());
+                file_key(date.get(..7).unwrap_or(date))
+            }
+        };
+
+        let index = match self.keys.get(&key) {
+            Some(index) => *index,
+            None => {
+                let index = self.files.len();
+                let path = self.path(key.as_str());
+                self.open.push((index, create_output(&path)?));
+                self.keys.insert(key, index);
+                self.files.push((path, 0));
+                index
+            }
+        };
+        let out = self.output(index)?;
+        out.write_all(text.as_bytes())?;
+        self.files[index].1 += 1;
+        self.part_bytes += text.len();
+
+        Ok(())
+    }
+
+    /// Finishes every file, returning their paths and numbers of games in the
+    /// order they were created.
+    pub fn close(self) -> std::io::Result<Vec<(PathBuf, usize)>> {
+        for (_, out) in self.open {
+            out.finish()?;
+        }
+        Ok(self.files)
+    }
+
+    /// Key of the current numbered file, closing it first if `new` is set.
+    fn next_part(&mut self, new: bool) -> std::io::Result<String> {
+        if new {
+            for (_, out) in self.open.drain(..) {
+                out.finish()?;
+            }
+            self.part += 1;
+            self.part_bytes = 0;
+        }
+        Ok(format!("{:04}", self.part))
+    }
+
+    /// Open output of the file at `index` in `files`, reopened if it was
+    /// closed, closing the least recently written file if too many are open.
+    fn output(&mut self, index: usize) -> std::io::Result<&mut Output> {
+        let out = match self.open.iter().position(|(i, _)| *i == index) {
+            Some(position) => self.open.remove(position).1,
+            None => {
+                let path = &self.files[index].0;
+                let f = OpenOptions::new().append(true).open(path)?;
+                Output::from_file(path, f)?
+            }
+        };
+        if self.open.len() >= MAX_OPEN_FILES {
+            self.open.remove(0).1.finish()?;
+        }
+        self.open.push((index, out));
+        Ok(&mut self.open.last_mut().unwrap().1)
+    }
+
+    /// Template path with `-{key}` inserted before the extensions.
+    fn path(&self, key: &str) -> PathBuf {
+        let name = self
+            .template
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("");
+        let name = match name.split_once('.') {
+            Some((stem, extensions)) => format!("{}-{}.{}", stem, key, extensions),
+            None => format!("{}-{}", name, key),
+        };
+        self.template.with_file_name(name)
+    }
+}
+
+/// Tag value made safe to use in a file name: characters other than
+/// letters, digits, `-` and `.` are written as `_` followed by their UTF-8
+/// bytes in hex, so that different values have different keys. Values
+/// without letters or digits, e.g. `????.??`, have the key `_unknown`, which
+/// no value has.
+fn file_key(value: &str) -> String {
+    if !value.chars().any(|c| c.is_alphanumeric()) {
+        return "_unknown".to_string();
+    }
+    let mut key = String::new();
+    for c in value.chars() {
+        if c.is_alphanumeric() || c == '-' || c == '.' {
+            key.push(c);
+        } else {
+            let mut bytes = [0; 4];
+            for byte in c.encode_utf8(&mut bytes).bytes() {
+                key.push_str(format!("_{:02X}", byte).as_str());
+            }
+        }
+    }
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pgn::{ReadOutcome, Reader};
+
+    #[test]
+    fn split_files() {
+        assert_eq!("games:100".parse::<SplitBy>().unwrap(), SplitBy::Games(100));
+        assert_eq!(
+            "size:2M".parse::<SplitBy>().unwrap(),
+            SplitBy::Size(2 << 20)
+        );
+        assert_eq!(
+            "tag:Event".parse::<SplitBy>().unwrap(),
+            SplitBy::Tag("Event".to_string())
+        );
+        assert_eq!("month".parse::<SplitBy>().unwrap(), SplitBy::Month);
+        assert!("games:0".parse::<SplitBy>().is_err());
+        assert!("size:M".parse::<SplitBy>().is_err());
+        assert!("size:99999999999999G".parse::<SplitBy>().is_err());
+        assert!("tag:".parse::<SplitBy>().is_err());
+
+        let splitter = Splitter::new(Path::new("out/games.pgn.zst"), SplitBy::Month);
+        assert_eq!(
+            splitter.path("0001"),
+            PathBuf::from("out/games-0001.pgn.zst")
+        );
+        assert_eq!(file_key("44th Olympiad: Open"), "44th_20Olympiad_3A_20Open");
+        assert_eq!(file_key("A/B"), "A_2FB");
+        assert_eq!(file_key("A_B"), "A_5FB");
+        assert_eq!(file_key("unknown"), "unknown");
+        assert_eq!(file_key("????.??"), "_unknown");
+
+        let dir = std::env::temp_dir().join(format!("mudfish-split-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut splitter = Splitter::new(&dir.join("games.pgn"), SplitBy::Games(2));
+        for i in 0..5 {
+            let mut pgn = Pgn::new("game", i);
+            pgn.set_tag("White", format!("player {}", i).as_str());
+            splitter.write(&pgn).unwrap();
+        }
+        let files = splitter.close().unwrap();
+        assert_eq!(
+            files,
+            vec![
+                (dir.join("games-0001.pgn"), 2),
+                (dir.join("games-0002.pgn"), 2),
+                (dir.join("games-0003.pgn"), 1),
+            ]
+        );
+        let last = std::fs::read_to_string(dir.join("games-0003.pgn")).unwrap();
+        assert!(last.contains("[White \"player 4\"]"));
+
+        // More events than files kept open: files are reopened, adding
+        // compressed streams.
+        let events = MAX_OPEN_FILES + 6;
+        let mut splitter = Splitter::new(
+            &dir.join("events.pgn.bz2"),
+            SplitBy::Tag("Event".to_string()),
+        );
+        for i in 0..events * 2 {
+            let mut pgn = Pgn::new("game", i);
+            pgn.set_tag("Event", format!("event {}", i % events).as_str());
+            splitter.write(&pgn).unwrap();
+        }
+        let files = splitter.close().unwrap();
+        assert_eq!(files.len(), events);
+        for (path, games) in files {
+            assert_eq!(games, 2);
+            let mut reader = Reader::new(&path).unwrap();
+            for _ in 0..2 {
+                assert!(
+                    matches!(reader.read_next(), ReadOutcome::Game(_)),
+                    "{:?}",
+                    path
+                );
+            }
+            assert!(matches!(reader.read_next(), ReadOutcome::Ended));
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
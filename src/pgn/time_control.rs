@@ -0,0 +1,299 @@
+use std::fmt;
+use std::str::FromStr;
+
+use simple_error::SimpleError;
+
+// Estimated game duration boundaries in seconds, as used by Lichess:
+// base + 40 * increment.
+const BULLET_LIMIT: u32 = 180;
+const BLITZ_LIMIT: u32 = 480;
+const RAPID_LIMIT: u32 = 1500;
+
+// A period allowing a day or more per move is played by correspondence.
+const CORRESPONDENCE_LIMIT: u32 = 86400;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeControlClass {
+    Bullet,
+    Blitz,
+    Rapid,
+    Classical,
+    Correspondence,
+}
+
+impl TimeControlClass {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TimeControlClass::Bullet => "bullet",
+            TimeControlClass::Blitz => "blitz",
+            TimeControlClass::Rapid => "rapid",
+            TimeControlClass::Classical => "classical",
+            TimeControlClass::Correspondence => "correspondence",
+        }
+    }
+}
+
+impl fmt::Display for TimeControlClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for TimeControlClass {
+    type Err = SimpleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "bullet" => Ok(TimeControlClass::Bullet),
+            "blitz" => Ok(TimeControlClass::Blitz),
+            "rapid" => Ok(TimeControlClass::Rapid),
+            "classical" => Ok(TimeControlClass::Classical),
+            "correspondence" => Ok(TimeControlClass::Correspondence),
+            _ => Err(SimpleError::new(format!(
+                "unknown time control class: {}",
+                s
+            ))),
+        }
+    }
+}
+
+/// One period of a time control, e.g. `40/7200`, `300`, `180+2` or `*60`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimeControlPeriod {
+    /// Number of moves to be made in the period, `None` for sudden death.
+    pub moves: Option<u32>,
+    pub seconds: u32,
+    pub increment: u32,
+    pub hourglass: bool,
+}
+
+impl FromStr for TimeControlPeriod {
+    type Err = SimpleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bad = || SimpleError::new(format!("bad time control period: {}", s));
+        let number = |t: &str| t.trim().parse::<u32>().map_err(|_| bad());
+
+        if let Some(seconds) = s.strip_prefix('*') {
+            return Ok(Self {
+                moves: None,
+                seconds: number(seconds)?,
+                increment: 0,
+                hourglass: true,
+            });
+        }
+
+        let (moves, rest) = match s.split_once('/') {
+            Some((moves, rest)) => (Some(number(moves)?), rest),
+            None => (None, s),
+        };
+
+        let (seconds, increment) = match rest.split_once('+') {
+            Some((seconds, increment)) => (number(seconds)?, number(increment)?),
+            None => (number(rest)?, 0),
+        };
+
+        Ok(Self {
+            moves,
+            seconds,
+            increment,
+            hourglass: false,
+        })
+    }
+}
+
+impl fmt::Display for TimeControlPeriod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.hourglass {
+            return write!(f, "*{}", self.seconds);
+        }
+        if let Some(moves) = self.moves {
+            write!(f, "{}/", moves)?;
+        }
+        write!(f, "{}", self.seconds)?;
+        if self.increment > 0 {
+            write!(f, "+{}", self.increment)?;
+        }
+        Ok(())
+    }
+}
+
+/// Value of the `TimeControl` tag as described in section 9.6.1 of the PGN
+/// standard.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TimeControl {
+    /// `?`: the time control is not known.
+    Unknown,
+    /// `-`: the game was played without a time control.
+    Unlimited,
+    /// One or more periods separated by `:`.
+    Periods(Vec<TimeControlPeriod>),
+}
+
+impl TimeControl {
+    /// Seconds available in the first period.
+    pub fn base(&self) -> Option<u32> {
+        match self {
+            TimeControl::Periods(periods) => periods.first().map(|p| p.seconds),
+            _ => None,
+        }
+    }
+
+    /// Seconds added per move in the first period.
+    pub fn increment(&self) -> Option<u32> {
+        match self {
+            TimeControl::Periods(periods) => periods.first().map(|p| p.increment),
+            _ => None,
+        }
+    }
+
+    /// Estimated time per player for a game of 40 moves, `None` if it does
+    /// not fit in a `u32`.
+    pub fn estimated_duration(&self) -> Option<u32> {
+        match self {
+            TimeControl::Periods(periods) => {
+                let total = periods
+                    .iter()
+                    .try_fold(0u32, |total, p| total.checked_add(p.seconds))?;
+                let increment = periods.first().map_or(0, |p| p.increment);
+                increment.checked_mul(40)?.checked_add(total)
+            }
+            _ => None,
+        }
+    }
+
+    /// Class of the time control. Games without a time control have no class:
+    /// `-` is also used by databases that did not record one.
+    pub fn class(&self) -> Option<TimeControlClass> {
+        match self {
+            TimeControl::Unknown | TimeControl::Unlimited => None,
+            TimeControl::Periods(periods) => {
+                if periods
+                    .iter()
+                    .any(|p| p.seconds / p.moves.unwrap_or(1).max(1) >= CORRESPONDENCE_LIMIT)
+                {
+                    return Some(TimeControlClass::Correspondence);
+                }
+
+                let duration = self.estimated_duration()?;
+                Some(if duration < BULLET_LIMIT {
+                    TimeControlClass::Bullet
+                } else if duration < BLITZ_LIMIT {
+                    TimeControlClass::Blitz
+                } else if duration < RAPID_LIMIT {
+                    TimeControlClass::Rapid
+                } else {
+                    TimeControlClass::Classical
+                })
+            }
+        }
+    }
+}
+
+impl FromStr for TimeControl {
+    type Err = SimpleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "" => Err(SimpleError::new("empty time control")),
+            "?" => Ok(TimeControl::Unknown),
+            "-" => Ok(TimeControl::Unlimited),
+            trimmed => trimmed
+                .split(':')
+                .map(TimeControlPeriod::from_str)
+                .collect::<Result<Vec<_>, _>>()
+                .map(TimeControl::Periods),
+        }
+    }
+}
+
+impl fmt::Display for TimeControl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TimeControl::Unknown => f.write_str("?"),
+            TimeControl::Unlimited => f.write_str("-"),
+            TimeControl::Periods(periods) => {
+                for (i, period) in periods.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(":")?;
+                    }
+                    write!(f, "{}", period)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_forms() {
+        let tc: TimeControl = "180+2".parse().unwrap();
+        assert_eq!(tc.base(), Some(180));
+        assert_eq!(tc.increment(), Some(2));
+        assert_eq!(tc.class(), Some(TimeControlClass::Blitz));
+
+        let tc: TimeControl = "40/7200:3600".parse().unwrap();
+        assert_eq!(
+            tc,
+            TimeControl::Periods(vec![
+                TimeControlPeriod {
+                    moves: Some(40),
+                    seconds: 7200,
+                    increment: 0,
+                    hourglass: false,
+                },
+                TimeControlPeriod {
+                    moves: None,
+                    seconds: 3600,
+                    increment: 0,
+                    hourglass: false,
+                },
+            ])
+        );
+        assert_eq!(tc.class(), Some(TimeControlClass::Classical));
+        assert_eq!(tc.to_string(), "40/7200:3600");
+
+        let tc: TimeControl = "*60".parse().unwrap();
+        assert_eq!(tc.base(), Some(60));
+        assert_eq!(tc.class(), Some(TimeControlClass::Bullet));
+        assert_eq!(tc.to_string(), "*60");
+
+        assert_eq!("?".parse::<TimeControl>().unwrap(), TimeControl::Unknown);
+        assert_eq!("-".parse::<TimeControl>().unwrap(), TimeControl::Unlimited);
+        assert!("40/abc".parse::<TimeControl>().is_err());
+        assert!("".parse::<TimeControl>().is_err());
+    }
+
+    #[test]
+    fn classify() {
+        let class = |s: &str| s.parse::<TimeControl>().unwrap().class();
+
+        assert_eq!(class("60"), Some(TimeControlClass::Bullet));
+        assert_eq!(class("120+1"), Some(TimeControlClass::Bullet));
+        assert_eq!(class("120+2"), Some(TimeControlClass::Blitz));
+        assert_eq!(class("300"), Some(TimeControlClass::Blitz));
+        assert_eq!(class("600+5"), Some(TimeControlClass::Rapid));
+        assert_eq!(class("900+10"), Some(TimeControlClass::Rapid));
+        assert_eq!(class("5400+30"), Some(TimeControlClass::Classical));
+        assert_eq!(class("1/259200"), Some(TimeControlClass::Correspondence));
+        assert_eq!(class("40/86400"), Some(TimeControlClass::Classical));
+        assert_eq!(class("-"), None);
+        assert_eq!(class("?"), None);
+        assert_eq!(class("60+4000000000"), None);
+        assert_eq!(
+            class("4000000000:4000000000"),
+            Some(TimeControlClass::Correspondence)
+        );
+        assert_eq!(
+            "3000000000:3000000000"
+                .parse::<TimeControl>()
+                .unwrap()
+                .estimated_duration(),
+            None
+        );
+    }
+}
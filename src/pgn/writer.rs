@@ -0,0 +1,330 @@
+use std::io::Write;
+use std::path::Path;
+
+use super::movetext::{tokenize, Token};
+use super::output::{create_output, Output};
+use super::Pgn;
+
+const SEVEN_TAG_ROSTER: [&str; 7] = ["Event", "Site", "Date", "Round", "White", "Black", "Result"];
+const MAX_LINE: usize = 80;
+
+/// Writes games in the export format of the PGN standard: the Seven Tag
+/// Roster first, then the other tags by name, and the move text with one
+/// space between tokens, wrapped at 80 columns.
+pub struct Writer<W: Write> {
+    out: W,
+}
+
+impl Writer<Output> {
+    /// Creates `path` for writing, compressed with bzip2 or zstd when its
+    /// extension is `bz2` or `zst`.
+    pub fn create(path: &Path) -> std::io::Result<Self> {
+        Ok(Self::new(create_output(path)?))
+    }
+
+    /// Completes the output, see `Output::finish`.
+    pub fn finish(self) -> std::io::Result<()> {
+        self.out.finish()
+    }
+}
+
+impl Writer<Vec<u8>> {
+    /// Formats `pgn` as a string.
+    pub fn format(pgn: &Pgn) -> String {
+        let mut writer = Writer::new(Vec::new());
+        writer.write(pgn).unwrap();
+        String::from_utf8(writer.into_inner()).unwrap()
+    }
+}
+
+impl<W: Write> Writer<W> {
+    pub fn new(out: W) -> Self {
+        Self { out }
+    }
+
+    pub fn write(&mut self, pgn: &Pgn) -> std::io::Result<()> {
+        for name in SEVEN_TAG_ROSTER.iter() {
+            let value = pgn.tags.get(*name).map_or(
+                match *name {
+                    "Date" => "????.??.??",
+                    "Result" => "*",
+                    _ => "?",
+                },
+                |v| v.as_str(),
+            );
+            self.write_tag(name, value)?;
+        }
+
+        let mut others: Vec<(&String, &String)> = pgn
+            .tags
+            .iter()
+            .filter(|(name, _)| !SEVEN_TAG_ROSTER.contains(&name.as_str()))
+            .collect();
+        others.sort();
+        for (name, value) in others {
+            self.write_tag(name, value)?;
+        }
+
+        writeln!(self.out)?;
+
+        let mut line = Line::default();
+        for word in movetext_words(pgn) {
+            if let Some(full) = line.push(word.as_str()) {
+                writeln!(self.out, "{}", full)?;
+            }
+        }
+        if !line.text.is_empty() {
+            writeln!(self.out, "{}", line.text)?;
+        }
+
+        writeln!(self.out)
+    }
+
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.out.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    fn write_tag(&mut self, name: &str, value: &str) -> std::io::Result<()> {
+        writeln!(
+            self.out,
+            "[{} \"{}\"]",
+            name,
+            value.replace('\\', "\\\\").replace('"', "\\\"")
+        )
+    }
+}
+
+#[derive(Default)]
+struct Line {
+    text: String,
+}
+
+impl Line {
+    /// Appends `word`, returning the previous text if it had to start a new
+    /// line.
+    fn push(&mut self, word: &str) -> Option<String> {
+        let mut full = None;
+        if !self.text.is_empty() && self.text.len() + 1 + word.len() > MAX_LINE {
+            full = Some(std::mem::take(&mut self.text));
+        }
+        if !self.text.is_empty() {
+            self.text.push(' ');
+        }
+        self.text.push_str(word);
+        full
+    }
+}
+
+/// Space separated words of the export format move text. Comments are split
+/// into words so that they can be wrapped as well.
+fn movetext_words(pgn: &Pgn) -> Vec<String> {
+    let mut tokens = tokenize(pgn.moves_text.as_str());
+
+    if tokens.is_empty() && !pgn.moves.is_empty() {
+        tokens = pgn.moves.iter().map(|m| Token::San(m.clone())).collect();
+    }
+
+    if !tokens.iter().any(|t| matches!(t, Token::Result(_))) {
+        let result = pgn.tags.get("Result").map_or("*", |r| r.as_str());
+        tokens.push(Token::Result(result.to_string()));
+    }
+
+    let first_ply = first_ply(pgn.tags.get("FEN").map(|fen| fen.as_str()));
+
+    let mut words: Vec<String> = Vec::new();
+    // Ply of the next move, one entry per open variation.
+    let mut plies: Vec<usize> = vec![first_ply];
+    // Ply of the last move played at each level, where variations start.
+    let mut last_plies: Vec<usize> = vec![first_ply];
+    let mut need_number = true;
+
+    for token in tokens {
+        match token {
+            Token::MoveNumber(_, _) => {}
+            Token::San(san) => {
+                let ply = *plies.last().unwrap();
+                let number = ply / 2 + 1;
+                if ply.is_multiple_of(2) {
+                    words.push(format!("{}.", number));
+                } else if need_number {
+                    words.push(format!("{}...", number));
+                }
+                words.push(san);
+                *last_plies.last_mut().unwrap() = ply;
+                *plies.last_mut().unwrap() = ply + 1;
+                need_number = false;
+            }
+            Token::Nag(nag) => words.push(format!("${}", nag)),
+            Token::Comment(comment) => {
+                let comment = comment.replace('}', "");
+                let mut parts: Vec<&str> = comment.split_whitespace().collect();
+                if parts.is_empty() {
+                    parts.push("");
+                }
+                let last = parts.len() - 1;
+                for (i, part) in parts.iter().enumerate() {
+                    let mut word = String::new();
+                    if i == 0 {
+                        word.push('{');
+                    }
+                    word.push_str(part);
+                    if i == last {
+                        word.push('}');
+                    }
+                    words.push(word);
+                }
+                need_number = true;
+            }
+            Token::VariationStart => {
+                // A variation replaces the last move played.
+                let ply = *last_plies.last().unwrap();
+                plies.push(ply);
+                last_plies.push(ply);
+                words.push("(".to_string());
+                need_number = true;
+            }
+            Token::VariationEnd => {
+                if plies.len() > 1 {
+                    plies.pop();
+                    last_plies.pop();
+                    words.push(")".to_string());
+                    need_number = true;
+                }
+            }
+            Token::Result(result) => {
+                if plies.len() == 1 {
+                    words.push(result);
+                }
+            }
+        }
+    }
+
+    // Close variations left open by malformed input.
+    for _ in 1..plies.len() {
+        words.push(")".to_string());
+    }
+
+    // `(` and `)` are attached to the adjacent tokens.
+    let mut joined: Vec<String> = Vec::new();
+    let mut open = false;
+    for word in words {
+        if word == ")" {
+            if let Some(last) = joined.last_mut() {
+                last.push(')');
+                continue;
+            }
+        }
+        if open {
+            joined.last_mut().unwrap().push_str(word.as_str());
+            open = false;
+        } else {
+            joined.push(word.clone());
+        }
+        if word == "(" {
+            open = true;
+        }
+    }
+
+    joined
+}
+
+/// Ply number, counted from 0, of the first move of a game starting from
+/// `fen`, the value of its `FEN` tag: games set up from a position can start
+/// with Black to move or after move 1.
+pub(super) fn first_ply(fen: Option<&str>) -> usize {
+    let fen = match fen {
+        Some(fen) => fen,
+        None => return 0,
+    };
+
+    let fields: Vec<&str> = fen.split_whitespace().collect();
+    let black = fields.get(1) == Some(&"b");
+    let number = fields
+        .get(5)
+        .and_then(|n| n.parse::<usize>().ok())
+        .unwrap_or(1)
+        .max(1);
+
+    (number - 1) * 2 + black as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn game(tags: &[(&str, &str)], moves_text: &str) -> Pgn {
+        let mut pgn = Pgn::new("test", 1);
+        for (name, value) in tags {
+            pgn.tags.insert(name.to_string(), value.to_string());
+        }
+        pgn.moves_text = moves_text.to_string();
+        pgn
+    }
+
+    #[test]
+    fn order_and_wrap() {
+        let pgn = game(
+            &[
+                ("WhiteElo", "2700"),
+                ("Result", "1-0"),
+                ("White", "A \"the\" \\ B"),
+                ("Black", "B"),
+                ("ECO", "C20"),
+            ],
+            "1. e4 e5 2. Nf3 Nc6 3. Bb5 a6 4. Ba4 Nf6 5. O-O Be7 6. Re1 b5 7. Bb3 d6 8. c3 O-O 9. h3 1-0",
+        );
+
+        assert_eq!(
+            Writer::format(&pgn),
+            r#"[Event "?"]
+[Site "?"]
+[Date "????.??.??"]
+[Round "?"]
+[White "A \"the\" \\ B"]
+[Black "B"]
+[Result "1-0"]
+[ECO "C20"]
+[WhiteElo "2700"]
+
+1. e4 e5 2. Nf3 Nc6 3. Bb5 a6 4. Ba4 Nf6 5. O-O Be7 6. Re1 b5 7. Bb3 d6 8. c3
+O-O 9. h3 1-0
+
+"#
+        );
+    }
+
+    #[test]
+    fn annotations() {
+        let pgn = game(
+            &[("Result", "*")],
+            "1.e4 e5 {a comment} 2.Nf3! (2.f4 exf4; gambit\n3.Bc4) Nc6?! $18 *",
+        );
+
+        let text = Writer::format(&pgn);
+        let moves = text.split("\n\n").nth(1).unwrap();
+        assert_eq!(
+            moves,
+            "1. e4 e5 {a comment} 2. Nf3 $1 (2. f4 exf4 {gambit} 3. Bc4) 2... Nc6 $6 $18 *"
+        );
+    }
+
+    #[test]
+    fn setup_position() {
+        let pgn = game(
+            &[
+                ("Result", "0-1"),
+                ("SetUp", "1"),
+                ("FEN", "4k3/8/8/8/8/8/4q3/4K3 b - - 0 30"),
+            ],
+            "30... Kd7 31. Kxe2 0-1",
+        );
+
+        let text = Writer::format(&pgn);
+        let moves = text.split("\n\n").nth(1).unwrap();
+        assert_eq!(moves, "30... Kd7 31. Kxe2 0-1");
+    }
+}
@@ -0,0 +1,329 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use simple_error::SimpleError;
+
+pub mod glicko2;
+
+/// Rating difference (dp) for a fractional score of 0.50 to 1.00 in steps of
+/// 0.01, from table 8.1a of the FIDE rating regulations. Scores below 0.50
+/// have the same difference, negated, as their complement.
+const DP_TABLE: [i32; 51] = [
+    0, 7, 14, 21, 29, 36, 43, 50, 57, 65, 72, 80, 87, 95, 102, 110, 117, 125, 133, 141, 149, 158,
+    166, 175, 184, 193, 202, 211, 220, 230, 240, 251, 262, 273, 284, 296, 309, 322, 336, 351, 366,
+    383, 401, 422, 444, 470, 501, 538, 589, 677, 800,
+];
+
+/// Largest rating difference taken into account for expected scores, as in
+/// the FIDE rating regulations.
+const MAX_DIFFERENCE: f64 = 400.0;
+
+/// Rating difference for a fractional score `p` in `[0, 1]`, rounded to two
+/// decimals as in the FIDE table.
+pub fn rating_difference(p: f64) -> i32 {
+    let index = (p.clamp(0.0, 1.0) * 100.0).round() as i32 - 50;
+    let dp = DP_TABLE[index.unsigned_abs() as usize];
+    if index < 0 {
+        -dp
+    } else {
+        dp
+    }
+}
+
+/// FIDE performance rating: the average rating of the opponents plus the
+/// rating difference for the fraction of `points` scored in `games`.
+pub fn performance(average_opponent: f64, points: f64, games: i64) -> f64 {
+    if games == 0 {
+        return average_opponent;
+    }
+    average_opponent + rating_difference(points / games as f64) as f64
+}
+
+/// Expected score of a player rated `rating` against one rated `opponent`,
+/// counting differences of more than 400 points as 400.
+pub fn expected_score(rating: f64, opponent: f64) -> f64 {
+    let difference = (rating - opponent).clamp(-MAX_DIFFERENCE, MAX_DIFFERENCE);
+    1.0 / (1.0 + 10f64.powf(-difference / 400.0))
+}
+
+/// How ratings of a pool are calculated.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Method {
+    /// Elo with a development coefficient `k`, updated after each game.
+    Elo { k: f64 },
+    /// Glicko-2 with a system constant `tau`, updated after each period.
+    Glicko2 { tau: f64 },
+}
+
+/// Length of the rating periods, which are also the steps of the rating
+/// history.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Period {
+    Day,
+    Month,
+    Year,
+}
+
+impl Period {
+    /// Period of a `YYYY.MM.DD` date, e.g. `2024.03` for a month.
+    pub fn of<'a>(&self, date: &'a str) -> &'a str {
+        let len = match self {
+            Period::Day => 10,
+            Period::Month => 7,
+            Period::Year => 4,
+        };
+        date.get(..len).unwrap_or(date)
+    }
+}
+
+impl FromStr for Period {
+    type Err = SimpleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "day" => Ok(Period::Day),
+            "month" => Ok(Period::Month),
+            "year" => Ok(Period::Year),
+            _ => Err(SimpleError::new(format!("unknown rating period: {}", s))),
+        }
+    }
+}
+
+/// A finished game between two players of a pool.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RatedGame {
+    pub date: String,
+    pub white: String,
+    pub black: String,
+    /// Score of White: 1, 0.5 or 0.
+    pub score: f64,
+}
+
+/// Rating of a player at the end of a period in which they played.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RatingEntry {
+    pub player: String,
+    pub period: String,
+    /// Games played in the period.
+    pub games: i32,
+    pub rating: f64,
+    /// Rating deviation, with Glicko-2 only.
+    pub deviation: Option<f64>,
+    /// Rating volatility, with Glicko-2 only.
+    pub volatility: Option<f64>,
+}
+
+/// Players rated against each other from their games only, e.g. a club
+/// without official ratings. New players start at the initial rating.
+#[derive(Debug, Clone)]
+pub struct RatingPool {
+    method: Method,
+    period: Period,
+    initial: glicko2::Rating,
+    ratings: HashMap<String, glicko2::Rating>,
+    history: Vec<RatingEntry>,
+}
+
+impl RatingPool {
+    pub fn new(method: Method, period: Period) -> Self {
+        Self {
+            method,
+            period,
+            initial: glicko2::Rating::default(),
+            ratings: HashMap::new(),
+            history: Vec::new(),
+        }
+    }
+
+    /// Rating of new players, 1500 by default.
+    pub fn with_initial(mut self, rating: f64) -> Self {
+        self.initial.rating = rating;
+        self
+    }
+
+    /// Rates `games`, which must be in chronological order.
+    pub fn rate<I: IntoIterator<Item = RatedGame>>(&mut self, games: I) {
+        let mut period: Option<String> = None;
+        let mut pending: Vec<RatedGame> = Vec::new();
+
+        for game in games {
+            let current = self.period.of(game.date.as_str());
+            if period.as_deref() != Some(current) {
+                if let Some(period) = period.take() {
+                    self.close_period(period.as_str(), &pending);
+                }
+                period = Some(current.to_string());
+                pending.clear();
+            }
+            pending.push(game);
+        }
+        if let Some(period) = period {
+            self.close_period(period.as_str(), &pending);
+        }
+    }
+
+    /// Current rating of each player, highest first, with the last period
+    /// they played in and the number of games they played.
+    pub fn ratings(&self) -> Vec<RatingEntry> {
+        let mut players: HashMap<&str, (&str, i32)> = HashMap::new();
+        for entry in self.history.iter() {
+            let (period, games) = players.entry(entry.player.as_str()).or_insert(("", 0));
+            *period = entry.period.as_str();
+            *games += entry.games;
+        }
+
+        let mut ratings: Vec<RatingEntry> = players
+            .into_iter()
+            .map(|(player, (period, games))| {
+                self.entry(player, period, games, &self.ratings[player])
+            })
+            .collect();
+        ratings.sort_by(|a, b| b.rating.total_cmp(&a.rating).then(a.player.cmp(&b.player)));
+        ratings
+    }
+
+    /// Rating of each player at the end of each period they played in, in
+    /// chronological order.
+    pub fn history(&self) -> &[RatingEntry] {
+        &self.history
+    }
+
+    fn entry(
+        &self,
+        player: &str,
+        period: &str,
+        games: i32,
+        rating: &glicko2::Rating,
+    ) -> RatingEntry {
+        let glicko = matches!(self.method, Method::Glicko2 { .. });
+        RatingEntry {
+            player: player.to_string(),
+            period: period.to_string(),
+            games,
+            rating: rating.rating,
+            deviation: glicko.then_some(rating.deviation),
+            volatility: glicko.then_some(rating.volatility),
+        }
+    }
+
+    fn close_period(&mut self, period: &str, games: &[RatedGame]) {
+        // Players of the period in order of their first game.
+        let mut players: Vec<&str> = Vec::new();
+        let mut counts: HashMap<&str, i32> = HashMap::new();
+        for game in games.iter() {
+            for player in [game.white.as_str(), game.black.as_str()] {
+                if !counts.contains_key(player) {
+                    players.push(player);
+                }
+                *counts.entry(player).or_insert(0) += 1;
+            }
+        }
+        for player in players.iter() {
+            self.ratings
+                .entry(player.to_string())
+                .or_insert(self.initial);
+        }
+
+        match self.method {
+            Method::Elo { k } => {
+                for game in games.iter() {
+                    let white = self.ratings[game.white.as_str()].rating;
+                    let black = self.ratings[game.black.as_str()].rating;
+                    let change = k * (game.score - expected_score(white, black));
+                    self.ratings.get_mut(game.white.as_str()).unwrap().rating += change;
+                    self.ratings.get_mut(game.black.as_str()).unwrap().rating -= change;
+                }
+            }
+            Method::Glicko2 { tau } => {
+                let mut results: HashMap<&str, Vec<(glicko2::Rating, f64)>> = HashMap::new();
+                for game in games.iter() {
+                    let white = self.ratings[game.white.as_str()];
+                    let black = self.ratings[game.black.as_str()];
+                    results
+                        .entry(game.white.as_str())
+                        .or_default()
+                        .push((black, game.score));
+                    results
+                        .entry(game.black.as_str())
+                        .or_default()
+                        .push((white, 1.0 - game.score));
+                }
+                // Players without games in the period grow less certain.
+                for (player, rating) in self.ratings.iter_mut() {
+                    let games = results
+                        .get(player.as_str())
+                        .map_or(&[][..], |r| r.as_slice());
+                    *rating = rating.update(games, tau);
+                }
+            }
+        }
+
+        for player in players {
+            let entry = self.entry(player, period, counts[player], &self.ratings[player]);
+            self.history.push(entry);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn performance_and_expected_scores() {
+        assert_eq!(rating_difference(0.5), 0);
+        assert_eq!(rating_difference(0.625), 95);
+        assert_eq!(rating_difference(0.25), -193);
+        assert_eq!(rating_difference(1.0), 800);
+        assert_eq!(performance(2400.0, 6.5, 9), 2566.0);
+        assert_eq!(performance(2400.0, 0.0, 0), 2400.0);
+
+        assert_eq!(expected_score(2000.0, 2000.0), 0.5);
+        assert!((expected_score(2200.0, 2000.0) - 0.76).abs() < 0.001);
+        assert_eq!(
+            expected_score(2800.0, 2000.0),
+            expected_score(2400.0, 2000.0)
+        );
+    }
+
+    #[test]
+    fn rate_pool() {
+        let game = |date: &str, white: &str, black: &str, score: f64| RatedGame {
+            date: date.to_string(),
+            white: white.to_string(),
+            black: black.to_string(),
+            score,
+        };
+        let games = vec![
+            game("2024.01.05", "ann", "bob", 1.0),
+            game("2024.01.12", "bob", "ann", 0.5),
+            game("2024.02.02", "bob", "cid", 0.0),
+        ];
+
+        let mut pool = RatingPool::new(Method::Elo { k: 20.0 }, Period::Month);
+        pool.rate(games.clone());
+        let history = pool.history();
+        assert_eq!(history.len(), 4);
+        assert_eq!(history[0].player, "ann");
+        assert_eq!(history[0].period, "2024.01");
+        assert_eq!(history[0].games, 2);
+        assert_eq!(history[0].deviation, None);
+        let ratings = pool.ratings();
+        assert_eq!(ratings[0].player, "cid");
+        assert_eq!(ratings[0].period, "2024.02");
+        assert_eq!(ratings[1].player, "ann");
+        assert_eq!(ratings[1].games, 2);
+        assert_eq!(ratings[2].player, "bob");
+        assert_eq!(ratings[2].games, 3);
+        let total: f64 = ratings.iter().map(|r| r.rating).sum();
+        assert!((total - 4500.0).abs() < 1e-9);
+
+        let mut pool =
+            RatingPool::new(Method::Glicko2 { tau: 0.5 }, Period::Year).with_initial(1200.0);
+        pool.rate(games);
+        assert_eq!(pool.history().len(), 3);
+        let ratings = pool.ratings();
+        assert!(ratings.iter().all(|r| r.deviation.unwrap() < 350.0));
+        assert!(ratings[0].rating > 1200.0);
+    }
+}
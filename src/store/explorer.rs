@@ -0,0 +1,45 @@
+/// A game shown by the explorer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GameSummary {
+    pub id: String,
+    pub white: String,
+    pub black: String,
+    pub date: String,
+    pub result: String,
+}
+
+/// Statistics of a move played from an explored position.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExplorerMove {
+    pub san: String,
+    pub games: i64,
+    pub white_wins: i64,
+    pub draws: i64,
+    pub black_wins: i64,
+    /// Average rating of the players, over games where both are rated.
+    pub average_elo: Option<f64>,
+    /// Game with the latest date.
+    pub last_game: Option<GameSummary>,
+}
+
+impl ExplorerMove {
+    pub fn white_percent(&self) -> f64 {
+        percent(self.white_wins, self.games)
+    }
+
+    pub fn draw_percent(&self) -> f64 {
+        percent(self.draws, self.games)
+    }
+
+    pub fn black_percent(&self) -> f64 {
+        percent(self.black_wins, self.games)
+    }
+}
+
+fn percent(count: i64, total: i64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        100.0 * count as f64 / total as f64
+    }
+}
@@ -1,4 +1,13 @@
+mod explorer;
+pub use self::explorer::{ExplorerMove, GameSummary};
+
+mod player;
+pub use self::player::{BandStats, OpeningStats, PlayerReport, Score, YearStats, RATING_BAND};
+
 mod postgres;
 pub use self::postgres::PostgresStore;
 
-pub(self) mod tables;
+mod query;
+pub use self::query::{Query, SortBy};
+
+mod tables;
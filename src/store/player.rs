@@ -0,0 +1,117 @@
+use crate::rating::performance;
+
+/// Results of a player over some games. Unfinished games are not counted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Score {
+    pub games: i64,
+    pub wins: i64,
+    pub draws: i64,
+    pub losses: i64,
+}
+
+impl Score {
+    pub fn points(&self) -> f64 {
+        self.wins as f64 + self.draws as f64 / 2.0
+    }
+
+    pub fn percent(&self) -> f64 {
+        if self.games == 0 {
+            0.0
+        } else {
+            100.0 * self.points() / self.games as f64
+        }
+    }
+}
+
+/// Results of a player in one year.
+#[derive(Debug, Clone, PartialEq)]
+pub struct YearStats {
+    /// `YYYY`, or `????` for undated games.
+    pub year: String,
+    pub score: Score,
+    /// Results against rated opponents only.
+    pub rated: Score,
+    pub average_opponent: Option<f64>,
+}
+
+impl YearStats {
+    /// FIDE performance rating over the games against rated opponents.
+    pub fn performance(&self) -> Option<f64> {
+        self.average_opponent
+            .map(|average| performance(average, self.rated.points(), self.rated.games))
+    }
+}
+
+/// Results of a player in an opening with one colour.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpeningStats {
+    pub eco: String,
+    pub opening: String,
+    pub score: Score,
+}
+
+/// Results of a player against opponents rated within
+/// `[min_elo, min_elo + RATING_BAND)`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BandStats {
+    pub min_elo: i32,
+    pub score: Score,
+}
+
+/// Width of the rating bands of `PlayerReport::bands`.
+pub const RATING_BAND: i32 = 200;
+
+/// Everything stored about the games of one player.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlayerReport {
+    /// Name the player appears with most.
+    pub name: String,
+    pub white: Score,
+    pub black: Score,
+    pub years: Vec<YearStats>,
+    /// Most played openings with White, most played first.
+    pub white_openings: Vec<OpeningStats>,
+    /// Most played openings with Black, most played first.
+    pub black_openings: Vec<OpeningStats>,
+    pub average_plies: Option<f64>,
+    /// Results by rating band of the opponent, lowest first.
+    pub bands: Vec<BandStats>,
+}
+
+impl PlayerReport {
+    pub fn total(&self) -> Score {
+        Score {
+            games: self.white.games + self.black.games,
+            wins: self.white.wins + self.black.wins,
+            draws: self.white.draws + self.black.draws,
+            losses: self.white.losses + self.black.losses,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scores() {
+        let score = Score {
+            games: 4,
+            wins: 2,
+            draws: 1,
+            losses: 1,
+        };
+        assert_eq!(score.points(), 2.5);
+        assert_eq!(score.percent(), 62.5);
+
+        let mut year = YearStats {
+            year: "2024".to_string(),
+            score,
+            rated: score,
+            average_opponent: None,
+        };
+        assert_eq!(year.performance(), None);
+        year.average_opponent = Some(2000.0);
+        assert_eq!(year.performance(), Some(2095.0));
+    }
+}
@@ -1,12 +1,27 @@
 use std::str::FromStr;
 
+use postgres::fallible_iterator::FallibleIterator;
+use postgres::types::ToSql;
 use postgres::{Client, NoTls};
+use std::io::Write;
 
+use shakmaty::Chess;
+
+use super::explorer::{ExplorerMove, GameSummary};
+use super::player::{BandStats, OpeningStats, PlayerReport, Score, YearStats, RATING_BAND};
+use super::query::{Params, Query};
 use super::tables;
-use crate::pgn::Pgn;
+use crate::analysis::{GameAnalysis, PlayerAnalysis};
+use crate::board::material::Endgame;
+use crate::board::pattern::{game_codes, Pattern};
+use crate::board::{polyglot_key, Replay};
+use crate::pgn::sequence::{ngrams, MoveSequence};
+use crate::pgn::{self, Parser, Pgn, Writer, DATE_ORDER_SQL, DATE_UNKNOWN_SQL};
+use crate::rating::{RatedGame, RatingEntry};
 
 pub struct PostgresStore {
     client: Client,
+    parser: Parser,
     empty: String,
 }
 
@@ -21,6 +36,7 @@ impl PostgresStore {
 
         let mut store = Self {
             client,
+            parser: Parser::default(),
             empty: String::new(),
         };
 
@@ -30,10 +46,19 @@ impl PostgresStore {
     }
 
     fn create_tables(&mut self) -> Result<(), postgres::error::Error> {
-        for migration in tables::pgn::get_migrations() {
+        let migrations = tables::pgn::get_migrations()
+            .into_iter()
+            .chain(tables::position::get_migrations())
+            .chain(tables::game_analysis::get_migrations())
+            .chain(tables::game_pattern::get_migrations())
+            .chain(tables::move_ngram::get_migrations())
+            .chain(tables::rating_history::get_migrations());
+        for migration in migrations {
             let done = (migration.test)(&mut self.client)?;
             if !done {
-                (migration.apply)(&mut self.client)?;
+                let mut transaction = self.client.transaction()?;
+                (migration.apply)(&mut transaction)?;
+                transaction.commit()?;
             }
         }
         Ok(())
@@ -61,7 +86,12 @@ impl PostgresStore {
                 variation,
                 result,
                 tags,
-                moves
+                moves,
+                time_control_base,
+                time_control_increment,
+                time_control_class,
+                moves_fingerprint,
+                ply_count
             )
             VALUES(
                 $1,
@@ -84,7 +114,12 @@ impl PostgresStore {
                 $18,
                 $19,
                 $20,
-                $21)
+                $21,
+                $22,
+                $23,
+                $24,
+                $25,
+                $26)
             ON CONFLICT (id) DO UPDATE SET
                 event = $2,
                 site = $3,
@@ -105,38 +140,851 @@ impl PostgresStore {
                 variation = $18,
                 result = $19,
                 tags = $20,
-                moves = $21";
+                moves = $21,
+                time_control_base = $22,
+                time_control_increment = $23,
+                time_control_class = $24,
+                moves_fingerprint = $25,
+                ply_count = $26";
+
+        let (time_control_base, time_control_increment, time_control_class) =
+            time_control_columns(pgn);
+
+        self.client.execute(
+            statement,
+            &[
+                &pgn.id,
+                pgn.tags.get("Event").unwrap_or(&self.empty),
+                pgn.tags.get("Site").unwrap_or(&self.empty),
+                pgn.tags.get("Round").unwrap_or(&self.empty),
+                pgn.tags.get("Date").unwrap_or(&self.empty),
+                pgn.tags.get("UTCTime").unwrap_or(&self.empty),
+                pgn.tags.get("TimeControl").unwrap_or(&self.empty),
+                pgn.tags.get("White").unwrap_or(&self.empty),
+                pgn.tags.get("WhiteTitle").unwrap_or(&self.empty),
+                &parse_to_number(pgn.tags.get("WhiteElo")),
+                &parse_to_number(pgn.tags.get("WhiteFideId")),
+                pgn.tags.get("Black").unwrap_or(&self.empty),
+                pgn.tags.get("BlackTitle").unwrap_or(&self.empty),
+                &parse_to_number(pgn.tags.get("BlackElo")),
+                &parse_to_number(pgn.tags.get("BlackFideId")),
+                pgn.tags.get("ECO").unwrap_or(&self.empty),
+                pgn.tags.get("Opening").unwrap_or(&self.empty),
+                pgn.tags.get("Variation").unwrap_or(&self.empty),
+                pgn.tags.get("Result").unwrap_or(&self.empty),
+                &pgn.tags_text,
+                &pgn.moves_text,
+                &time_control_base,
+                &time_control_increment,
+                &time_control_class,
+                &(pgn.moves_fingerprint as i64),
+                &(pgn.moves.len() as i32),
+            ],
+        )?;
+
+        self.index_game(pgn)
+    }
+
+    /// Stores the move n-grams of `pgn` and what is found by replaying it:
+    /// its positions, where it entered an endgame and its pattern codes.
+    /// Games whose moves cannot be replayed get none of the latter.
+    fn index_game(&mut self, pgn: &Pgn) -> Result<(), postgres::error::Error> {
+        self.index_ngrams(pgn)?;
+        let replay = Replay::new(pgn).ok();
+        self.index_positions(pgn, replay.as_ref())?;
+        self.index_endgame(pgn, replay.as_ref())?;
+        self.index_pattern(pgn, replay.as_ref())
+    }
+
+    /// Replaces the rows of `pgn` in the move n-gram table, see
+    /// `sequence::ngrams`.
+    fn index_ngrams(&mut self, pgn: &Pgn) -> Result<(), postgres::error::Error> {
+        self.client
+            .execute("DELETE FROM move_ngram WHERE pgn_id = $1", &[&pgn.id])?;
+
+        let ngrams = ngrams(&pgn.moves);
+        let keys: Vec<i64> = ngrams.iter().map(|(key, _, _)| *key).collect();
+        let plies: Vec<i32> = ngrams.iter().map(|(_, ply, _)| *ply).collect();
+        let lengths: Vec<i16> = ngrams.iter().map(|(_, _, n)| *n).collect();
+
+        self.client
+            .execute(
+                "INSERT INTO move_ngram (key, pgn_id, ply, n)
+                SELECT unnest($1::BIGINT[]), $2, unnest($3::INT[]), unnest($4::SMALLINT[])",
+                &[&keys, &pgn.id, &plies, &lengths],
+            )
+            .map(|_| ())
+    }
+
+    /// Replaces the rows of `pgn` in the position table: the key of each
+    /// position reached and the move played from it, empty after the last
+    /// move.
+    fn index_positions(
+        &mut self,
+        pgn: &Pgn,
+        replay: Option<&Replay>,
+    ) -> Result<(), postgres::error::Error> {
+        self.client
+            .execute("DELETE FROM position WHERE pgn_id = $1", &[&pgn.id])?;
+
+        let replay = match replay {
+            Some(replay) => replay,
+            None => return Ok(()),
+        };
+
+        let keys: Vec<i64> = replay
+            .positions
+            .iter()
+            .map(|pos| polyglot_key(pos) as i64)
+            .collect();
+        let plies: Vec<i32> = (0..keys.len() as i32).collect();
+        let mut moves: Vec<&str> = pgn.moves.iter().map(|m| m.as_str()).collect();
+        moves.push("");
+
+        self.client
+            .execute(
+                "INSERT INTO position (key, pgn_id, ply, move)
+                SELECT unnest($1::BIGINT[]), $2, unnest($3::INT[]), unnest($4::VARCHAR[])",
+                &[&keys, &pgn.id, &plies, &moves],
+            )
+            .map(|_| ())
+    }
 
-        return self
-            .client
+    /// Replaces the analysis totals of the stored game `pgn_id`.
+    pub fn upsert_game_analysis(
+        &mut self,
+        pgn_id: &str,
+        analysis: &GameAnalysis,
+    ) -> Result<(), postgres::error::Error> {
+        let statement = "INSERT INTO game_analysis (
+                pgn_id,
+                white_moves,
+                white_acpl,
+                white_accuracy,
+                white_inaccuracies,
+                white_mistakes,
+                white_blunders,
+                black_moves,
+                black_acpl,
+                black_accuracy,
+                black_inaccuracies,
+                black_mistakes,
+                black_blunders)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)
+            ON CONFLICT (pgn_id) DO UPDATE SET
+                white_moves = EXCLUDED.white_moves,
+                white_acpl = EXCLUDED.white_acpl,
+                white_accuracy = EXCLUDED.white_accuracy,
+                white_inaccuracies = EXCLUDED.white_inaccuracies,
+                white_mistakes = EXCLUDED.white_mistakes,
+                white_blunders = EXCLUDED.white_blunders,
+                black_moves = EXCLUDED.black_moves,
+                black_acpl = EXCLUDED.black_acpl,
+                black_accuracy = EXCLUDED.black_accuracy,
+                black_inaccuracies = EXCLUDED.black_inaccuracies,
+                black_mistakes = EXCLUDED.black_mistakes,
+                black_blunders = EXCLUDED.black_blunders";
+
+        let counts = |player: &PlayerAnalysis| -> [i32; 4] {
+            [
+                player.moves as i32,
+                player.inaccuracies as i32,
+                player.mistakes as i32,
+                player.blunders as i32,
+            ]
+        };
+        let white = counts(&analysis.white);
+        let black = counts(&analysis.black);
+
+        self.client
             .execute(
                 statement,
                 &[
-                    &pgn.id,
-                    pgn.tags.get("Event").unwrap_or(&self.empty),
-                    pgn.tags.get("Site").unwrap_or(&self.empty),
-                    pgn.tags.get("Round").unwrap_or(&self.empty),
-                    pgn.tags.get("Date").unwrap_or(&self.empty),
-                    pgn.tags.get("UTCTime").unwrap_or(&self.empty),
-                    pgn.tags.get("TimeControl").unwrap_or(&self.empty),
-                    pgn.tags.get("White").unwrap_or(&self.empty),
-                    pgn.tags.get("WhiteTitle").unwrap_or(&self.empty),
-                    &parse_to_number(pgn.tags.get("WhiteElo")),
-                    &parse_to_number(pgn.tags.get("WhiteFideId")),
-                    pgn.tags.get("Black").unwrap_or(&self.empty),
-                    pgn.tags.get("BlackTitle").unwrap_or(&self.empty),
-                    &parse_to_number(pgn.tags.get("BlackElo")),
-                    &parse_to_number(pgn.tags.get("BlackFideId")),
-                    pgn.tags.get("ECO").unwrap_or(&self.empty),
-                    pgn.tags.get("Opening").unwrap_or(&self.empty),
-                    pgn.tags.get("Variation").unwrap_or(&self.empty),
-                    pgn.tags.get("Result").unwrap_or(&self.empty),
-                    &pgn.tags_text,
-                    &pgn.moves_text,
+                    &pgn_id,
+                    &white[0],
+                    &analysis.white.acpl,
+                    &analysis.white.accuracy,
+                    &white[1],
+                    &white[2],
+                    &white[3],
+                    &black[0],
+                    &analysis.black.acpl,
+                    &analysis.black.accuracy,
+                    &black[1],
+                    &black[2],
+                    &black[3],
                 ],
             )
-            .map(|_| ());
+            .map(|_| ())
+    }
+
+    /// Sets the material signature and category of the first endgame
+    /// position of `pgn`, empty if it has none.
+    fn index_endgame(
+        &mut self,
+        pgn: &Pgn,
+        replay: Option<&Replay>,
+    ) -> Result<(), postgres::error::Error> {
+        let endgame = replay.and_then(Endgame::find);
+        let (material, category) = endgame
+            .as_ref()
+            .map_or(("", ""), |e| (e.signature.as_str(), e.category.as_str()));
+
+        self.client
+            .execute(
+                "UPDATE pgn SET material = $2, endgame = $3 WHERE id = $1",
+                &[&pgn.id, &material, &category],
+            )
+            .map(|_| ())
     }
+
+    /// Replaces the pattern codes of `pgn`, see `pattern::game_codes`.
+    fn index_pattern(
+        &mut self,
+        pgn: &Pgn,
+        replay: Option<&Replay>,
+    ) -> Result<(), postgres::error::Error> {
+        self.client
+            .execute("DELETE FROM game_pattern WHERE pgn_id = $1", &[&pgn.id])?;
+
+        match replay {
+            Some(replay) => self
+                .client
+                .execute(
+                    "INSERT INTO game_pattern (pgn_id, codes) VALUES ($1, $2)",
+                    &[&pgn.id, &game_codes(replay)],
+                )
+                .map(|_| ()),
+            None => Ok(()),
+        }
+    }
+
+    /// Calls `f` with each game matching the filters of `query` that has a
+    /// position matching `pattern`, and the ply of the first such position.
+    /// Only games whose pattern codes include those the pattern requires are
+    /// replayed. The sort order, offset and limit of `query` apply to the
+    /// games matching. Returns the number of games passed to `f`.
+    pub fn search_pattern<F>(
+        &mut self,
+        pattern: &Pattern,
+        query: &Query,
+        mut f: F,
+    ) -> Result<usize, Box<dyn std::error::Error>>
+    where
+        F: FnMut(Pgn, usize) -> Result<(), Box<dyn std::error::Error>>,
+    {
+        let mut params: Params = vec![Box::new(pattern.required_codes())];
+        let mut statement = "SELECT g.id, g.tags, g.moves FROM pgn g
+            JOIN game_pattern gp ON gp.pgn_id = g.id
+            WHERE gp.codes @> $1"
+            .to_string();
+        let conditions = query.conditions("g", &mut params);
+        if !conditions.is_empty() {
+            statement.push_str(" AND ");
+            statement.push_str(conditions.as_str());
+        }
+        statement.push_str(query.order_by().as_str());
+        let params: Vec<&(dyn ToSql + Sync)> = params.iter().map(|p| p.as_ref()).collect();
+
+        let mut rows = self.client.query_raw(statement.as_str(), params)?;
+
+        let (mut skip, limit) = query.bounds();
+        let mut count: usize = 0;
+        while let Some(row) = rows.next()? {
+            if limit == Some(count) {
+                break;
+            }
+            let pgn = row_to_pgn(&self.parser, &row);
+            let ply = Replay::new(&pgn)
+                .ok()
+                .and_then(|replay| pattern.first_match(&replay));
+            if let Some(ply) = ply {
+                if skip > 0 {
+                    skip -= 1;
+                    continue;
+                }
+                f(pgn, ply)?;
+                count += 1;
+            }
+        }
+
+        Ok(count)
+    }
+
+    /// Calls `f` with each game matching the filters of `query` where
+    /// `sequence` starts within the inclusive range of 1-based plies, and the
+    /// plies where it does. Candidates are looked up in the move n-gram
+    /// table. The sort order, offset and limit of `query` apply to the games
+    /// matching. Returns the number of games passed to `f`.
+    pub fn search_moves<F>(
+        &mut self,
+        sequence: &MoveSequence,
+        min_ply: Option<usize>,
+        max_ply: Option<usize>,
+        query: &Query,
+        mut f: F,
+    ) -> Result<usize, Box<dyn std::error::Error>>
+    where
+        F: FnMut(Pgn, Vec<usize>) -> Result<(), Box<dyn std::error::Error>>,
+    {
+        let (key, n) = sequence.index_key();
+        let min = min_ply.map_or(1, |ply| ply as i32);
+        let max = max_ply.map_or(i32::MAX, |ply| ply as i32);
+        let mut params: Params = vec![Box::new(key), Box::new(n), Box::new(min), Box::new(max)];
+        let mut statement = "SELECT g.id, g.tags, g.moves FROM pgn g
+            WHERE g.id IN (
+                SELECT pgn_id FROM move_ngram
+                WHERE key = $1 AND n = $2 AND ply BETWEEN $3 AND $4)"
+            .to_string();
+        let conditions = query.conditions("g", &mut params);
+        if !conditions.is_empty() {
+            statement.push_str(" AND ");
+            statement.push_str(conditions.as_str());
+        }
+        statement.push_str(query.order_by().as_str());
+        let params: Vec<&(dyn ToSql + Sync)> = params.iter().map(|p| p.as_ref()).collect();
+
+        let mut rows = self.client.query_raw(statement.as_str(), params)?;
+
+        let (mut skip, limit) = query.bounds();
+        let mut count: usize = 0;
+        while let Some(row) = rows.next()? {
+            if limit == Some(count) {
+                break;
+            }
+            let pgn = row_to_pgn(&self.parser, &row);
+            let plies = sequence.find(&pgn, min_ply, max_ply);
+            if !plies.is_empty() {
+                if skip > 0 {
+                    skip -= 1;
+                    continue;
+                }
+                f(pgn, plies)?;
+                count += 1;
+            }
+        }
+
+        Ok(count)
+    }
+
+    /// Fills the position, pattern and move n-gram tables and endgame
+    /// columns for the games matching `query`, e.g. games stored before they
+    /// existed. Returns the number of games indexed.
+    pub fn reindex_positions(&mut self, query: &Query) -> Result<usize, postgres::error::Error> {
+        self.for_each_page(query, |store, games| {
+            games.iter().try_for_each(|pgn| store.index_game(pgn))
+        })
+    }
+
+    /// Calls `f` with the store and each game matching `query` and stores the
+    /// games it returns true for, stopping at the first error `f` returns.
+    /// Returns the number of games stored.
+    pub fn update_each<F>(
+        &mut self,
+        query: &Query,
+        mut f: F,
+    ) -> Result<usize, Box<dyn std::error::Error>>
+    where
+        F: FnMut(&mut Self, &mut Pgn) -> Result<bool, Box<dyn std::error::Error>>,
+    {
+        let mut updated: usize = 0;
+        self.for_each_page::<_, Box<dyn std::error::Error>>(query, |store, games| {
+            for mut pgn in games {
+                if f(store, &mut pgn)? {
+                    store.upsert_pgn(&pgn)?;
+                    updated += 1;
+                }
+            }
+            Ok(())
+        })?;
+
+        Ok(updated)
+    }
+
+    /// Calls `f` with the games matching the filters of `query` a page at a
+    /// time, in the order of their ids, so that `f` can write to the store.
+    /// Pages start after the last id read rather than at an offset, so games
+    /// that `f` changes are neither skipped nor read twice. Returns the
+    /// number of games read.
+    fn for_each_page<F, E>(&mut self, query: &Query, mut f: F) -> Result<usize, E>
+    where
+        F: FnMut(&mut Self, Vec<Pgn>) -> Result<(), E>,
+        E: From<postgres::error::Error>,
+    {
+        const PAGE_SIZE: i64 = 1000;
+
+        let mut count: usize = 0;
+        let mut last_id = String::new();
+        loop {
+            let mut params: Params = vec![Box::new(last_id), Box::new(PAGE_SIZE)];
+            let mut statement = "SELECT id, tags, moves FROM pgn WHERE id > $1".to_string();
+            let conditions = query.conditions("pgn", &mut params);
+            if !conditions.is_empty() {
+                statement.push_str(" AND ");
+                statement.push_str(conditions.as_str());
+            }
+            statement.push_str(" ORDER BY id LIMIT $2");
+            let params: Vec<&(dyn ToSql + Sync)> = params.iter().map(|p| p.as_ref()).collect();
+
+            let rows = self.client.query(statement.as_str(), &params)?;
+            let games: Vec<Pgn> = rows
+                .iter()
+                .map(|row| row_to_pgn(&self.parser, row))
+                .collect();
+            let read = games.len();
+            last_id = match games.last() {
+                Some(pgn) => pgn.id.clone(),
+                None => return Ok(count),
+            };
+
+            f(self, games)?;
+            count += read;
+
+            if (read as i64) < PAGE_SIZE {
+                return Ok(count);
+            }
+        }
+    }
+
+    /// Report on the games of `player`, given as a FIDE id or a name where
+    /// `%` and `_` act as wildcards, among the games matching the filters of
+    /// `query`. Lists at most `openings` openings per colour. `None` if the
+    /// player has no games.
+    pub fn player_report(
+        &mut self,
+        player: &str,
+        query: &Query,
+        openings: usize,
+    ) -> Result<Option<PlayerReport>, postgres::error::Error> {
+        let (is_white, is_black, param): (&str, &str, Box<dyn ToSql + Sync>) =
+            match player.parse::<i32>() {
+                Ok(fide_id) => ("white_fide = $1", "black_fide = $1", Box::new(fide_id)),
+                Err(_) => (
+                    "white ILIKE $1",
+                    "black ILIKE $1",
+                    Box::new(player.to_string()),
+                ),
+            };
+        let mut params: Params = vec![param];
+        let mut conditions = query.conditions("pgn", &mut params);
+        if !conditions.is_empty() {
+            conditions.insert_str(0, " AND ");
+        }
+
+        // The games from the point of view of the player, with `score` 1, 0.5
+        // or 0, and NULL for unfinished games.
+        let games = format!(
+            "WITH games AS (
+                SELECT 'w' AS colour, white AS name, date, eco, opening, ply_count,
+                    black_elo AS opponent_elo,
+                    CASE result WHEN '1-0' THEN 1.0 WHEN '1/2-1/2' THEN 0.5
+                        WHEN '0-1' THEN 0.0 END AS score
+                FROM pgn WHERE {is_white}{conditions}
+                UNION ALL
+                SELECT 'b', black, date, eco, opening, ply_count, white_elo,
+                    CASE result WHEN '0-1' THEN 1.0 WHEN '1/2-1/2' THEN 0.5
+                        WHEN '1-0' THEN 0.0 END
+                FROM pgn WHERE {is_black}{conditions})",
+            is_white = is_white,
+            is_black = is_black,
+            conditions = conditions
+        );
+        let score_columns = |filter: &str| -> String {
+            ["score IS NOT NULL", "score = 1", "score = 0.5", "score = 0"]
+                .iter()
+                .map(|condition| format!("COUNT(*) FILTER (WHERE {}{})", condition, filter))
+                .collect::<Vec<String>>()
+                .join(", ")
+        };
+        let score_at = |row: &postgres::Row, index: usize| Score {
+            games: row.get(index),
+            wins: row.get(index + 1),
+            draws: row.get(index + 2),
+            losses: row.get(index + 3),
+        };
+
+        let params: Vec<&(dyn ToSql + Sync)> = params.iter().map(|p| p.as_ref()).collect();
+        let mut select = |statement: String| {
+            self.client
+                .query(format!("{} {}", games, statement).as_str(), &params)
+        };
+
+        let rows = select(
+            "SELECT name FROM games GROUP BY name ORDER BY COUNT(*) DESC, name LIMIT 1".to_string(),
+        )?;
+        let name: String = match rows.first() {
+            Some(row) => row.get(0),
+            None => return Ok(None),
+        };
+        let average_plies: Option<f64> =
+            select("SELECT AVG(ply_count)::FLOAT8 FROM games".to_string())?[0].get(0);
+
+        let mut report = PlayerReport {
+            name,
+            white: Score::default(),
+            black: Score::default(),
+            years: Vec::new(),
+            white_openings: Vec::new(),
+            black_openings: Vec::new(),
+            average_plies,
+            bands: Vec::new(),
+        };
+
+        for row in select(format!(
+            "SELECT colour, {} FROM games GROUP BY colour",
+            score_columns("")
+        ))? {
+            let colour: String = row.get(0);
+            match colour.as_str() {
+                "w" => report.white = score_at(&row, 1),
+                _ => report.black = score_at(&row, 1),
+            }
+        }
+
+        for row in select(format!(
+            "SELECT LEFT(date, 4) AS year, {}, {},
+                (AVG(opponent_elo) FILTER (WHERE opponent_elo > 0 AND score IS NOT NULL))::FLOAT8
+            FROM games GROUP BY year ORDER BY year",
+            score_columns(""),
+            score_columns(" AND opponent_elo > 0")
+        ))? {
+            report.years.push(YearStats {
+                year: row.get(0),
+                score: score_at(&row, 1),
+                rated: score_at(&row, 5),
+                average_opponent: row.get(9),
+            });
+        }
+
+        for row in select(format!(
+            "SELECT colour, eco, opening, {} FROM games
+            GROUP BY colour, eco, opening ORDER BY COUNT(*) DESC, eco, opening",
+            score_columns("")
+        ))? {
+            let colour: String = row.get(0);
+            let list = match colour.as_str() {
+                "w" => &mut report.white_openings,
+                _ => &mut report.black_openings,
+            };
+            if list.len() < openings {
+                list.push(OpeningStats {
+                    eco: row.get(1),
+                    opening: row.get(2),
+                    score: score_at(&row, 3),
+                });
+            }
+        }
+
+        for row in select(format!(
+            "SELECT (opponent_elo / {band} * {band})::INT AS band, {columns} FROM games
+            WHERE opponent_elo > 0 GROUP BY band ORDER BY band",
+            band = RATING_BAND,
+            columns = score_columns("")
+        ))? {
+            report.bands.push(BandStats {
+                min_elo: row.get(0),
+                score: score_at(&row, 1),
+            });
+        }
+
+        Ok(Some(report))
+    }
+
+    /// Finished games matching the filters of `query`, oldest first, to be
+    /// rated by a `RatingPool`. Games without a known year cannot be placed
+    /// in a period and are left out; unknown months and days sort first.
+    pub fn rated_games(&mut self, query: &Query) -> Result<Vec<RatedGame>, postgres::error::Error> {
+        let mut params: Params = Vec::new();
+        let conditions = query.conditions("pgn", &mut params);
+
+        let mut statement = "SELECT date, white, black, result FROM pgn
+            WHERE result IN ('1-0', '1/2-1/2', '0-1')
+                AND date ~ '^[0-9]{4}'"
+            .to_string();
+        if !conditions.is_empty() {
+            statement.push_str(" AND ");
+            statement.push_str(conditions.as_str());
+        }
+        statement.push_str(format!(" ORDER BY {}, id", DATE_ORDER_SQL).as_str());
+
+        let params: Vec<&(dyn ToSql + Sync)> = params.iter().map(|p| p.as_ref()).collect();
+        let rows = self.client.query(statement.as_str(), &params)?;
+
+        Ok(rows
+            .iter()
+            .map(|row| {
+                let result: &str = row.get(3);
+                RatedGame {
+                    date: row.get(0),
+                    white: row.get(1),
+                    black: row.get(2),
+                    score: match result {
+                        "1-0" => 1.0,
+                        "0-1" => 0.0,
+                        _ => 0.5,
+                    },
+                }
+            })
+            .collect())
+    }
+
+    /// Replaces the rating history of `pool` with `history`.
+    pub fn replace_rating_history(
+        &mut self,
+        pool: &str,
+        history: &[RatingEntry],
+    ) -> Result<(), postgres::error::Error> {
+        let players: Vec<&str> = history.iter().map(|e| e.player.as_str()).collect();
+        let periods: Vec<&str> = history.iter().map(|e| e.period.as_str()).collect();
+        let games: Vec<i32> = history.iter().map(|e| e.games).collect();
+        let ratings: Vec<f64> = history.iter().map(|e| e.rating).collect();
+        let deviations: Vec<Option<f64>> = history.iter().map(|e| e.deviation).collect();
+        let volatilities: Vec<Option<f64>> = history.iter().map(|e| e.volatility).collect();
+
+        let mut transaction = self.client.transaction()?;
+        transaction.execute("DELETE FROM rating_history WHERE pool = $1", &[&pool])?;
+        transaction.execute(
+            "INSERT INTO rating_history
+                (pool, player, period, games, rating, deviation, volatility)
+            SELECT $1, unnest($2::TEXT[]), unnest($3::TEXT[]), unnest($4::INT[]),
+                unnest($5::FLOAT8[]), unnest($6::FLOAT8[]), unnest($7::FLOAT8[])",
+            &[
+                &pool,
+                &players,
+                &periods,
+                &games,
+                &ratings,
+                &deviations,
+                &volatilities,
+            ],
+        )?;
+        transaction.commit()
+    }
+
+    /// Moves played from `pos` in the games matching `query`, most played
+    /// first. Only the filters of `query` are used.
+    pub fn explore(
+        &mut self,
+        pos: &Chess,
+        query: &Query,
+    ) -> Result<Vec<ExplorerMove>, postgres::error::Error> {
+        let mut params: Params = vec![Box::new(polyglot_key(pos) as i64)];
+        let mut conditions = query.conditions("g", &mut params);
+        if !conditions.is_empty() {
+            conditions.insert_str(0, " AND ");
+        }
+
+        // The last game of each move is the one with the latest known date,
+        // unknown parts of a date counting as earliest.
+        let statement = format!(
+            "WITH moves AS (
+                SELECT p.move, g.id, g.white, g.black, g.date, g.result, g.white_elo, g.black_elo
+                FROM position p JOIN pgn g ON g.id = p.pgn_id
+                WHERE p.key = $1 AND p.move <> ''{}),
+            last_games AS (
+                SELECT DISTINCT ON (move) move, id, white, black, date, result
+                FROM moves
+                WHERE NOT {}
+                ORDER BY move, {} DESC, id DESC)
+            SELECT m.move, COUNT(*),
+                COUNT(*) FILTER (WHERE m.result = '1-0'),
+                COUNT(*) FILTER (WHERE m.result = '1/2-1/2'),
+                COUNT(*) FILTER (WHERE m.result = '0-1'),
+                (AVG((m.white_elo + m.black_elo) / 2.0)
+                    FILTER (WHERE m.white_elo > 0 AND m.black_elo > 0))::FLOAT8,
+                l.id, l.white, l.black, l.date, l.result
+            FROM moves m LEFT JOIN last_games l ON l.move = m.move
+            GROUP BY m.move, l.id, l.white, l.black, l.date, l.result
+            ORDER BY COUNT(*) DESC, m.move",
+            conditions, DATE_UNKNOWN_SQL, DATE_ORDER_SQL
+        );
+
+        let params: Vec<&(dyn ToSql + Sync)> = params.iter().map(|p| p.as_ref()).collect();
+        let rows = self.client.query(statement.as_str(), &params)?;
+
+        Ok(rows
+            .iter()
+            .map(|row| ExplorerMove {
+                san: row.get(0),
+                games: row.get(1),
+                white_wins: row.get(2),
+                draws: row.get(3),
+                black_wins: row.get(4),
+                average_elo: row.get(5),
+                last_game: row.get::<_, Option<String>>(6).map(|id| GameSummary {
+                    id,
+                    white: row.get(7),
+                    black: row.get(8),
+                    date: row.get(9),
+                    result: row.get(10),
+                }),
+            })
+            .collect())
+    }
+
+    /// Writes the games matching `query` to `out` in PGN export format.
+    /// Returns the number of games written.
+    pub fn export(
+        &mut self,
+        query: &Query,
+        out: &mut dyn Write,
+    ) -> Result<usize, Box<dyn std::error::Error>> {
+        let mut writer = Writer::new(out);
+        self.for_each(query, |pgn| writer.write(&pgn).map_err(|e| e.into()))
+    }
+
+    /// Calls `f` with the games matching `query`, reading them one at a time.
+    /// Returns the number of games read.
+    pub fn for_each<F>(
+        &mut self,
+        query: &Query,
+        mut f: F,
+    ) -> Result<usize, Box<dyn std::error::Error>>
+    where
+        F: FnMut(Pgn) -> Result<(), Box<dyn std::error::Error>>,
+    {
+        let (statement, params) = query.to_sql("id, tags, moves");
+        let params: Vec<&(dyn ToSql + Sync)> = params.iter().map(|p| p.as_ref()).collect();
+
+        let mut rows = self.client.query_raw(statement.as_str(), params)?;
+
+        let mut count: usize = 0;
+        while let Some(row) = rows.next()? {
+            f(row_to_pgn(&self.parser, &row))?;
+            count += 1;
+        }
+
+        Ok(count)
+    }
+
+    /// Stores `pgn` unless a richer copy of the same game is already stored,
+    /// in which case nothing is written and `false` is returned. Poorer copies
+    /// are removed.
+    pub fn upsert_pgn_dedup(&mut self, pgn: &Pgn) -> Result<bool, postgres::error::Error> {
+        let statement = "SELECT id, tags, moves FROM pgn
+            WHERE moves_fingerprint = $1 AND white = $2 AND black = $3 AND date = $4
+                AND id <> $5";
+
+        let rows = self.client.query(
+            statement,
+            &[
+                &(pgn.moves_fingerprint as i64),
+                pgn.tags.get("White").unwrap_or(&self.empty),
+                pgn.tags.get("Black").unwrap_or(&self.empty),
+                pgn.tags.get("Date").unwrap_or(&self.empty),
+                &pgn.id,
+            ],
+        )?;
+
+        let richness = pgn.richness();
+        let mut poorer: Vec<String> = Vec::new();
+        for row in rows.iter() {
+            if richness_of(row) >= richness {
+                return Ok(false);
+            }
+            poorer.push(row.get(0));
+        }
+
+        if !poorer.is_empty() {
+            self.client
+                .execute("DELETE FROM pgn WHERE id = ANY($1)", &[&poorer])?;
+        }
+
+        self.upsert_pgn(pgn).map(|_| true)
+    }
+
+    /// Removes duplicated games, i.e. games with the same moves, players and
+    /// date, keeping the richest copy of each. Returns the number of games
+    /// removed.
+    pub fn dedup(&mut self) -> Result<usize, postgres::error::Error> {
+        let statement = "SELECT moves_fingerprint, white, black, date FROM pgn
+            WHERE moves_fingerprint <> 0
+            GROUP BY moves_fingerprint, white, black, date
+            HAVING COUNT(*) > 1";
+
+        let clusters = self.client.query(statement, &[])?;
+
+        let mut removed: usize = 0;
+        for cluster in clusters.iter() {
+            let fingerprint: i64 = cluster.get(0);
+            let white: String = cluster.get(1);
+            let black: String = cluster.get(2);
+            let date: String = cluster.get(3);
+
+            let rows = self.client.query(
+                "SELECT id, tags, moves FROM pgn
+                WHERE moves_fingerprint = $1 AND white = $2 AND black = $3 AND date = $4
+                ORDER BY id",
+                &[&fingerprint, &white, &black, &date],
+            )?;
+
+            let keep = pgn::richest(rows.iter().map(richness_of)).unwrap_or(0);
+
+            let others: Vec<String> = rows
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| *i != keep)
+                .map(|(_, row)| row.get(0))
+                .collect();
+
+            removed += self
+                .client
+                .execute("DELETE FROM pgn WHERE id = ANY($1)", &[&others])?
+                as usize;
+        }
+
+        Ok(removed)
+    }
+}
+
+/// Base, increment and class of the `TimeControl` tag of `pgn`, `0` and
+/// empty when unknown or too large for the columns.
+pub(super) fn time_control_columns(pgn: &Pgn) -> (i32, i32, &'static str) {
+    let time_control = pgn.time_control();
+    let base = time_control.as_ref().and_then(|tc| tc.base()).unwrap_or(0);
+    let increment = time_control
+        .as_ref()
+        .and_then(|tc| tc.increment())
+        .unwrap_or(0);
+    let class = time_control
+        .as_ref()
+        .and_then(|tc| tc.class())
+        .map_or("", |class| class.as_str());
+
+    (
+        i32::try_from(base).unwrap_or(0),
+        i32::try_from(increment).unwrap_or(0),
+        class,
+    )
+}
+
+// Rows were validated when stored, if the moves can no longer be
+// extracted the game is still returned with its texts.
+pub(super) fn row_to_pgn(parser: &Parser, row: &postgres::Row) -> Pgn {
+    let id: String = row.get(0);
+    let tags_text: String = row.get(1);
+    let moves_text: String = row.get(2);
+
+    match parser.parse(id.clone(), tags_text.clone(), moves_text.clone()) {
+        Ok(pgn) => pgn,
+        Err(_) => {
+            let mut pgn = Pgn::new("", 0);
+            pgn.id = id;
+            pgn.tags = tags_text
+                .lines()
+                .filter_map(|line| parser.parse_tag(line.trim()))
+                .collect();
+            pgn.tags_text = tags_text;
+            pgn.moves_text = moves_text;
+            pgn
+        }
+    }
+}
+
+fn richness_of(row: &postgres::Row) -> usize {
+    let tags: &str = row.get(1);
+    let moves: &str = row.get(2);
+    pgn::richness(tags, moves)
 }
 
 fn parse_to_number(o: Option<&String>) -> i32 {
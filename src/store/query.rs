@@ -0,0 +1,392 @@
+use std::str::FromStr;
+
+use postgres::types::ToSql;
+use simple_error::SimpleError;
+
+use crate::board::material::EndgameCategory;
+use crate::pgn::{TimeControlClass, DATE_ORDER_SQL, DATE_UNKNOWN_SQL};
+
+pub(crate) type Params = Vec<Box<dyn ToSql + Sync>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortBy {
+    Id,
+    Date,
+    WhiteElo,
+    BlackElo,
+    /// Average rating of both players.
+    Elo,
+    Plies,
+}
+
+impl SortBy {
+    fn column(&self) -> &'static str {
+        match self {
+            SortBy::Id => "id",
+            SortBy::Date => DATE_ORDER_SQL,
+            SortBy::WhiteElo => "white_elo",
+            SortBy::BlackElo => "black_elo",
+            SortBy::Elo => "(white_elo + black_elo)",
+            SortBy::Plies => "ply_count",
+        }
+    }
+
+    /// Condition true for the games without a value to sort on, which are
+    /// sorted last in either direction.
+    fn unknown(&self) -> Option<&'static str> {
+        match self {
+            SortBy::Date => Some(DATE_UNKNOWN_SQL),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for SortBy {
+    type Err = SimpleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "id" => Ok(SortBy::Id),
+            "date" => Ok(SortBy::Date),
+            "white-elo" => Ok(SortBy::WhiteElo),
+            "black-elo" => Ok(SortBy::BlackElo),
+            "elo" => Ok(SortBy::Elo),
+            "plies" => Ok(SortBy::Plies),
+            _ => Err(SimpleError::new(format!("unknown sort key: {}", s))),
+        }
+    }
+}
+
+/// Filters, order and page of games to read from a store.
+///
+/// ```
+/// use mudfish::store::{Query, SortBy};
+///
+/// let query = Query::new()
+///     .player("Carlsen, Magnus")
+///     .elo(Some(2600), None)
+///     .sort_by(SortBy::Date, true)
+///     .limit(20);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Query {
+    player: Option<String>,
+    min_elo: Option<i32>,
+    max_elo: Option<i32>,
+    date_from: Option<String>,
+    date_to: Option<String>,
+    eco_from: Option<String>,
+    eco_to: Option<String>,
+    result: Option<String>,
+    event: Option<String>,
+    time_control_class: Option<TimeControlClass>,
+    min_plies: Option<i32>,
+    material: Option<String>,
+    endgame: Option<EndgameCategory>,
+    sort_by: Option<SortBy>,
+    descending: bool,
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+impl Query {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Games played by `player` with either colour. `%` and `_` act as
+    /// wildcards, matching is case insensitive.
+    pub fn player(mut self, player: impl Into<String>) -> Self {
+        self.player = Some(player.into());
+        self
+    }
+
+    /// Games where both players are rated within the inclusive range.
+    pub fn elo(mut self, min: Option<i32>, max: Option<i32>) -> Self {
+        self.min_elo = min;
+        self.max_elo = max;
+        self
+    }
+
+    /// Games played within the inclusive range of `YYYY.MM.DD` dates. Bounds
+    /// can leave out the day or the month: `2024` to `2024` is the whole
+    /// year. Unknown parts of dates count as `00`.
+    pub fn date(mut self, from: Option<String>, to: Option<String>) -> Self {
+        self.date_from = from.map(|from| pad_date(from.as_str(), '0'));
+        self.date_to = to.map(|to| pad_date(to.as_str(), '9'));
+        self
+    }
+
+    /// Games classified within the inclusive range of ECO codes.
+    pub fn eco(mut self, from: Option<String>, to: Option<String>) -> Self {
+        self.eco_from = from;
+        self.eco_to = to;
+        self
+    }
+
+    pub fn result(mut self, result: impl Into<String>) -> Self {
+        self.result = Some(result.into());
+        self
+    }
+
+    /// Games whose event contains `event`, case insensitive.
+    pub fn event(mut self, event: impl Into<String>) -> Self {
+        self.event = Some(event.into());
+        self
+    }
+
+    pub fn time_control_class(mut self, class: TimeControlClass) -> Self {
+        self.time_control_class = Some(class);
+        self
+    }
+
+    pub fn min_plies(mut self, plies: i32) -> Self {
+        self.min_plies = Some(plies);
+        self
+    }
+
+    /// Games whose first endgame position has the material signature
+    /// `material`, e.g. `KRPvKR`. `%` and `_` act as wildcards.
+    pub fn material(mut self, material: impl Into<String>) -> Self {
+        self.material = Some(material.into());
+        self
+    }
+
+    /// Games whose first endgame position is of the category.
+    pub fn endgame(mut self, category: EndgameCategory) -> Self {
+        self.endgame = Some(category);
+        self
+    }
+
+    pub fn sort_by(mut self, sort_by: SortBy, descending: bool) -> Self {
+        self.sort_by = Some(sort_by);
+        self.descending = descending;
+        self
+    }
+
+    pub fn limit(mut self, limit: i64) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn offset(mut self, offset: i64) -> Self {
+        self.offset = Some(offset);
+        self
+    }
+
+    /// Conditions on the `pgn` table, aliased as `alias`, joined with `AND`
+    /// and numbered after the `params` already given. Empty when there is no
+    /// filter.
+    pub(crate) fn conditions(&self, alias: &str, params: &mut Params) -> String {
+        let mut conditions: Vec<String> = Vec::new();
+        let mut add = |condition: &str, param: Box<dyn ToSql + Sync>| {
+            params.push(param);
+            conditions.push(
+                condition
+                    .replace("{t}", alias)
+                    .replace("{p}", format!("${}", params.len()).as_str()),
+            );
+        };
+
+        if let Some(player) = &self.player {
+            add(
+                "({t}.white ILIKE {p} OR {t}.black ILIKE {p})",
+                Box::new(player.clone()),
+            );
+        }
+        if let Some(min_elo) = self.min_elo {
+            add(
+                "LEAST({t}.white_elo, {t}.black_elo) >= {p}",
+                Box::new(min_elo),
+            );
+        }
+        // Unrated players are stored with an Elo of 0.
+        if let Some(max_elo) = self.max_elo {
+            add(
+                "LEAST({t}.white_elo, {t}.black_elo) > 0 \
+                AND GREATEST({t}.white_elo, {t}.black_elo) <= {p}",
+                Box::new(max_elo),
+            );
+        }
+        if let Some(date_from) = &self.date_from {
+            add(
+                "translate({t}.date, '?', '0') >= {p}",
+                Box::new(date_from.clone()),
+            );
+        }
+        if let Some(date_to) = &self.date_to {
+            // Games without a known year are not within any range.
+            add(
+                "translate({t}.date, '?', '0') BETWEEN '0001' AND {p}",
+                Box::new(date_to.clone()),
+            );
+        }
+        if let Some(eco_from) = &self.eco_from {
+            add("{t}.eco >= {p}", Box::new(eco_from.clone()));
+        }
+        if let Some(eco_to) = &self.eco_to {
+            add("{t}.eco <= {p}", Box::new(eco_to.clone()));
+        }
+        if let Some(result) = &self.result {
+            add("{t}.result = {p}", Box::new(result.clone()));
+        }
+        if let Some(event) = &self.event {
+            add("{t}.event ILIKE '%' || {p} || '%'", Box::new(event.clone()));
+        }
+        if let Some(class) = self.time_control_class {
+            add(
+                "{t}.time_control_class = {p}",
+                Box::new(class.as_str().to_string()),
+            );
+        }
+        if let Some(min_plies) = self.min_plies {
+            add("{t}.ply_count >= {p}", Box::new(min_plies));
+        }
+        if let Some(material) = &self.material {
+            let condition = if material.contains(['%', '_']) {
+                "{t}.material LIKE {p}"
+            } else {
+                "{t}.material = {p}"
+            };
+            add(condition, Box::new(material.clone()));
+        }
+        if let Some(category) = self.endgame {
+            add("{t}.endgame = {p}", Box::new(category.as_str().to_string()));
+        }
+
+        conditions.join(" AND ")
+    }
+
+    /// `ORDER BY` clause of the sort order, starting with a space, or empty
+    /// when there is none.
+    pub(crate) fn order_by(&self) -> String {
+        let sort_by = match self.sort_by {
+            Some(sort_by) => sort_by,
+            None => return String::new(),
+        };
+
+        let mut clause = " ORDER BY ".to_string();
+        if let Some(unknown) = sort_by.unknown() {
+            clause.push_str(unknown);
+            clause.push_str(", ");
+        }
+        clause.push_str(sort_by.column());
+        if self.descending {
+            clause.push_str(" DESC");
+        }
+        clause.push_str(", id");
+        clause
+    }
+
+    /// Number of matching games to skip and most to return, for searches
+    /// that filter the rows of their statement further.
+    pub(crate) fn bounds(&self) -> (usize, Option<usize>) {
+        let offset = self.offset.map_or(0, |offset| offset.max(0) as usize);
+        let limit = self.limit.map(|limit| limit.max(0) as usize);
+        (offset, limit)
+    }
+
+    /// `SELECT` statement returning `columns` of the matching games.
+    pub(crate) fn to_sql(&self, columns: &str) -> (String, Params) {
+        let mut params: Params = Vec::new();
+        let mut statement = format!("SELECT {} FROM pgn", columns);
+
+        let conditions = self.conditions("pgn", &mut params);
+        if !conditions.is_empty() {
+            statement.push_str(" WHERE ");
+            statement.push_str(conditions.as_str());
+        }
+
+        statement.push_str(self.order_by().as_str());
+
+        if let Some(limit) = self.limit {
+            params.push(Box::new(limit));
+            statement.push_str(format!(" LIMIT ${}", params.len()).as_str());
+        }
+
+        if let Some(offset) = self.offset {
+            params.push(Box::new(offset));
+            statement.push_str(format!(" OFFSET ${}", params.len()).as_str());
+        }
+
+        (statement, params)
+    }
+}
+
+/// `date` completed to `YYYY.MM.DD` with `fill` for the missing digits.
+fn pad_date(date: &str, fill: char) -> String {
+    let mut padded = date.to_string();
+    for c in "YYYY.MM.DD".chars().skip(date.chars().count()) {
+        padded.push(if c == '.' { '.' } else { fill });
+    }
+    padded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_statement() {
+        let (statement, params) = Query::new().to_sql("id");
+        assert_eq!(statement, "SELECT id FROM pgn");
+        assert!(params.is_empty());
+
+        let (statement, params) = Query::new()
+            .player("Carlsen%")
+            .elo(Some(2500), None)
+            .time_control_class(TimeControlClass::Blitz)
+            .sort_by(SortBy::Elo, true)
+            .limit(10)
+            .offset(20)
+            .to_sql("id, tags");
+        assert_eq!(
+            statement,
+            "SELECT id, tags FROM pgn \
+            WHERE (pgn.white ILIKE $1 OR pgn.black ILIKE $1) \
+            AND LEAST(pgn.white_elo, pgn.black_elo) >= $2 \
+            AND pgn.time_control_class = $3 \
+            ORDER BY (white_elo + black_elo) DESC, id LIMIT $4 OFFSET $5"
+        );
+        assert_eq!(params.len(), 5);
+
+        let (statement, params) = Query::new()
+            .material("KR%vKR%")
+            .endgame(EndgameCategory::Rook)
+            .to_sql("id");
+        assert_eq!(
+            statement,
+            "SELECT id FROM pgn WHERE pgn.material LIKE $1 AND pgn.endgame = $2"
+        );
+        assert_eq!(params.len(), 2);
+
+        let (statement, _) = Query::new().material("KRPvKR").to_sql("id");
+        assert_eq!(statement, "SELECT id FROM pgn WHERE pgn.material = $1");
+
+        let (statement, _) = Query::new()
+            .date(Some("2024".to_string()), Some("2024.03".to_string()))
+            .to_sql("id");
+        assert_eq!(
+            statement,
+            "SELECT id FROM pgn WHERE translate(pgn.date, '?', '0') >= $1 \
+            AND translate(pgn.date, '?', '0') BETWEEN '0001' AND $2"
+        );
+        assert_eq!(pad_date("2024", '0'), "2024.00.00");
+        assert_eq!(pad_date("2024.03", '9'), "2024.03.99");
+        assert_eq!(pad_date("2024.03.15", '9'), "2024.03.15");
+
+        let (statement, _) = Query::new().elo(None, Some(1800)).to_sql("id");
+        assert_eq!(
+            statement,
+            "SELECT id FROM pgn WHERE LEAST(pgn.white_elo, pgn.black_elo) > 0 \
+            AND GREATEST(pgn.white_elo, pgn.black_elo) <= $1"
+        );
+
+        let (statement, _) = Query::new().sort_by(SortBy::Date, true).to_sql("id");
+        assert_eq!(
+            statement,
+            "SELECT id FROM pgn ORDER BY (btrim(date) = '' OR btrim(date) LIKE '?%'), \
+            translate(btrim(date), '?', '0') DESC, id"
+        );
+    }
+}
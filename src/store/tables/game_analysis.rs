@@ -0,0 +1,31 @@
+use super::Migration;
+
+pub fn get_migrations() -> Vec<Migration> {
+    vec![Migration {
+        test: |client| {
+            let statement = "
+                SELECT FROM pg_tables
+                WHERE schemaname = 'public' AND tablename  = 'game_analysis'";
+
+            client.query_opt(statement, &[]).map(|opt| opt.is_some())
+        },
+        apply: |client| {
+            client.batch_execute(
+                "CREATE TABLE game_analysis (
+                    pgn_id              VARCHAR(255)    PRIMARY KEY REFERENCES pgn (id) ON DELETE CASCADE,
+                    white_moves         INT             NOT NULL,
+                    white_acpl          FLOAT8,
+                    white_accuracy      FLOAT8,
+                    white_inaccuracies  INT             NOT NULL,
+                    white_mistakes      INT             NOT NULL,
+                    white_blunders      INT             NOT NULL,
+                    black_moves         INT             NOT NULL,
+                    black_acpl          FLOAT8,
+                    black_accuracy      FLOAT8,
+                    black_inaccuracies  INT             NOT NULL,
+                    black_mistakes      INT             NOT NULL,
+                    black_blunders      INT             NOT NULL);",
+            )
+        },
+    }]
+}
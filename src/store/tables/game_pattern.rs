@@ -0,0 +1,52 @@
+use super::{backfill, Migration};
+use crate::board::pattern::game_codes;
+use crate::board::Replay;
+
+pub fn get_migrations() -> Vec<Migration> {
+    vec![Migration {
+        test: |client| {
+            let statement = "
+                SELECT FROM pg_tables
+                WHERE schemaname = 'public' AND tablename  = 'game_pattern'";
+
+            client.query_opt(statement, &[]).map(|opt| opt.is_some())
+        },
+        apply: |client| {
+            client.batch_execute(
+                "CREATE TABLE game_pattern (
+                    pgn_id      VARCHAR(255)    PRIMARY KEY REFERENCES pgn (id) ON DELETE CASCADE,
+                    codes       INT[]           NOT NULL)",
+            )?;
+
+            backfill(client, |client, games| {
+                let mut ids: Vec<&str> = Vec::new();
+                let mut codes: Vec<String> = Vec::new();
+                for pgn in games.iter() {
+                    if let Ok(replay) = Replay::new(pgn) {
+                        let game_codes: Vec<String> =
+                            game_codes(&replay).iter().map(|c| c.to_string()).collect();
+                        ids.push(pgn.id.as_str());
+                        codes.push(format!("{{{}}}", game_codes.join(",")));
+                    }
+                }
+
+                // Codes are passed as array literals, as arrays of arrays
+                // must all have the same length.
+                client
+                    .execute(
+                        "INSERT INTO game_pattern (pgn_id, codes)
+                        SELECT u.id, u.codes::INT[]
+                        FROM unnest($1::VARCHAR[], $2::TEXT[]) AS u (id, codes)",
+                        &[&ids, &codes],
+                    )
+                    .map(|_| ())
+            })?;
+
+            client.execute(
+                "CREATE INDEX game_pattern_codes_idx ON game_pattern USING GIN (codes)",
+                &[],
+            )?;
+            Ok(())
+        },
+    }]
+}
@@ -1,8 +1,59 @@
-use postgres::Client;
+use postgres::{Client, Transaction};
 
+use super::postgres::{row_to_pgn, time_control_columns};
+use crate::pgn::{Parser, Pgn};
+
+/// A schema change, applied when `test` returns false. `apply` runs in a
+/// transaction, so a migration that fails partway is applied again in full
+/// the next time the store is opened.
 pub struct Migration {
     pub test: fn(&mut Client) -> Result<bool, postgres::error::Error>,
-    pub apply: fn(&mut Client) -> Result<(), postgres::error::Error>,
+    pub apply: fn(&mut Transaction) -> Result<(), postgres::error::Error>,
+}
+
+pub fn column_exists(
+    client: &mut Client,
+    table: &str,
+    column: &str,
+) -> Result<bool, postgres::error::Error> {
+    let statement = "
+        SELECT FROM information_schema.columns
+        WHERE table_schema = 'public' AND table_name = $1 AND column_name = $2";
+
+    client
+        .query_opt(statement, &[&table, &column])
+        .map(|opt| opt.is_some())
+}
+
+/// Calls `update` with the stored games a page at a time, in the order of
+/// their ids, to fill columns added after the games were stored.
+pub fn backfill<F>(client: &mut Transaction, mut update: F) -> Result<(), postgres::error::Error>
+where
+    F: FnMut(&mut Transaction, &[Pgn]) -> Result<(), postgres::error::Error>,
+{
+    const PAGE_SIZE: i64 = 1000;
+
+    let parser = Parser::default();
+    let mut last_id = String::new();
+    loop {
+        let rows = client.query(
+            "SELECT id, tags, moves FROM pgn WHERE id > $1 ORDER BY id LIMIT $2",
+            &[&last_id, &PAGE_SIZE],
+        )?;
+        let games: Vec<Pgn> = rows.iter().map(|row| row_to_pgn(&parser, row)).collect();
+
+        update(client, &games)?;
+
+        match games.last() {
+            Some(pgn) if games.len() as i64 == PAGE_SIZE => last_id = pgn.id.clone(),
+            _ => return Ok(()),
+        }
+    }
 }
 
+pub(crate) mod game_analysis;
+pub(crate) mod game_pattern;
+pub(crate) mod move_ngram;
 pub(crate) mod pgn;
+pub(crate) mod position;
+pub(crate) mod rating_history;
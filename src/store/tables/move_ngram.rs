@@ -0,0 +1,53 @@
+use super::{backfill, Migration};
+use crate::pgn::sequence::ngrams;
+
+pub fn get_migrations() -> Vec<Migration> {
+    vec![Migration {
+        test: |client| {
+            let statement = "
+                SELECT FROM pg_tables
+                WHERE schemaname = 'public' AND tablename  = 'move_ngram'";
+
+            client.query_opt(statement, &[]).map(|opt| opt.is_some())
+        },
+        apply: |client| {
+            client.batch_execute(
+                "CREATE TABLE move_ngram (
+                    key         BIGINT          NOT NULL,
+                    pgn_id      VARCHAR(255)    NOT NULL REFERENCES pgn (id) ON DELETE CASCADE,
+                    ply         INT             NOT NULL,
+                    n           SMALLINT        NOT NULL,
+                    PRIMARY KEY (pgn_id, ply, n))",
+            )?;
+
+            backfill(client, |client, games| {
+                let mut keys: Vec<i64> = Vec::new();
+                let mut ids: Vec<&str> = Vec::new();
+                let mut plies: Vec<i32> = Vec::new();
+                let mut lengths: Vec<i16> = Vec::new();
+                for pgn in games.iter() {
+                    for (key, ply, n) in ngrams(&pgn.moves) {
+                        keys.push(key);
+                        ids.push(pgn.id.as_str());
+                        plies.push(ply);
+                        lengths.push(n);
+                    }
+                }
+
+                client
+                    .execute(
+                        "INSERT INTO move_ngram (key, pgn_id, ply, n)
+                        SELECT * FROM unnest($1::BIGINT[], $2::VARCHAR[], $3::INT[], $4::SMALLINT[])",
+                        &[&keys, &ids, &plies, &lengths],
+                    )
+                    .map(|_| ())
+            })?;
+
+            client.execute(
+                "CREATE INDEX move_ngram_key_idx ON move_ngram (key, ply)",
+                &[],
+            )?;
+            Ok(())
+        },
+    }]
+}
@@ -1,16 +1,19 @@
-use super::Migration;
+use super::{backfill, column_exists, time_control_columns, Migration};
+use crate::board::material::Endgame;
+use crate::board::Replay;
 
 pub fn get_migrations() -> Vec<Migration> {
-    return vec![Migration {
-        test: |client| {
-            let statement = "
+    vec![
+        Migration {
+            test: |client| {
+                let statement = "
     				SELECT FROM pg_tables
     				WHERE schemaname = 'public' AND tablename  = 'pgn'";
 
-            client.query_opt(statement, &[]).map(|opt| opt.is_some())
-        },
-        apply: |client| {
-            let statement = "CREATE TABLE pgn (
+                client.query_opt(statement, &[]).map(|opt| opt.is_some())
+            },
+            apply: |client| {
+                let statement = "CREATE TABLE pgn (
 		                id          VARCHAR(255)    NOT NULL PRIMARY KEY,
 	                    event       TEXT            DEFAULT '',
 	                    site        TEXT            DEFAULT '',
@@ -32,7 +35,137 @@ pub fn get_migrations() -> Vec<Migration> {
 	                    result      VARCHAR(15)     DEFAULT '',
 	                    tags        TEXT            NOT NULL,
 	                    moves       TEXT            NOT NULL)";
-            client.execute(statement, &[]).map(|_| ())
+                client.execute(statement, &[]).map(|_| ())
+            },
+        },
+        Migration {
+            test: |client| column_exists(client, "pgn", "time_control_class"),
+            apply: |client| {
+                let statement = "ALTER TABLE pgn
+                        ADD COLUMN time_control_base      INT         DEFAULT 0,
+                        ADD COLUMN time_control_increment INT         DEFAULT 0,
+                        ADD COLUMN time_control_class     VARCHAR(15) DEFAULT ''";
+                client.execute(statement, &[])?;
+
+                backfill(client, |client, games| {
+                    let ids: Vec<&str> = games.iter().map(|pgn| pgn.id.as_str()).collect();
+                    let columns: Vec<(i32, i32, &str)> =
+                        games.iter().map(time_control_columns).collect();
+                    let bases: Vec<i32> = columns.iter().map(|c| c.0).collect();
+                    let increments: Vec<i32> = columns.iter().map(|c| c.1).collect();
+                    let classes: Vec<&str> = columns.iter().map(|c| c.2).collect();
+
+                    client
+                        .execute(
+                            "UPDATE pgn SET
+                                time_control_base = u.base,
+                                time_control_increment = u.increment,
+                                time_control_class = u.class
+                            FROM unnest($1::VARCHAR[], $2::INT[], $3::INT[], $4::VARCHAR[])
+                                AS u (id, base, increment, class)
+                            WHERE pgn.id = u.id",
+                            &[&ids, &bases, &increments, &classes],
+                        )
+                        .map(|_| ())
+                })
+            },
+        },
+        Migration {
+            test: |client| column_exists(client, "pgn", "moves_fingerprint"),
+            apply: |client| {
+                client.execute(
+                    "ALTER TABLE pgn ADD COLUMN moves_fingerprint BIGINT DEFAULT 0",
+                    &[],
+                )?;
+
+                backfill(client, |client, games| {
+                    let ids: Vec<&str> = games.iter().map(|pgn| pgn.id.as_str()).collect();
+                    let fingerprints: Vec<i64> = games
+                        .iter()
+                        .map(|pgn| pgn.moves_fingerprint as i64)
+                        .collect();
+
+                    client
+                        .execute(
+                            "UPDATE pgn SET moves_fingerprint = u.fingerprint
+                            FROM unnest($1::VARCHAR[], $2::BIGINT[]) AS u (id, fingerprint)
+                            WHERE pgn.id = u.id",
+                            &[&ids, &fingerprints],
+                        )
+                        .map(|_| ())
+                })?;
+
+                client.execute(
+                    "CREATE INDEX pgn_dedup_idx ON pgn (moves_fingerprint, white, black, date)",
+                    &[],
+                )?;
+                Ok(())
+            },
+        },
+        Migration {
+            test: |client| column_exists(client, "pgn", "ply_count"),
+            apply: |client| {
+                let statement = "ALTER TABLE pgn ADD COLUMN ply_count INT DEFAULT 0";
+                client.execute(statement, &[])?;
+
+                backfill(client, |client, games| {
+                    let ids: Vec<&str> = games.iter().map(|pgn| pgn.id.as_str()).collect();
+                    let ply_counts: Vec<i32> =
+                        games.iter().map(|pgn| pgn.moves.len() as i32).collect();
+
+                    client
+                        .execute(
+                            "UPDATE pgn SET ply_count = u.ply_count
+                            FROM unnest($1::VARCHAR[], $2::INT[]) AS u (id, ply_count)
+                            WHERE pgn.id = u.id",
+                            &[&ids, &ply_counts],
+                        )
+                        .map(|_| ())
+                })
+            },
+        },
+        Migration {
+            test: |client| column_exists(client, "pgn", "endgame"),
+            apply: |client| {
+                client.batch_execute(
+                    "ALTER TABLE pgn
+                        ADD COLUMN material VARCHAR(63) DEFAULT '',
+                        ADD COLUMN endgame  VARCHAR(15) DEFAULT ''",
+                )?;
+
+                backfill(client, |client, games| {
+                    let ids: Vec<&str> = games.iter().map(|pgn| pgn.id.as_str()).collect();
+                    let endgames: Vec<Option<Endgame>> = games
+                        .iter()
+                        .map(|pgn| Replay::new(pgn).ok().as_ref().and_then(Endgame::find))
+                        .collect();
+                    let materials: Vec<&str> = endgames
+                        .iter()
+                        .map(|e| e.as_ref().map_or("", |e| e.signature.as_str()))
+                        .collect();
+                    let categories: Vec<&str> = endgames
+                        .iter()
+                        .map(|e| e.as_ref().map_or("", |e| e.category.as_str()))
+                        .collect();
+
+                    client
+                        .execute(
+                            "UPDATE pgn SET material = u.material, endgame = u.endgame
+                            FROM unnest($1::VARCHAR[], $2::VARCHAR[], $3::VARCHAR[])
+                                AS u (id, material, endgame)
+                            WHERE pgn.id = u.id",
+                            &[&ids, &materials, &categories],
+                        )
+                        .map(|_| ())
+                })?;
+
+                // Indexes with the default operator class cannot serve `LIKE`
+                // unless the database uses the C collation.
+                client.batch_execute(
+                    "CREATE INDEX pgn_material_pattern_idx ON pgn (material varchar_pattern_ops);
+                    CREATE INDEX pgn_endgame_idx ON pgn (endgame);",
+                )
+            },
         },
-    }];
+    ]
 }
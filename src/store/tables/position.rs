@@ -0,0 +1,52 @@
+use super::{backfill, Migration};
+use crate::board::{polyglot_key, Replay};
+
+pub fn get_migrations() -> Vec<Migration> {
+    vec![Migration {
+        test: |client| {
+            let statement = "
+                SELECT FROM pg_tables
+                WHERE schemaname = 'public' AND tablename  = 'position'";
+
+            client.query_opt(statement, &[]).map(|opt| opt.is_some())
+        },
+        apply: |client| {
+            client.batch_execute(
+                "CREATE TABLE position (
+                    key         BIGINT          NOT NULL,
+                    pgn_id      VARCHAR(255)    NOT NULL REFERENCES pgn (id) ON DELETE CASCADE,
+                    ply         INT             NOT NULL,
+                    move        VARCHAR(15)     DEFAULT '',
+                    PRIMARY KEY (pgn_id, ply))",
+            )?;
+
+            backfill(client, |client, games| {
+                let mut keys: Vec<i64> = Vec::new();
+                let mut ids: Vec<&str> = Vec::new();
+                let mut plies: Vec<i32> = Vec::new();
+                let mut moves: Vec<&str> = Vec::new();
+                for pgn in games.iter() {
+                    if let Ok(replay) = Replay::new(pgn) {
+                        for (ply, pos) in replay.positions.iter().enumerate() {
+                            keys.push(polyglot_key(pos) as i64);
+                            ids.push(pgn.id.as_str());
+                            plies.push(ply as i32);
+                            moves.push(pgn.moves.get(ply).map_or("", |m| m.as_str()));
+                        }
+                    }
+                }
+
+                client
+                    .execute(
+                        "INSERT INTO position (key, pgn_id, ply, move)
+                        SELECT * FROM unnest($1::BIGINT[], $2::VARCHAR[], $3::INT[], $4::VARCHAR[])",
+                        &[&keys, &ids, &plies, &moves],
+                    )
+                    .map(|_| ())
+            })?;
+
+            client.execute("CREATE INDEX position_key_idx ON position (key)", &[])?;
+            Ok(())
+        },
+    }]
+}
@@ -0,0 +1,26 @@
+use super::Migration;
+
+pub fn get_migrations() -> Vec<Migration> {
+    vec![Migration {
+        test: |client| {
+            let statement = "
+                SELECT FROM pg_tables
+                WHERE schemaname = 'public' AND tablename  = 'rating_history'";
+
+            client.query_opt(statement, &[]).map(|opt| opt.is_some())
+        },
+        apply: |client| {
+            client.batch_execute(
+                "CREATE TABLE rating_history (
+                    pool        VARCHAR(63)     NOT NULL,
+                    player      VARCHAR(255)    NOT NULL,
+                    period      VARCHAR(31)     NOT NULL,
+                    games       INT             NOT NULL,
+                    rating      FLOAT8          NOT NULL,
+                    deviation   FLOAT8,
+                    volatility  FLOAT8,
+                    PRIMARY KEY (pool, player, period));",
+            )
+        },
+    }]
+}
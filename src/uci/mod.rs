@@ -0,0 +1,371 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+use shakmaty::{Chess, Color, Position};
+
+use crate::board::{fen, Replay};
+use crate::pgn::annotation::Eval;
+use crate::pgn::Pgn;
+
+/// How long the engine searches a position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    Depth(u32),
+    Nodes(u64),
+    /// Milliseconds.
+    MoveTime(u64),
+}
+
+impl Limit {
+    fn command(&self) -> String {
+        match self {
+            Limit::Depth(depth) => format!("go depth {}", depth),
+            Limit::Nodes(nodes) => format!("go nodes {}", nodes),
+            Limit::MoveTime(ms) => format!("go movetime {}", ms),
+        }
+    }
+}
+
+/// An `info` line sent by the engine during a search. The score is from the
+/// point of view of the side to move, as in the protocol.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Info {
+    pub depth: Option<u32>,
+    pub seldepth: Option<u32>,
+    pub multipv: Option<u32>,
+    pub score: Option<Eval>,
+    pub nodes: Option<u64>,
+    pub nps: Option<u64>,
+    /// Milliseconds.
+    pub time: Option<u64>,
+    pub pv: Vec<String>,
+}
+
+/// Parses an `info` line. `None` for other lines and `info string`.
+/// Lower and upper bound scores are skipped.
+pub fn parse_info(line: &str) -> Option<Info> {
+    let mut words = line.split_whitespace();
+    if words.next() != Some("info") {
+        return None;
+    }
+
+    let mut info = Info::default();
+    while let Some(word) = words.next() {
+        match word {
+            "string" => return None,
+            "depth" => info.depth = words.next().and_then(|v| v.parse().ok()),
+            "seldepth" => info.seldepth = words.next().and_then(|v| v.parse().ok()),
+            "multipv" => info.multipv = words.next().and_then(|v| v.parse().ok()),
+            "nodes" => info.nodes = words.next().and_then(|v| v.parse().ok()),
+            "nps" => info.nps = words.next().and_then(|v| v.parse().ok()),
+            "time" => info.time = words.next().and_then(|v| v.parse().ok()),
+            "score" => {
+                let kind = words.next();
+                let value = words.next().and_then(|v| v.parse::<i32>().ok());
+                info.score = match (kind, value) {
+                    (Some("cp"), Some(cp)) => Some(Eval::Cp(cp)),
+                    (Some("mate"), Some(n)) => Some(Eval::Mate(n)),
+                    _ => None,
+                };
+            }
+            "lowerbound" | "upperbound" => info.score = None,
+            "pv" => {
+                info.pv = words.by_ref().map(|m| m.to_string()).collect();
+            }
+            _ => {}
+        }
+    }
+
+    Some(info)
+}
+
+/// Outcome of a search: the best move in UCI notation and the last `info`
+/// with a score, for the first line when the engine sends several.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchResult {
+    pub best_move: Option<String>,
+    pub ponder: Option<String>,
+    pub info: Option<Info>,
+    /// Last `info` with a score of each line, best first, when the `MultiPV`
+    /// option is set.
+    pub lines: Vec<Info>,
+}
+
+/// A UCI engine running as a child process.
+pub struct Engine {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    /// From `id name`.
+    pub name: Option<String>,
+    /// Names of the options the engine declares.
+    pub options: Vec<String>,
+    /// Value of each option, as declared by the engine or last set.
+    values: HashMap<String, String>,
+}
+
+impl Engine {
+    /// Starts `program` and waits for `uciok` and `readyok`.
+    pub fn launch(program: &str, args: &[String]) -> io::Result<Self> {
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let stdin = child.stdin.take().unwrap();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+
+        let mut engine = Self {
+            child,
+            stdin,
+            stdout,
+            name: None,
+            options: Vec::new(),
+            values: HashMap::new(),
+        };
+
+        engine.send("uci")?;
+        loop {
+            let line = engine.read_line()?;
+            if line == "uciok" {
+                break;
+            } else if let Some(name) = line.strip_prefix("id name ") {
+                engine.name = Some(name.trim().to_string());
+            } else if let Some(option) = line.strip_prefix("option name ") {
+                let (name, rest) = option.split_once(" type ").unwrap_or((option, ""));
+                let name = name.trim().to_string();
+                if let Some((_, default)) = rest.split_once(" default ") {
+                    let default = [" min ", " max ", " var "]
+                        .iter()
+                        .filter_map(|keyword| default.find(keyword))
+                        .min()
+                        .map_or(default, |end| &default[..end]);
+                    engine
+                        .values
+                        .insert(name.clone(), default.trim().to_string());
+                }
+                engine.options.push(name);
+            }
+        }
+        engine.is_ready()?;
+
+        Ok(engine)
+    }
+
+    pub fn set_option(&mut self, name: &str, value: &str) -> io::Result<()> {
+        self.send(format!("setoption name {} value {}", name, value).as_str())?;
+        self.values.insert(name.to_string(), value.to_string());
+        self.is_ready()
+    }
+
+    /// Value of an option: the last one set, or the default the engine
+    /// declared.
+    pub fn option(&self, name: &str) -> Option<&str> {
+        self.values.get(name).map(|v| v.as_str())
+    }
+
+    /// Tells the engine the next positions come from another game.
+    pub fn new_game(&mut self) -> io::Result<()> {
+        self.send("ucinewgame")?;
+        self.is_ready()
+    }
+
+    /// Searches the position after `moves`, in UCI notation, from `start`
+    /// or the standard starting position.
+    pub fn go(
+        &mut self,
+        start: Option<&Chess>,
+        moves: &[String],
+        limit: Limit,
+    ) -> io::Result<SearchResult> {
+        let mut position = match start {
+            Some(pos) => format!("position fen {}", fen(pos)),
+            None => "position startpos".to_string(),
+        };
+        if !moves.is_empty() {
+            position.push_str(" moves ");
+            position.push_str(moves.join(" ").as_str());
+        }
+        self.send(position.as_str())?;
+        self.send(limit.command().as_str())?;
+
+        let mut lines: Vec<Info> = Vec::new();
+        loop {
+            let line = self.read_line()?;
+            if let Some(rest) = line.strip_prefix("bestmove") {
+                let mut words = rest.split_whitespace();
+                let best_move = words.next().filter(|m| *m != "(none)");
+                let ponder = match words.next() {
+                    Some("ponder") => words.next(),
+                    _ => None,
+                };
+                return Ok(SearchResult {
+                    best_move: best_move.map(|m| m.to_string()),
+                    ponder: ponder.map(|m| m.to_string()),
+                    info: lines.first().cloned(),
+                    lines,
+                });
+            }
+            if let Some(parsed) = parse_info(line.as_str()) {
+                if parsed.score.is_some() {
+                    let index = parsed.multipv.unwrap_or(1).max(1) as usize - 1;
+                    if index < lines.len() {
+                        lines[index] = parsed;
+                    } else if index == lines.len() {
+                        lines.push(parsed);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Evaluation after each ply of `pgn`, from the point of view of White.
+    /// `None` when the game is over at that ply or the engine gives no score.
+    pub fn analyze(&mut self, pgn: &Pgn, limit: Limit) -> io::Result<Vec<Option<Eval>>> {
+        let replay = Replay::new(pgn).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        // The starting position is only sent as FEN when the game has one.
+        let start = pgn
+            .tags
+            .contains_key("FEN")
+            .then(|| replay.positions[0].clone());
+        let uci_moves = replay.uci_moves();
+
+        self.new_game()?;
+        let mut evals: Vec<Option<Eval>> = Vec::new();
+        for (ply, pos) in replay.positions.iter().enumerate().skip(1) {
+            if pos.is_game_over() {
+                evals.push(None);
+                continue;
+            }
+            let result = self.go(start.as_ref(), &uci_moves[..ply], limit)?;
+            let score = result.info.and_then(|info| info.score);
+            evals.push(score.map(|score| white_pov(score, pos.turn())));
+        }
+
+        Ok(evals)
+    }
+
+    /// Sends `quit` and waits for the engine to exit.
+    pub fn quit(mut self) -> io::Result<()> {
+        self.send("quit")?;
+        self.child.wait()?;
+        Ok(())
+    }
+
+    fn is_ready(&mut self) -> io::Result<()> {
+        self.send("isready")?;
+        while self.read_line()? != "readyok" {}
+        Ok(())
+    }
+
+    fn send(&mut self, command: &str) -> io::Result<()> {
+        writeln!(self.stdin, "{}", command)?;
+        self.stdin.flush()
+    }
+
+    fn read_line(&mut self) -> io::Result<String> {
+        let mut line = String::new();
+        if self.stdout.read_line(&mut line)? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "engine exited",
+            ));
+        }
+        Ok(line.trim().to_string())
+    }
+}
+
+impl Drop for Engine {
+    fn drop(&mut self) {
+        if let Ok(None) = self.child.try_wait() {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+    }
+}
+
+fn white_pov(score: Eval, turn: Color) -> Eval {
+    match (score, turn) {
+        (Eval::Cp(cp), Color::Black) => Eval::Cp(-cp),
+        (Eval::Mate(n), Color::Black) => Eval::Mate(-n),
+        (score, Color::White) => score,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn info_lines() {
+        let info =
+            parse_info("info depth 12 seldepth 18 multipv 1 score cp -35 nodes 1000 nps 50000 time 20 pv e7e5 g1f3")
+                .unwrap();
+        assert_eq!(info.depth, Some(12));
+        assert_eq!(info.seldepth, Some(18));
+        assert_eq!(info.score, Some(Eval::Cp(-35)));
+        assert_eq!(info.nodes, Some(1000));
+        assert_eq!(info.time, Some(20));
+        assert_eq!(info.pv, vec!["e7e5", "g1f3"]);
+
+        assert_eq!(
+            parse_info("info depth 3 score mate -2").unwrap().score,
+            Some(Eval::Mate(-2))
+        );
+        assert_eq!(parse_info("info string hello depth 3"), None);
+        assert_eq!(parse_info("bestmove e2e4"), None);
+    }
+
+    /// Answers every search with 25 centipawns for the side to move.
+    #[cfg(unix)]
+    const STUB_ENGINE: &str = r#"
+while read -r line; do
+    case "$line" in
+        uci) echo "id name Stub"; echo "option name Hash type spin default 16"; echo "uciok" ;;
+        isready) echo "readyok" ;;
+        go*) echo "info string thinking"; echo "info depth 1 score cp 25 pv e2e4"; echo "bestmove e2e4 ponder e7e5" ;;
+        quit) exit 0 ;;
+    esac
+done
+"#;
+
+    #[cfg(unix)]
+    #[test]
+    fn stub_engine() {
+        let path = std::env::temp_dir().join(format!("mudfish-stub-{}.sh", std::process::id()));
+        std::fs::write(&path, STUB_ENGINE).unwrap();
+
+        let mut engine = Engine::launch("sh", &[path.to_string_lossy().to_string()]).unwrap();
+        assert_eq!(engine.name.as_deref(), Some("Stub"));
+        assert_eq!(engine.options, vec!["Hash"]);
+        assert_eq!(engine.option("Hash"), Some("16"));
+        engine.set_option("Hash", "32").unwrap();
+        assert_eq!(engine.option("Hash"), Some("32"));
+
+        let result = engine.go(None, &[], Limit::Depth(1)).unwrap();
+        assert_eq!(result.best_move.as_deref(), Some("e2e4"));
+        assert_eq!(result.ponder.as_deref(), Some("e7e5"));
+        assert_eq!(result.info.unwrap().score, Some(Eval::Cp(25)));
+        assert_eq!(result.lines.len(), 1);
+
+        let mut pgn = Pgn::new("game", 1);
+        pgn.moves = ["f3", "e5", "g4", "Qh4#"]
+            .iter()
+            .map(|m| m.to_string())
+            .collect();
+        assert_eq!(
+            engine.analyze(&pgn, Limit::Nodes(100)).unwrap(),
+            vec![
+                Some(Eval::Cp(-25)),
+                Some(Eval::Cp(25)),
+                Some(Eval::Cp(-25)),
+                None
+            ]
+        );
+
+        engine.quit().unwrap();
+        std::fs::remove_file(path).unwrap();
+    }
+}